@@ -0,0 +1,154 @@
+//! Derive macros for `async-actor`, re-exported from it behind the `derive` feature.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Ident, Path, Type};
+
+/// Implements `Actor` for the actor named in `#[actor(...)]`, with an `on_msg` that dispatches
+/// each variant of the annotated message enum to the handler method named in its
+/// `#[handler(...)]` attribute.
+///
+/// Handlers are async methods on the actor that take the actor's `WeakActorRef` followed by the
+/// variant's fields in declaration order, and return `Result<(), Error>`:
+///
+/// ```ignore
+/// #[derive(Actor)]
+/// #[actor(PingActor, error = ())]
+/// enum PingMessage {
+///     #[handler(handle_ping)]
+///     Ping(usize),
+///     #[handler(handle_stop)]
+///     Stop,
+/// }
+///
+/// impl PingActor {
+///     async fn handle_ping(&mut self, this: &WeakActorRef<Self>, num: usize) -> Result<(), ()> {
+///         // ...
+///     }
+/// }
+/// ```
+///
+/// The error type defaults to `()`. Every variant must name a handler, so adding a variant
+/// without one is a compile error, as is a handler with the wrong arguments.
+///
+/// The match is also generated as an inherent `dispatch(self, actor, this)` method on the enum.
+/// With `#[actor(PingActor, dispatch_only)]`, only that method is generated, so that an actor
+/// which implements other hooks of `Actor` itself can call it from its own `on_msg`.
+#[proc_macro_derive(Actor, attributes(actor, handler))]
+pub fn derive_actor(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let Data::Enum(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "`#[derive(Actor)]` must be used on the actor's message enum",
+        ));
+    };
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "`#[derive(Actor)]` does not support generic message enums",
+        ));
+    }
+
+    let mut actor: Option<Path> = None;
+    let mut error: Option<Type> = None;
+    let mut dispatch_only = false;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("actor")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("error") {
+                error = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("dispatch_only") {
+                dispatch_only = true;
+            } else if actor.is_none() {
+                actor = Some(meta.path);
+            } else {
+                return Err(meta.error("expected `error = ...` or `dispatch_only`"));
+            }
+            Ok(())
+        })?;
+    }
+    let Some(actor) = actor else {
+        return Err(syn::Error::new(
+            Span::call_site(),
+            "missing `#[actor(ActorType)]` naming the actor that handles this enum",
+        ));
+    };
+    let error = error.unwrap_or_else(|| syn::parse_quote!(()));
+
+    let mut arms = Vec::new();
+    for variant in &data.variants {
+        let mut handler: Option<Ident> = None;
+        for attr in variant
+            .attrs
+            .iter()
+            .filter(|a| a.path().is_ident("handler"))
+        {
+            handler = Some(attr.parse_args()?);
+        }
+        let Some(handler) = handler else {
+            return Err(syn::Error::new_spanned(
+                &variant.ident,
+                "missing `#[handler(method)]` naming the method that handles this variant",
+            ));
+        };
+
+        let name = &variant.ident;
+        let bindings: Vec<Ident> = (0..variant.fields.len())
+            .map(|i| format_ident!("__field{}", i))
+            .collect();
+        let pattern = match &variant.fields {
+            Fields::Unit => quote!(Self::#name),
+            Fields::Unnamed(_) => quote!(Self::#name(#(#bindings),*)),
+            Fields::Named(fields) => {
+                let names = fields.named.iter().map(|f| &f.ident);
+                quote!(Self::#name { #(#names: #bindings),* })
+            }
+        };
+        arms.push(quote! {
+            #pattern => actor.#handler(this, #(#bindings),*).await,
+        });
+    }
+
+    let message = &input.ident;
+    let vis = &input.vis;
+    let actor_impl = (!dispatch_only).then(|| {
+        quote! {
+            impl ::async_actor::Actor for #actor {
+                type Message = #message;
+                type Error = #error;
+
+                async fn on_msg(
+                    &mut self,
+                    this: &::async_actor::WeakActorRef<Self>,
+                    msg: Self::Message,
+                ) -> ::core::result::Result<(), Self::Error> {
+                    msg.dispatch(self, this).await
+                }
+            }
+        }
+    });
+
+    Ok(quote! {
+        impl #message {
+            /// Calls the handler method of `actor` for this message.
+            #vis async fn dispatch(
+                self,
+                actor: &mut #actor,
+                this: &::async_actor::WeakActorRef<#actor>,
+            ) -> ::core::result::Result<(), #error> {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+
+        #actor_impl
+    })
+}
@@ -0,0 +1,58 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use event_listener::Event;
+
+#[derive(Debug, Default)]
+struct AbortState {
+    aborted: AtomicBool,
+    event: Event,
+}
+
+#[derive(Debug, Clone, Default)]
+/// A handle to cooperatively abort a running actor, obtained from
+/// [`ActorRun::abort_handle`](crate::ActorRun::abort_handle).
+///
+/// Aborting interrupts the actor at the next point where its run loop or handler awaits: the
+/// handler that is running is cancelled, queued messages are dropped, and [`Actor::on_stop`]
+/// is called with [`None`] before the [`ActorRun`](crate::ActorRun) resolves. This is not a
+/// forced kill: a handler that is stuck in a loop that never yields cannot be aborted.
+///
+/// The abort is received by the run loop like a stop, so `on_stop` runs exactly once, and an
+/// abort that arrives while it runs does not interrupt it. Custom [`Actor::run_with`] loops
+/// receive the abort as [`MailboxEvent::Stop`] with [`None`] from [`Mailbox::recv`].
+///
+/// [`Actor::on_stop`]: crate::Actor::on_stop
+/// [`Actor::run_with`]: crate::Actor::run_with
+/// [`MailboxEvent::Stop`]: crate::MailboxEvent::Stop
+/// [`Mailbox::recv`]: crate::Mailbox::recv
+pub struct AbortHandle(Arc<AbortState>);
+
+impl AbortHandle {
+    /// Aborts the actor. Calling this more than once has no further effect.
+    pub fn abort(&self) {
+        self.0.aborted.store(true, Ordering::Release);
+        self.0.event.notify(usize::MAX);
+    }
+
+    /// Returns `true` if [`AbortHandle::abort`] has been called.
+    pub fn is_aborted(&self) -> bool {
+        self.0.aborted.load(Ordering::Acquire)
+    }
+
+    /// Resolves once [`AbortHandle::abort`] has been called.
+    pub(crate) async fn aborted(&self) {
+        loop {
+            if self.is_aborted() {
+                return;
+            }
+            let listener = self.0.event.listen();
+            if self.is_aborted() {
+                return;
+            }
+            listener.await;
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use async_oneshot_channel::{Receiver as OneshotReceiver, Sender as OneshotSender};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Returned by [`ActorRef::send_ack`](crate::ActorRef::send_ack) when a message was not
+/// confirmed as processed. This happens if the message could not be enqueued, if the actor's
+/// handler returned an error for it, or if the actor stopped before getting to it.
+pub struct Unacked;
+
+#[derive(Debug)]
+/// Acknowledgement token attached to a message, fired by the run loop once the message has
+/// been handled successfully. Dropping the token without firing it resolves the waiting
+/// sender with [`Unacked`].
+pub(crate) enum Ack {
+    Single(OneshotSender<()>),
+    /// One of the messages sent with `ActorRef::send_batch_ack`, sharing the batch's state.
+    Batch(Arc<Batch>),
+}
+
+#[derive(Debug)]
+/// Completion state shared by the messages of a batch. `done` is only sent on once every
+/// message has been handled, so if any token is dropped unfired, the sender is dropped along
+/// with the last token instead, and the waiting producer reads `processed`.
+pub(crate) struct Batch {
+    remaining: AtomicUsize,
+    processed: Arc<AtomicUsize>,
+    done: OneshotSender<()>,
+}
+
+impl Ack {
+    pub(crate) fn new() -> (Self, OneshotReceiver<()>) {
+        let (sender, receiver) = async_oneshot_channel::oneshot();
+        (Self::Single(sender), receiver)
+    }
+
+    /// Creates the shared state for a batch of `len` messages, along with the receiver that
+    /// resolves once all of them are handled, and the count of messages handled so far.
+    pub(crate) fn batch(len: usize) -> (Arc<Batch>, OneshotReceiver<()>, Arc<AtomicUsize>) {
+        let (done, receiver) = async_oneshot_channel::oneshot();
+        let processed = Arc::new(AtomicUsize::new(0));
+        let batch = Arc::new(Batch {
+            remaining: AtomicUsize::new(len),
+            processed: processed.clone(),
+            done,
+        });
+        (batch, receiver, processed)
+    }
+
+    pub(crate) fn fire(self) {
+        match self {
+            Self::Single(sender) => {
+                let _ = sender.send(());
+            }
+            Self::Batch(batch) => {
+                batch.processed.fetch_add(1, Ordering::AcqRel);
+                if batch.remaining.fetch_sub(1, Ordering::AcqRel) == 1 {
+                    let _ = batch.done.send(());
+                }
+            }
+        }
+    }
+}
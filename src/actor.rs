@@ -1,7 +1,14 @@
-use either::Either;
-use std::future::Future;
+use std::{any::Any, future::Future, time::Duration};
 
-use crate::{ActorRef, ActorRun, Mailbox, WeakActorRef};
+use crate::{
+    control::Control,
+    dispatch::dispatch,
+    envelope::{HandlerCtx, Meta},
+    mailbox::Event,
+    supervise::{finish, handle, StopReason},
+    ActorRef, ActorRun, Deadline, DrainErrorPolicy, ErrorAction, Mailbox, Supervision,
+    WeakActorRef,
+};
 
 pub trait Actor: Send + Sized + 'static {
     type Error: Send;
@@ -30,12 +37,147 @@ pub trait Actor: Send + Sized + 'static {
     /// messages to itself, or to stop itself. As this is a weak reference, the
     /// actor may have already been dropped by external code, so it may not be
     /// possible to use the weak reference.
+    ///
+    /// Handlers run one at a time: the default run loop awaits each call to completion before
+    /// it receives the next message, so a handler has exclusive access to the actor's state for
+    /// as long as it runs, including across await points, and messages are handled in the order
+    /// the mailbox delivers them. Work that should overlap with later messages has to be moved
+    /// out of the handler, e.g. with [`WeakActorRef::detach`]. See [`ActorRef::concurrency`].
     fn on_msg(
         &mut self,
         this: &WeakActorRef<Self>,
         msg: Self::Message,
     ) -> impl Future<Output = Result<(), Self::Error>> + Send;
 
+    /// Like [`Actor::on_msg`], but also returns follow-up messages for the actor itself. The
+    /// default run loop handles the follow-ups right after the current message, in order, ahead
+    /// of the messages still queued in the mailbox.
+    ///
+    /// Sending to itself with `this.send(..)` from within a handler waits for space in the
+    /// mailbox, which never frees up while the handler is waiting if the mailbox is bounded and
+    /// full; debug builds fail such sends instead, see [`WeakActorRef::send`]. Follow-ups do not
+    /// go through the mailbox's bound, so they cannot deadlock.
+    ///
+    /// The default implementation calls [`Actor::on_msg`] and returns no follow-ups. The run
+    /// loop only calls this method, so when overriding it, [`Actor::on_msg`] is no longer called
+    /// by the run loop.
+    fn on_msg_emit(
+        &mut self,
+        this: &WeakActorRef<Self>,
+        msg: Self::Message,
+    ) -> impl Future<Output = Result<Vec<Self::Message>, Self::Error>> + Send {
+        async move {
+            self.on_msg(this, msg).await?;
+            Ok(Vec::new())
+        }
+    }
+
+    #[allow(unused_variables)]
+    /// Like [`Actor::on_msg_emit`], but also receives the message's [`Meta`]data if it was sent
+    /// with [`ActorRef::send_enveloped`], or [`None`] otherwise. This is the hook for middleware
+    /// that needs to know e.g. when a message was sent or which request it belongs to.
+    ///
+    /// The default implementation ignores the metadata and calls [`Actor::on_msg_emit`]. The
+    /// run loop calls this method through [`Actor::on_msg_ctx`].
+    fn on_msg_meta(
+        &mut self,
+        this: &WeakActorRef<Self>,
+        msg: Self::Message,
+        meta: Option<Meta>,
+    ) -> impl Future<Output = Result<Vec<Self::Message>, Self::Error>> + Send {
+        self.on_msg_emit(this, msg)
+    }
+
+    /// Like [`Actor::on_msg_meta`], but receives a [`HandlerCtx`] with the message's metadata
+    /// and the mailbox's backlog, so that handlers can adapt to the load the actor is under.
+    ///
+    /// The default implementation calls [`Actor::on_msg_meta`]. As with `on_msg_emit`, the run
+    /// loop only calls this method.
+    fn on_msg_ctx(
+        &mut self,
+        this: &WeakActorRef<Self>,
+        msg: Self::Message,
+        ctx: HandlerCtx,
+    ) -> impl Future<Output = Result<Vec<Self::Message>, Self::Error>> + Send {
+        self.on_msg_meta(this, msg, ctx.meta)
+    }
+
+    #[allow(unused_variables)]
+    /// Called when [`Actor::on_msg`] returns an error, before the error is handled any further.
+    /// The returned [`ErrorAction`] decides whether the error fails the actor, which is the
+    /// default, or whether the actor is rolled back to its state before the message, see
+    /// [`Mailbox::set_transactional`]. This is also the place to log such errors.
+    fn on_error(&mut self, err: &Self::Error) -> ErrorAction {
+        ErrorAction::Fail
+    }
+
+    #[allow(unused_variables)]
+    /// Called when a handler returns an error while the actor drains its mailbox after
+    /// receiving a stop message. The returned [`DrainErrorPolicy`] decides whether draining
+    /// continues and [`Actor::on_stop`] still runs, which is the default, so that cleanup is
+    /// not skipped because one queued message failed during shutdown. This is also the place
+    /// to log such errors.
+    fn on_drain_error(&mut self, err: &Self::Error) -> DrainErrorPolicy {
+        DrainErrorPolicy::ContinueThenFail
+    }
+
+    #[allow(unused_variables)]
+    /// Called by the default run loop with every message before it is handled. Returning
+    /// `true` stashes the message: instead of being handled, it is set aside with the messages
+    /// stashed before it, until a handler calls [`WeakActorRef::unstash`]. This lets a state
+    /// machine defer the messages it can't handle in its current state, without losing them or
+    /// their order.
+    ///
+    /// Unstashed messages pass through this method again, so they can be stashed once more.
+    /// See [`UnstashMode`](crate::UnstashMode) for how they are ordered against new messages.
+    /// Messages that are still stashed when the actor stops are dropped without being handled;
+    /// senders waiting on an acknowledgement get [`Unacked`](crate::Unacked).
+    fn should_stash(&self, msg: &Self::Message) -> bool {
+        false
+    }
+
+    #[allow(unused_variables)]
+    /// Called with every message rejected because the actor is shedding load, see
+    /// [`Mailbox::set_shed_above`]. This runs on the sending task, before the message is
+    /// returned to the sender, so it is useful for counting or logging shed messages in one
+    /// place regardless of which producer sent them.
+    fn on_shed(msg: &Self::Message) {}
+
+    #[allow(unused_variables)]
+    /// Called by the run loop instead of the handler with every message sent with
+    /// [`ActorRef::send_ttl`] that had been queued for longer than its TTL by the time it was
+    /// received. Such messages count as handled for [`ActorRef::flush`], but are not
+    /// acknowledged. The default implementation drops the message.
+    fn on_expired(&mut self, msg: Self::Message) {}
+
+    #[allow(unused_variables)]
+    /// Called by the run loop instead of the handler with every message sent with
+    /// [`ActorRef::send_by`] whose deadline had passed by the time it was received, as handling
+    /// it could no longer produce a result in time. As with [`Actor::on_expired`], such messages
+    /// count as handled for [`ActorRef::flush`], but are not acknowledged. The default
+    /// implementation drops the message.
+    fn on_deadline_exceeded(&mut self, msg: Self::Message, deadline: Deadline) {}
+
+    /// Serializes the actor's state for [`Mailbox::set_snapshot_sink`], or returns [`None`] to
+    /// skip this snapshot. The default implementation never takes snapshots.
+    fn persist(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    #[allow(unused_variables)]
+    /// Rebuilds the actor from a snapshot taken by [`Actor::persist`], e.g. when a supervisor
+    /// restarts it after a crash, or returns [`None`] if the snapshot can't be used. The default
+    /// implementation always returns [`None`].
+    fn restore(snapshot: &[u8]) -> Option<Self> {
+        None
+    }
+
+    #[allow(unused_variables)]
+    /// Called when the [`SnapshotSink`](crate::SnapshotSink) fails to write a snapshot taken by
+    /// [`Actor::persist`]. The actor keeps running, and the next snapshot is attempted as
+    /// usual. The default implementation ignores the error.
+    fn on_snapshot_error(&mut self, err: std::io::Error) {}
+
     #[allow(unused_variables)]
     /// Called when the actor is stopped. This is the place to perform any
     /// cleanup logic, such as closing connections, etc. This method is called
@@ -51,6 +193,46 @@ pub trait Actor: Send + Sized + 'static {
         async { Ok(()) }
     }
 
+    #[allow(unused_variables)]
+    /// Called when [`Actor::on_msg`] panics, with the panic's payload. The returned
+    /// [`Supervision`] decides whether the panic takes down the actor (the default), or whether
+    /// the actor is restarted in place.
+    ///
+    /// On restart, the message that caused the panic is passed to [`Actor::on_poison`] so that
+    /// it is not reprocessed, [`Actor::on_start`] is called again, and the actor continues with
+    /// the messages still queued in its mailbox. The actor's state is kept as it was when the
+    /// handler panicked, so `on_start` should reset anything that may have been left
+    /// inconsistent.
+    fn on_panic(&mut self, panic: &(dyn Any + Send)) -> Supervision {
+        Supervision::Stop
+    }
+
+    #[allow(unused_variables)]
+    /// Called with the message whose handler panicked, before the actor is restarted, see
+    /// [`Actor::on_panic`]. This is the place to log or dead-letter the poison message.
+    ///
+    /// `msg` is [`None`] if the message could not be recovered, which is currently always the
+    /// case as the message is moved into [`Actor::on_msg`].
+    fn on_poison(
+        &mut self,
+        msg: Option<Self::Message>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        async { Ok(()) }
+    }
+
+    #[allow(unused_variables)]
+    /// Called when [`Actor::on_msg`] did not complete within the time limit set with
+    /// [`Mailbox::set_handler_timeout`]. By then the handler has been cancelled at the
+    /// await point it was suspended at, and its message dropped, so the actor's state may have
+    /// been left partially updated; this is the place to repair it. Returning an error stops the
+    /// actor, returning `Ok` continues with the next message.
+    fn on_msg_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        async { Ok(()) }
+    }
+
     /// Runs the actor with the given mailbox. Unless you have a specific reason to,
     /// the default implementation of this method should be used. You should not need to
     /// call this method directly either, see [`Actor::into_future`] instead.
@@ -60,27 +242,84 @@ pub trait Actor: Send + Sized + 'static {
     ) -> impl Future<Output = Result<(), Self::Error>> + Send {
         async move {
             let this = mailbox.this.clone();
+            let mut dispatcher = mailbox.dispatcher.lock().unwrap().take();
             self.on_start(&this).await?;
+            mailbox.set_started();
 
             loop {
-                match mailbox.recv().await {
-                    Either::Left(stop) => {
-                        mailbox.receiver.close();
-                        // Consume all remaining messages in the mailbox
-                        while let Ok(msg) = mailbox.receiver.recv().await {
-                            self.on_msg(&this, msg).await?;
+                match mailbox
+                    .next_event(dispatch(&mut dispatcher, &mailbox))
+                    .await
+                {
+                    Event::Control(Control::Flush(ack)) => mailbox.add_barrier(ack),
+                    Event::Control(Control::Escalate(stop, more_severe)) => {
+                        mailbox.escalate(stop, more_severe)
+                    }
+                    Event::Control(Control::Watch(watcher, ack)) => {
+                        mailbox.add_watcher(self, watcher, ack)
+                    }
+                    Event::External => {
+                        if let Some(trigger) = &mailbox.trigger {
+                            trigger.handle(self, &this);
+                            mailbox.check_watchers(self);
                         }
-                        self.on_stop(stop).await?;
+                    }
+                    Event::Express(msg) => handle(self, &mailbox, msg).await?,
+                    Event::Control(Control::Express(_)) => {
+                        unreachable!("express messages arrive as `Event::Express`")
+                    }
+                    Event::Control(Control::TakeBacklog(reply)) => {
+                        let _ = reply.send(mailbox.take_backlog());
+                        finish(self, &mailbox, StopReason::Stop(None)).await?;
                         break Ok(());
                     }
-                    Either::Right(msg) => {
+                    Event::Control(Control::Replace(new)) => {
+                        self.on_stop(None).await?;
+                        *self = new;
+                        self.on_start(&this).await?;
+                        mailbox.check_watchers(self);
+                    }
+                    Event::Stop(stop) => {
+                        // Senders waiting for space fail once the channel is closed, so the
+                        // drain only handles messages that were queued before the stop.
+                        mailbox.close();
+                        // Consume all remaining messages in the mailbox
+                        let mut failed = None;
+                        while !this.shared.is_drain_skipped() && !this.shared.abort.is_aborted() {
+                            // Express messages are still handled ahead of the queued ones.
+                            let msg = match mailbox.try_recv_express() {
+                                Some(msg) => msg,
+                                None => match dispatch(&mut dispatcher, &mailbox).await {
+                                    Some(msg) => msg,
+                                    None => break,
+                                },
+                            };
+                            let Err(err) = handle(self, &mailbox, msg).await else {
+                                continue;
+                            };
+                            match self.on_drain_error(&err) {
+                                DrainErrorPolicy::Abort => return Err(err),
+                                DrainErrorPolicy::Continue => {}
+                                DrainErrorPolicy::ContinueThenFail => {
+                                    failed.get_or_insert(err);
+                                }
+                            }
+                        }
+                        finish(self, &mailbox, StopReason::Stop(stop)).await?;
+                        break failed.map_or(Ok(()), Err);
+                    }
+                    Event::Message(msg) => {
                         if let Some(msg) = msg {
-                            self.on_msg(&this, msg).await?;
+                            handle(self, &mailbox, msg).await?;
                         } else {
-                            self.on_stop(None).await?;
+                            finish(self, &mailbox, StopReason::Stop(None)).await?;
                             break Ok(());
                         }
                     }
+                    Event::Abort => {
+                        finish(self, &mailbox, StopReason::Aborted).await?;
+                        break Ok(());
+                    }
                 }
             }
         }
@@ -93,4 +332,29 @@ pub trait Actor: Send + Sized + 'static {
     fn into_future(self, mailbox_size: Option<usize>) -> (ActorRef<Self>, ActorRun<Self>) {
         ActorRun::new(self, mailbox_size)
     }
+
+    /// Like [`Actor::into_future`], but hands back the actor and its mailbox instead of an
+    /// [`ActorRun`], for callers that drive the actor themselves, e.g. to instrument
+    /// [`Actor::run_with`] or interleave it with other work on the same task.
+    ///
+    /// The caller takes over what [`ActorRun`] would do: nothing runs until the actor is driven
+    /// with `actor.run_with(mailbox)`, and an actor driven this way has no
+    /// [`AbortHandle`](crate::AbortHandle).
+    fn into_parts(self, mailbox_size: Option<usize>) -> (ActorRef<Self>, Self, Mailbox<Self>) {
+        let (mailbox, actor_ref) = Mailbox::new(mailbox_size);
+        (actor_ref, self, mailbox)
+    }
+
+    /// Like [`Actor::into_future`], but runs the actor on the current task until
+    /// [`Actor::on_start`] has completed before returning, so that the [`ActorRef`] is only
+    /// handed out once the actor is ready, see [`ActorRun::run_until_started`]. The returned
+    /// [`ActorRun`] still needs to be spawned to run the rest of the actor. Fails with the error
+    /// of `on_start` if it fails.
+    fn start(
+        self,
+        mailbox_size: Option<usize>,
+    ) -> impl Future<Output = Result<(ActorRef<Self>, ActorRun<Self>), Self::Error>> + Send {
+        let (actor_ref, run) = self.into_future(mailbox_size);
+        async move { Ok((actor_ref, run.run_until_started().await?)) }
+    }
 }
@@ -1,6 +1,20 @@
-use crate::Actor;
+use std::{
+    hash::Hash,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use async_channel::{Sender as MultiSender, WeakSender as WeakMultiSender};
+use crate::{
+    ack::{Ack, Unacked},
+    channel::{Sender as MultiSender, WeakSender as WeakMultiSender},
+    control::Control,
+    mailbox::Packet,
+    queue::CoalesceKey,
+    shared::Shared,
+    Actor, Deadline, Tier,
+};
+
+use async_channel::{Sender as ControlSender, WeakSender as WeakControlSender};
 use async_oneshot_channel::{Sender as OneshotSender, WeakSender as WeakOneshotSender};
 
 #[derive(Debug)]
@@ -10,28 +24,557 @@ use async_oneshot_channel::{Sender as OneshotSender, WeakSender as WeakOneshotSe
 pub struct ActorRef<A: Actor> {
     pub(crate) sender: MultiSender<A::Message>,
     pub(crate) stop: OneshotSender<A::Message>,
+    pub(crate) control: ControlSender<Control<A>>,
+    pub(crate) weak: WeakActorRef<A>,
 }
 
 impl<A: Actor> ActorRef<A> {
+    pub(crate) fn new(
+        sender: MultiSender<A::Message>,
+        stop: OneshotSender<A::Message>,
+        control: ControlSender<Control<A>>,
+        shared: Arc<Shared>,
+    ) -> Self {
+        let weak = WeakActorRef {
+            sender: sender.downgrade(),
+            stop: stop.downgrade(),
+            control: control.downgrade(),
+            shared,
+            #[cfg(debug_assertions)]
+            is_self: false,
+        };
+        Self {
+            sender,
+            stop,
+            control,
+            weak,
+        }
+    }
+
     /// Sends a message to the actor. If the mailbox is full, the message will be returned in [`Err`].
+    ///
+    /// If the actor sheds load, see [`Mailbox::set_shed_above`](crate::Mailbox::set_shed_above),
+    /// the message is also returned right away once the mailbox holds at least the configured
+    /// number of messages.
     pub async fn send(&self, msg: A::Message) -> Result<(), A::Message> {
-        self.sender.send(msg).await.map_err(|e| e.0)
+        self.send_packet(Packet::new(msg))
+            .await
+            .map_err(|packet| packet.msg)
+    }
+
+    /// Sends a packet to the actor, applying load shedding.
+    pub(crate) async fn send_packet(
+        &self,
+        packet: Packet<A::Message>,
+    ) -> Result<(), Packet<A::Message>> {
+        send_packet::<A>(&self.sender, &self.weak.shared, packet).await
+    }
+
+    /// Attempts to send a packet to the actor without waiting, applying load shedding.
+    fn try_send_packet(
+        &self,
+        packet: Packet<A::Message>,
+    ) -> Result<(), TrySendError<Packet<A::Message>>> {
+        try_send_packet::<A>(&self.sender, &self.weak.shared, packet)
+    }
+
+    /// Like [`ActorRef::send`], but also returns a [`Receipt`] with the number of messages
+    /// queued in the mailbox right after this one was enqueued. Useful for spotting which
+    /// producers are building up a backlog.
+    pub async fn send_receipt(&self, msg: A::Message) -> Result<Receipt, A::Message> {
+        self.send(msg).await?;
+        Ok(Receipt {
+            depth_at_enqueue: self.sender.len(),
+        })
+    }
+
+    /// Attempts to send a message to the actor without waiting. If the mailbox is full or closed,
+    /// the message is returned in [`Err`].
+    pub fn try_send(&self, msg: A::Message) -> Result<(), TrySendError<A::Message>> {
+        self.try_send_packet(Packet::new(msg))
+            .map_err(|e| e.map(|packet| packet.msg))
+    }
+
+    /// Sends anything that converts into the actor's message type, such as a single variant's
+    /// payload with a `From` impl for the message enum. If the mailbox is full, the converted
+    /// message is returned in [`Err`].
+    ///
+    /// The original value cannot be returned on failure, as that would require converting back
+    /// out of the message type. This is a separate method rather than a generic
+    /// [`ActorRef::send`], since a generic `send` would break type inference for integer
+    /// literals and closures at existing call sites.
+    pub async fn send_into<T: Into<A::Message>>(&self, msg: T) -> Result<(), A::Message> {
+        self.send(msg.into()).await
+    }
+
+    /// Sends a message to the actor in the given scheduling tier. Tiers only affect the order of
+    /// delivery for actors with a weighted mailbox, see [`Mailbox::weighted`](crate::Mailbox::weighted);
+    /// otherwise this behaves like [`ActorRef::send`].
+    pub async fn send_tiered(&self, msg: A::Message, tier: Tier) -> Result<(), A::Message> {
+        let packet = Packet {
+            tier,
+            ..Packet::new(msg)
+        };
+        self.send_packet(packet).await.map_err(|packet| packet.msg)
+    }
+
+    /// Sends a message that goes stale after `ttl`: if it is still queued by then, the run loop
+    /// passes it to [`Actor::on_expired`] instead of the handler once it is received. Useful
+    /// for messages that are worthless once old, such as price updates, so that a backlog sheds
+    /// them without the handler doing any work.
+    pub async fn send_ttl(&self, msg: A::Message, ttl: Duration) -> Result<(), A::Message> {
+        let packet = Packet {
+            expires_at: Instant::now().checked_add(ttl),
+            ..Packet::new(msg)
+        };
+        self.send_packet(packet).await.map_err(|packet| packet.msg)
+    }
+
+    /// Sends a message that must be handled by `deadline`: if the deadline has passed by the
+    /// time the actor receives it, the run loop passes it to [`Actor::on_deadline_exceeded`]
+    /// instead of the handler. The handler finds the deadline in
+    /// [`HandlerCtx::deadline`](crate::HandlerCtx::deadline), so that it can send its own
+    /// messages with the same [`Deadline`] and bound the whole request end to end.
+    pub async fn send_by(
+        &self,
+        msg: A::Message,
+        deadline: impl Into<Deadline>,
+    ) -> Result<(), A::Message> {
+        let packet = Packet {
+            deadline: Some(deadline.into()),
+            ..Packet::new(msg)
+        };
+        self.send_packet(packet).await.map_err(|packet| packet.msg)
+    }
+
+    /// Sends a message that replaces the message queued with the same `key`, if there is one,
+    /// for actors with a coalescing mailbox, see [`Mailbox::coalescing`](crate::Mailbox::coalescing);
+    /// otherwise this behaves like [`ActorRef::send`]. Keys match if they are of the same type
+    /// and equal, so keys whose hashes collide don't replace each other's messages.
+    pub async fn send_coalesced<K: Hash + Eq + Send + 'static>(
+        &self,
+        key: K,
+        msg: A::Message,
+    ) -> Result<(), A::Message> {
+        let packet = Packet {
+            coalesce: Some(CoalesceKey::new(key)),
+            ..Packet::new(msg)
+        };
+        self.send_packet(packet).await.map_err(|packet| packet.msg)
+    }
+
+    /// Sends a message to the actor, and waits until the actor has processed it. Resolves to
+    /// `Ok(())` once the actor's [`Actor::on_msg`] has returned `Ok` for this message, including
+    /// when it is processed while the actor drains its mailbox on stop.
+    ///
+    /// Resolves to [`Unacked`] if the message could not be enqueued (in which case it is
+    /// dropped), if the handler returned an error, or if the actor stopped without processing it.
+    pub async fn send_ack(&self, msg: A::Message) -> Result<(), Unacked> {
+        let (ack, acked) = Ack::new();
+        let packet = Packet {
+            ack: Some(ack),
+            ..Packet::new(msg)
+        };
+        self.send_packet(packet).await.map_err(|_| Unacked)?;
+        acked.recv().await.ok_or(Unacked)
+    }
+
+    /// Waits for a free slot in the actor's mailbox and reserves it, so that the message sent
+    /// with [`Permit::send`] is guaranteed to fit. Useful when the message is expensive to
+    /// build, or must not be lost to a full mailbox once built. Dropping the [`Permit`] without
+    /// sending releases the slot. Fails with [`Closed`] if the mailbox is closed.
+    ///
+    /// A reserved slot counts towards the mailbox's capacity, so other senders wait, or fail to
+    /// [`ActorRef::try_send`], while the permit is held. Unbounded mailboxes always have room.
+    pub async fn reserve(&self) -> Result<Permit<'_, A>, Closed> {
+        self.sender.reserve().await.map_err(|_| Closed)?;
+        Ok(Permit { actor_ref: self })
+    }
+
+    /// Waits until the actor's mailbox has at least one free slot, without reserving it, e.g. so
+    /// that a producer loop only builds its next message once there is room for it. Unbounded
+    /// mailboxes always have room. Fails with [`Closed`] if the mailbox is closed.
+    ///
+    /// Unlike [`ActorRef::reserve`], the slot is not held, so another sender may take it first
+    /// and a following [`ActorRef::send`] can still wait.
+    pub async fn wait_for_capacity(&self) -> Result<(), Closed> {
+        self.sender.wait_for_room().await.map_err(|_| Closed)
+    }
+
+    /// Returns a guard that sends `msg` to the actor when it is dropped, e.g. so that a
+    /// connection handle tells the actor it was closed. The guard holds a clone of this
+    /// [`ActorRef`], so the actor keeps running at least until the message is sent.
+    ///
+    /// As `Drop` can't wait, the message is sent with [`ActorRef::try_send`]: if the mailbox is
+    /// full or closed when the guard is dropped, the message is silently dropped. Use a mailbox
+    /// with room to spare, or [`DropNotifier::cancel`] to send it some other way.
+    pub fn notify_on_drop(&self, msg: A::Message) -> DropNotifier<A> {
+        DropNotifier {
+            actor_ref: self.clone(),
+            msg: Some(msg),
+        }
+    }
+
+    /// Returns the number of messages the actor can handle right away without exceeding its
+    /// rate limit, or [`None`] if it was not started with
+    /// [`Mailbox::set_rate_limit`](crate::Mailbox::set_rate_limit).
+    pub fn rate_limit_tokens(&self) -> Option<u32> {
+        self.weak
+            .shared
+            .rate_limit
+            .get()
+            .map(|limit| limit.tokens())
+    }
+
+    /// Sends every message in `msgs` to the actor, and waits until all of them have been
+    /// processed, like [`ActorRef::send_ack`] for the batch as a whole. All messages share one
+    /// completion counter, which is cheaper than waiting on each message's acknowledgement.
+    ///
+    /// Messages processed while the actor drains its mailbox on stop count as processed. If any
+    /// message could not be enqueued, failed in its handler, or was dropped because the actor
+    /// stopped, this resolves to the number of messages that were processed, once the others
+    /// have been handled or dropped. Messages after one that could not be enqueued are not sent.
+    pub async fn send_batch_ack(&self, msgs: Vec<A::Message>) -> Result<(), usize> {
+        if msgs.is_empty() {
+            return Ok(());
+        }
+        let (batch, done, processed) = Ack::batch(msgs.len());
+        for msg in msgs {
+            let packet = Packet {
+                ack: Some(Ack::Batch(batch.clone())),
+                ..Packet::new(msg)
+            };
+            if self.send_packet(packet).await.is_err() {
+                break;
+            }
+        }
+        drop(batch);
+        match done.recv().await {
+            Some(()) => Ok(()),
+            None => Err(processed.load(std::sync::atomic::Ordering::Acquire)),
+        }
     }
 
     /// Stops the actor by sending a stop message to it. If a stop message has already been sent,
     /// the stop message will be returned in [`Err`].
     pub fn stop(&self, stop: A::Message) -> Result<(), A::Message> {
-        self.stop.send(stop)
+        self.request_stop(stop).map_err(|e| e.0)
+    }
+
+    /// Like [`ActorRef::stop`], but tells callers that lost a race to stop the actor apart with
+    /// [`AlreadyStopping`]. Every reference to the actor shares one flag, so only the first stop
+    /// request sends its message, and later ones fail right away without contending for the
+    /// stop channel.
+    pub fn request_stop(&self, stop: A::Message) -> Result<(), AlreadyStopping<A::Message>> {
+        if !self.weak.shared.request_stop() {
+            return Err(AlreadyStopping(stop));
+        }
+        self.stop.send(stop).map_err(AlreadyStopping)
+    }
+
+    /// Cuts short the drain that the actor performs once it has been stopped. Messages still
+    /// queued are dropped instead of handled, and [`Actor::on_stop`] runs with the original stop
+    /// message. A handler that is already running completes first, as the drain is only checked
+    /// between messages. If the actor has not been stopped yet, its drain will be skipped when
+    /// it is; this does not stop the actor by itself.
+    ///
+    /// This is the escalation for a drain that takes too long. Aborting the actor through an
+    /// [`AbortHandle`](crate::AbortHandle) also ends the drain, but without waiting for the
+    /// running handler to complete.
+    pub fn skip_drain(&self) {
+        self.weak.shared.skip_drain();
+    }
+
+    /// Like [`ActorRef::stop`], but for when any of several owners may initiate shutdown: if
+    /// the actor is already stopping or has stopped, `stop` is silently dropped. Only the first
+    /// stop message sent to the actor reaches [`Actor::on_stop`].
+    pub fn stop_idempotent(&self, stop: A::Message) {
+        let _ = self.stop(stop);
+    }
+
+    /// Stops the actor with the default message as the stop message, and returns whether this
+    /// call stopped it. This is a one-liner for actors whose stop message carries no data, such
+    /// as `()`. Returns `false` if a stop message has already been sent.
+    pub fn try_stop(&self) -> bool
+    where
+        A::Message: Default,
+    {
+        self.stop(A::Message::default()).is_ok()
+    }
+
+    /// Sends every message from `msgs` to the actor, in order, and reports how many were sent.
+    ///
+    /// With [`SendMode::BlockAll`], each send waits for space in the mailbox like
+    /// [`ActorRef::send`], so sending only stops early if the mailbox is closed. With
+    /// [`SendMode::BestEffort`], sending stops at the first message that does not fit in the
+    /// mailbox right away. Either way, the message that could not be sent and all messages after
+    /// it are returned in [`SendReport::remaining`], in their original order.
+    pub async fn send_iter(
+        &self,
+        msgs: impl IntoIterator<Item = A::Message>,
+        mode: SendMode,
+    ) -> SendReport<A::Message> {
+        let mut msgs = msgs.into_iter();
+        let mut sent = 0;
+        while let Some(msg) = msgs.next() {
+            let res = match mode {
+                SendMode::BlockAll => self.send_packet(Packet::new(msg)).await,
+                SendMode::BestEffort => self
+                    .try_send_packet(Packet::new(msg))
+                    .map_err(TrySendError::into_inner),
+            };
+            if let Err(packet) = res {
+                let remaining = std::iter::once(packet.msg).chain(msgs).collect();
+                return SendReport { sent, remaining };
+            }
+            sent += 1;
+        }
+        SendReport {
+            sent,
+            remaining: Vec::new(),
+        }
+    }
+
+    /// Returns the number of messages currently queued in the actor's mailbox.
+    pub fn len(&self) -> usize {
+        self.sender.len()
+    }
+
+    /// Returns a copy of the messages currently queued in the actor's mailbox, without removing
+    /// them, for debugging and admin tooling. The snapshot is taken at a single point in time,
+    /// and can be out of date as soon as it is returned.
+    ///
+    /// Messages are listed in delivery order for LIFO and priority mailboxes, and tier by tier
+    /// for weighted mailboxes. Returns [`SnapshotUnsupported`] for actors with the default FIFO mailbox,
+    /// which does not support inspecting queued messages.
+    pub fn snapshot_mailbox(&self) -> Result<Vec<A::Message>, SnapshotUnsupported>
+    where
+        A::Message: Clone,
+    {
+        self.sender.snapshot().ok_or(SnapshotUnsupported)
+    }
+
+    /// Returns `true` if there are no messages queued in the actor's mailbox.
+    pub fn is_empty(&self) -> bool {
+        self.sender.is_empty()
+    }
+
+    /// Returns the number of messages the actor may handle at the same time. This is 1, as
+    /// handlers run one after the other, see [`Actor::on_msg`], unless the actor was started
+    /// with [`ConcurrentActor::into_future_concurrent`](crate::ConcurrentActor::into_future_concurrent).
+    pub fn concurrency(&self) -> usize {
+        self.weak.shared.concurrency.get().copied().unwrap_or(1)
+    }
+
+    /// Returns the capacity of the actor's mailbox, or [`None`] if the mailbox is unbounded.
+    pub fn capacity(&self) -> Option<usize> {
+        self.sender.capacity()
+    }
+
+    /// Returns `true` if the actor's mailbox no longer accepts messages, because the actor is
+    /// stopping or has stopped.
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+
+    /// Tells whether a message sent right now would be accepted without waiting, without sending
+    /// anything, e.g. so that a load balancer can pick the least loaded of several actors that
+    /// are still running. Combines [`ActorRef::is_finished`], [`ActorRef::is_closed`],
+    /// [`ActorRef::len`] and [`ActorRef::capacity`] with the threshold of
+    /// [`Mailbox::set_shed_above`](crate::Mailbox::set_shed_above). It only reads atomics,
+    /// without taking any lock, whatever the kind of mailbox, so it is cheap enough to call on
+    /// many actors for every request.
+    ///
+    /// The answer can be out of date as soon as it is returned, as other senders race for the
+    /// same room. Slots reserved with [`ActorRef::reserve`] are not accounted for.
+    pub fn would_accept(&self) -> Admission {
+        let shared = &self.weak.shared;
+        if shared.is_finished() || shared.is_stop_requested() || self.sender.is_closed() {
+            return Admission::Closed;
+        }
+        let depth = self.sender.len();
+        let shedding = shared
+            .shed_above
+            .get()
+            .is_some_and(|&shed_above| depth >= shed_above);
+        let full = self.sender.capacity().is_some_and(|cap| depth >= cap);
+        if shedding || full {
+            Admission::Backpressure(depth)
+        } else {
+            Admission::Accept
+        }
+    }
+
+    /// Closes the actor's mailbox, so that no further messages can be sent, and returns `true`
+    /// if this call closed it. This is a lighter shutdown than [`ActorRef::stop`]: the actor
+    /// handles the messages that are already queued, and then stops with [`Actor::on_stop`]
+    /// being called with [`None`]. Any clone of the reference can close the mailbox, and closing
+    /// it again has no effect.
+    pub fn close(&self) -> bool {
+        self.sender.close()
+    }
+
+    /// Returns `true` once the actor's [`Actor::on_start`] has completed successfully. Messages
+    /// sent before then are queued as usual, and processed once the actor has started.
+    pub fn is_started(&self) -> bool {
+        self.weak.shared.is_started()
+    }
+
+    /// Returns `true` once the actor has stopped running, including [`Actor::on_stop`], and its
+    /// [`ActorRun`](crate::ActorRun) has resolved or was dropped. Unlike
+    /// [`ActorRef::is_closed`], which turns `true` as soon as the mailbox stops accepting
+    /// messages, this only turns `true` once nothing of the actor is running anymore, e.g. for
+    /// a supervisor polling the health of actors it spawned.
+    ///
+    /// Actors that are not driven by an `ActorRun`, e.g. because they run their mailbox
+    /// directly, count as finished once their mailbox has been dropped.
+    pub fn is_finished(&self) -> bool {
+        self.weak.is_finished()
+    }
+
+    /// Returns `true` once the actor has been asked to stop, see
+    /// [`WeakActorRef::stop_requested`].
+    pub fn stop_requested(&self) -> bool {
+        self.weak.stop_requested()
+    }
+
+    /// Waits until the actor's [`Actor::on_start`] has completed successfully. Resolves to
+    /// [`NotStarted`] if the actor stops running without starting, e.g. because `on_start`
+    /// returned an error, which is then reported by the actor's [`ActorRun`](crate::ActorRun).
+    pub async fn wait_started(&self) -> Result<(), NotStarted> {
+        if self.weak.shared.wait_started().await {
+            Ok(())
+        } else {
+            Err(NotStarted)
+        }
+    }
+
+    /// Returns `true` if the actor was aborted rather than stopping on its own, either with
+    /// [`AbortHandle::abort`](crate::AbortHandle::abort) or by dropping its
+    /// [`ActorRun`](crate::ActorRun) before it finished. This is set before [`Actor::on_stop`]
+    /// runs on abort, so that `on_stop` can tell an abort from a stop by dropping every
+    /// reference, where it receives [`None`] as well.
+    ///
+    /// An aborted actor counts as failed, e.g. for [`ActorRef::drain_and_stop`] and
+    /// [`ShutdownGroup::stop_all`](crate::ShutdownGroup::stop_all), even though its `ActorRun`
+    /// resolves with the actor.
+    pub fn is_aborted(&self) -> bool {
+        self.weak.is_aborted()
+    }
+
+    /// Waits until the actor next goes idle, i.e. finishes handling a message and finds its
+    /// mailbox empty. This only fires on the transition from busy to idle: if the mailbox is
+    /// already empty, it waits for the next message to be handled. Fails with [`Exited`] if
+    /// the actor stops running first.
+    ///
+    /// Useful for autoscalers, or to wait for a burst of messages to be worked off. Only the
+    /// default [`Actor::run_with`] reports idleness.
+    pub async fn idle(&self) -> Result<(), Exited> {
+        if self.weak.shared.wait_idle().await {
+            Ok(())
+        } else {
+            Err(Exited)
+        }
+    }
+
+    /// Stops the actor without a stop message: the mailbox stops accepting new messages right
+    /// away, the actor processes every message that is already queued, and then runs
+    /// [`Actor::on_stop`] with [`None`]. Resolves once the actor has stopped running, to `Ok` if
+    /// it drained and stopped cleanly, or to the reason it did not, see [`DrainStopError`]. The
+    /// error itself is reported by the actor's [`ActorRun`](crate::ActorRun).
+    ///
+    /// This can be called from any number of references concurrently; every call resolves once
+    /// the actor has stopped. Unlike [`ActorRef::stop`], it does not require a stop message.
+    pub async fn drain_and_stop(&self) -> Result<(), DrainStopError> {
+        let shared = &self.weak.shared;
+        if shared.is_exited() {
+            return Err(DrainStopError::Exited);
+        }
+        let stopping = shared.is_stop_requested();
+        self.sender.close();
+        let failed = shared.wait_finished().await;
+        if !shared.is_started() {
+            Err(DrainStopError::NotStarted)
+        } else if failed || shared.is_stop_failed() {
+            Err(DrainStopError::Failed)
+        } else if stopping {
+            Err(DrainStopError::AlreadyStopping)
+        } else {
+            Ok(())
+        }
     }
 
     /// Creates a [`WeakActorRef`] from this [`ActorRef`], which can be used as a handle to the actor that
     /// doesn't keep the actor alive, if it is the last handle to the actor.
     pub fn downgrade(&self) -> WeakActorRef<A> {
-        WeakActorRef {
-            sender: self.sender.downgrade(),
-            stop: self.stop.downgrade(),
+        self.weak.clone()
+    }
+
+    /// Splits the reference into its message sender and its stop sender, e.g. to hand the stop
+    /// sender to a shutdown system that works with plain oneshot channels. The parts keep the
+    /// actor alive like the reference did, until either of them is dropped: dropping the
+    /// [`MessageSender`] closes the mailbox, and dropping the stop sender stops the actor as if
+    /// every [`ActorRef`] was dropped. [`ActorRef::from_parts`] puts them back together.
+    pub fn into_parts(self) -> (MessageSender<A>, OneshotSender<A::Message>) {
+        let sender = MessageSender {
+            sender: self.sender,
+            control: self.control,
+            weak: self.weak,
+        };
+        (sender, self.stop)
+    }
+
+    /// Reassembles a reference from the parts returned by [`ActorRef::into_parts`]. The stop
+    /// sender must belong to the same actor as `sender`, which cannot be checked, as oneshot
+    /// senders carry no identity; otherwise stopping the returned reference stops another actor.
+    pub fn from_parts(sender: MessageSender<A>, stop: OneshotSender<A::Message>) -> Self {
+        Self {
+            sender: sender.sender,
+            stop,
+            control: sender.control,
+            weak: sender.weak,
         }
     }
+
+    /// Creates a [`SendRef`] to the actor, which can only send messages to it. Like a
+    /// [`WeakActorRef`], it doesn't keep the actor alive.
+    pub fn downgrade_sender_only(&self) -> SendRef<A> {
+        SendRef(self.downgrade())
+    }
+
+    /// Creates a [`StopRef`] to the actor, which can only stop it. Like a [`WeakActorRef`], it
+    /// doesn't keep the actor alive.
+    pub fn stop_only(&self) -> StopRef<A> {
+        StopRef(self.downgrade())
+    }
+
+    /// Returns a receiver for the values the actor emits, if it was started with outputs of
+    /// type `Out`, see [`Mailbox::set_outputs`](crate::Mailbox::set_outputs). Each value is
+    /// received by only one of the receivers handed out. The receiver ends once the actor has
+    /// stopped and all emitted values have been received.
+    pub fn outputs<Out: Send + 'static>(&self) -> Option<async_channel::Receiver<Out>> {
+        let (_, receiver) = self.weak.shared.outputs.get()?.channel::<Out>()?;
+        Some(receiver.clone())
+    }
+
+    /// Returns a reference to the [`WeakActorRef`] cached in this [`ActorRef`]. This is the same
+    /// handle that [`ActorRef::downgrade`] would return, but without cloning it, which is useful
+    /// in hot paths that only need to borrow the weak reference.
+    pub fn as_weak(&self) -> &WeakActorRef<A> {
+        &self.weak
+    }
+
+    /// Returns `true` if `other` refers to the same actor as this [`ActorRef`]. Clones, and
+    /// references obtained by downgrading and upgrading again, all refer to the same actor.
+    pub fn same_actor(&self, other: &ActorRef<A>) -> bool {
+        self.weak.same_actor(&other.weak)
+    }
+
+    /// Returns `true` if `other` refers to the same actor as this [`ActorRef`], see
+    /// [`ActorRef::same_actor`].
+    pub fn same_actor_weak(&self, other: &WeakActorRef<A>) -> bool {
+        self.weak.same_actor(other)
+    }
 }
 
 impl<A: Actor> Clone for ActorRef<A> {
@@ -39,10 +582,270 @@ impl<A: Actor> Clone for ActorRef<A> {
         Self {
             sender: self.sender.clone(),
             stop: self.stop.clone(),
+            control: self.control.clone(),
+            weak: self.weak.clone(),
+        }
+    }
+}
+
+/// References compare equal if they refer to the same actor, see [`ActorRef::same_actor`], so
+/// they can be used as keys in sets and maps of actors. A reference also compares equal to the
+/// [`WeakActorRef`]s of the same actor.
+impl<A: Actor> PartialEq for ActorRef<A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.same_actor(other)
+    }
+}
+
+impl<A: Actor> Eq for ActorRef<A> {}
+
+impl<A: Actor> PartialEq<WeakActorRef<A>> for ActorRef<A> {
+    fn eq(&self, other: &WeakActorRef<A>) -> bool {
+        self.same_actor_weak(other)
+    }
+}
+
+/// Hashes the actor's identity, consistently with the [`WeakActorRef`]s of the same actor.
+impl<A: Actor> std::hash::Hash for ActorRef<A> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.weak.hash(state);
+    }
+}
+
+/// Downgrades every [`ActorRef`] in `refs`, in order.
+pub fn downgrade_all<A: Actor>(refs: &[ActorRef<A>]) -> Vec<WeakActorRef<A>> {
+    refs.iter().map(ActorRef::downgrade).collect()
+}
+
+/// Upgrades every [`WeakActorRef`] in `weaks`, in order, with [`None`] for actors that have
+/// been dropped.
+pub fn upgrade_all<A: Actor>(weaks: &[WeakActorRef<A>]) -> Vec<Option<ActorRef<A>>> {
+    weaks.iter().map(WeakActorRef::upgrade).collect()
+}
+
+/// Upgrades the [`WeakActorRef`]s in `weaks` whose actors are still alive, in order, skipping
+/// the ones that have been dropped.
+pub fn upgrade_alive<A: Actor>(weaks: &[WeakActorRef<A>]) -> Vec<ActorRef<A>> {
+    weaks.iter().filter_map(WeakActorRef::upgrade).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Returned by [`ActorRef::try_send`] when a message could not be sent right away.
+pub enum TrySendError<M> {
+    /// The actor's mailbox is full.
+    Full(M),
+    /// The actor's mailbox is closed, or the actor has been dropped.
+    Closed(M),
+    /// The actor is shedding load, see [`Mailbox::set_shed_above`](crate::Mailbox::set_shed_above).
+    Shed(M),
+}
+
+impl<M> TrySendError<M> {
+    /// Maps the message that could not be sent, keeping the reason.
+    pub(crate) fn map<T>(self, f: impl FnOnce(M) -> T) -> TrySendError<T> {
+        match self {
+            Self::Full(msg) => TrySendError::Full(f(msg)),
+            Self::Closed(msg) => TrySendError::Closed(f(msg)),
+            Self::Shed(msg) => TrySendError::Shed(f(msg)),
+        }
+    }
+
+    /// Returns the message that could not be sent.
+    pub fn into_inner(self) -> M {
+        match self {
+            Self::Full(msg) | Self::Closed(msg) | Self::Shed(msg) => msg,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Whether an actor would accept a message right now, see [`ActorRef::would_accept`].
+pub enum Admission {
+    /// The message would be queued right away.
+    Accept,
+    /// The mailbox is full, or the actor is shedding load, with this many messages queued. A
+    /// message would have to wait, or be rejected.
+    Backpressure(usize),
+    /// The actor is stopping or has stopped, so the message would be rejected.
+    Closed,
+}
+
+/// A slot in an actor's mailbox, reserved with [`ActorRef::reserve`].
+pub struct Permit<'a, A: Actor> {
+    actor_ref: &'a ActorRef<A>,
+}
+
+impl<A: Actor> Permit<'_, A> {
+    /// Sends a message into the reserved slot, without waiting. The message is only returned in
+    /// [`Err`] if the mailbox was closed after the slot was reserved.
+    ///
+    /// Load shedding is not applied, as the room for the message has already been set aside.
+    pub fn send(self, msg: A::Message) -> Result<(), A::Message> {
+        let sender = &self.actor_ref.sender;
+        std::mem::forget(self);
+        sender
+            .send_reserved(Packet::new(msg))
+            .map_err(|packet| packet.msg)
+    }
+}
+
+impl<A: Actor> Drop for Permit<'_, A> {
+    fn drop(&mut self) {
+        self.actor_ref.sender.release();
+    }
+}
+
+impl<A: Actor> std::fmt::Debug for Permit<'_, A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Permit").finish_non_exhaustive()
+    }
+}
+
+/// Sends a message to an actor when dropped, see [`ActorRef::notify_on_drop`].
+pub struct DropNotifier<A: Actor> {
+    actor_ref: ActorRef<A>,
+    msg: Option<A::Message>,
+}
+
+impl<A: Actor> DropNotifier<A> {
+    /// Returns the actor the message will be sent to.
+    pub fn actor_ref(&self) -> &ActorRef<A> {
+        &self.actor_ref
+    }
+
+    /// Disarms the guard, returning the message instead of sending it.
+    pub fn cancel(mut self) -> A::Message {
+        self.msg.take().expect("`DropNotifier` message taken twice")
+    }
+}
+
+impl<A: Actor> Drop for DropNotifier<A> {
+    fn drop(&mut self) {
+        if let Some(msg) = self.msg.take() {
+            let _ = self.actor_ref.try_send(msg);
         }
     }
 }
 
+impl<A: Actor> std::fmt::Debug for DropNotifier<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DropNotifier").finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Returned by [`ActorRef::reserve`] when the actor's mailbox is closed.
+pub struct Closed;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Returned by [`ActorRef::snapshot_mailbox`] for actors whose mailbox cannot be inspected.
+pub struct SnapshotUnsupported;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Returned by [`ActorRef::wait_started`] when the actor stopped running without completing
+/// [`Actor::on_start`].
+pub struct NotStarted;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The ways in which [`ActorRef::drain_and_stop`] can fail to drain and stop the actor cleanly.
+pub enum DrainStopError {
+    /// The actor had already stopped running.
+    Exited,
+    /// The actor had already been asked to stop with a stop message, like [`AlreadyStopping`],
+    /// so it stopped with that message instead. It still drained its mailbox, unless the drain
+    /// was skipped.
+    AlreadyStopping,
+    /// The actor stopped running without completing [`Actor::on_start`], like [`NotStarted`].
+    NotStarted,
+    /// The actor stopped with an error, from a handler or from [`Actor::on_stop`].
+    Failed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Returned by [`WeakActorRef::upgrade_err`] when the actor's references are gone.
+pub enum UpgradeError {
+    /// Every reference that can send messages to the actor was dropped, including the
+    /// [`MessageSender`]s split off with [`ActorRef::into_parts`]. The mailbox is closed.
+    MessageSenderGone,
+    /// Messages can still be sent, but every stop sender was dropped, which stops the actor as
+    /// if every [`ActorRef`] was dropped.
+    StopSenderGone,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Returned by [`ActorRef::request_stop`] when the actor has already been asked to stop, along
+/// with the stop message that was not sent.
+pub struct AlreadyStopping<M>(pub M);
+
+impl<M> AlreadyStopping<M> {
+    /// Returns the stop message that was not sent.
+    pub fn into_inner(self) -> M {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Returned by [`WeakActorRef::send`] when a message could not be sent.
+pub enum WeakSendError<M> {
+    /// The actor has been dropped, or its mailbox is closed or shedding load.
+    Closed(M),
+    /// The actor sent to itself from a handler while its mailbox was full, which would have
+    /// deadlocked. Only detected in debug builds.
+    WouldBlock(SelfSendWouldBlock<M>),
+}
+
+impl<M> WeakSendError<M> {
+    /// Returns the message that could not be sent.
+    pub fn into_inner(self) -> M {
+        match self {
+            Self::Closed(msg) | Self::WouldBlock(SelfSendWouldBlock(msg)) => msg,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A self-send from a handler that would have waited for space in the actor's own full
+/// mailbox, along with the message that was not sent, see [`WeakActorRef::send`].
+pub struct SelfSendWouldBlock<M>(pub M);
+
+impl<M> SelfSendWouldBlock<M> {
+    /// Returns the message that was not sent.
+    pub fn into_inner(self) -> M {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Returned by [`ActorRef::idle`] when the actor stopped running before going idle.
+pub struct Exited;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Returned by [`ActorRef::send_receipt`] for a message that was sent.
+pub struct Receipt {
+    /// The number of messages in the mailbox right after the message was enqueued, including
+    /// the message itself unless the actor already received it. The mailbox is shared, so this
+    /// is only approximate.
+    pub depth_at_enqueue: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How [`ActorRef::send_iter`] behaves when the actor's mailbox is full.
+pub enum SendMode {
+    /// Wait for space in the mailbox before sending each message.
+    BlockAll,
+    /// Stop sending as soon as a message does not fit in the mailbox.
+    BestEffort,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The outcome of [`ActorRef::send_iter`].
+pub struct SendReport<M> {
+    /// The number of messages that were sent.
+    pub sent: usize,
+    /// The messages that were not sent, in the order they were given.
+    pub remaining: Vec<M>,
+}
+
 #[derive(Debug)]
 /// A reference to an actor that allows messages to be sent to the actor.
 ///
@@ -51,30 +854,142 @@ impl<A: Actor> Clone for ActorRef<A> {
 pub struct WeakActorRef<A: Actor> {
     sender: WeakMultiSender<A::Message>,
     stop: WeakOneshotSender<A::Message>,
+    control: WeakControlSender<Control<A>>,
+    pub(crate) shared: Arc<Shared>,
+    /// Set only on the reference handed to the actor's own handlers, see [`WeakActorRef::send`].
+    /// Handlers only borrow that reference, and every clone of it is unmarked, so it can only
+    /// be used on the actor's own task.
+    #[cfg(debug_assertions)]
+    is_self: bool,
 }
 
 impl<A: Actor> WeakActorRef<A> {
     /// Attempts to upgrade this [`WeakActorRef`] to an [`ActorRef`]. If the actor has been dropped,
     /// this will return [`None`].
     pub fn upgrade(&self) -> Option<ActorRef<A>> {
-        Some(ActorRef {
-            sender: self.sender.upgrade()?,
-            stop: self.stop.upgrade()?,
+        self.upgrade_err().ok()
+    }
+
+    /// Like [`WeakActorRef::upgrade`], but tells which half of the actor's references is gone
+    /// if it fails. Both halves are alive as long as an [`ActorRef`] is, but the parts returned
+    /// by [`ActorRef::into_parts`] can be dropped separately. If both halves are gone,
+    /// [`UpgradeError::MessageSenderGone`] is returned.
+    pub fn upgrade_err(&self) -> Result<ActorRef<A>, UpgradeError> {
+        let sender = self.sender.upgrade();
+        let control = self.control.upgrade();
+        let (Some(sender), Some(control)) = (sender, control) else {
+            return Err(UpgradeError::MessageSenderGone);
+        };
+        Ok(ActorRef {
+            sender,
+            stop: self.stop.upgrade().ok_or(UpgradeError::StopSenderGone)?,
+            control,
+            weak: self.clone(),
         })
     }
 
-    /// Sends a message to the actor. If the actor has been dropped, or the mailbox is full,
-    /// the message will be returned in [`Err`].
-    pub async fn send(&self, msg: A::Message) -> Result<(), A::Message> {
+    /// Returns `true` if the actor was aborted, see [`ActorRef::is_aborted`].
+    pub fn is_aborted(&self) -> bool {
+        self.shared.is_aborted()
+    }
+
+    /// Sends a message to the actor, waiting for space if the mailbox is full. If the actor has
+    /// been dropped, or its mailbox is closed, the message is returned in
+    /// [`WeakSendError::Closed`].
+    ///
+    /// When an actor sends to itself from a handler through the `this` reference it was given,
+    /// waiting for space in a full mailbox would deadlock, as the actor cannot drain its mailbox
+    /// until the handler returns. In debug builds, such a send fails right away with
+    /// [`WeakSendError::WouldBlock`] instead of waiting; release builds skip the check, so fix
+    /// what it finds, e.g. with [`Actor::on_msg_emit`], which avoids the problem altogether.
+    ///
+    /// The check applies while any handler of the actor is running. A
+    /// [`ConcurrentActor`](crate::ConcurrentActor) with room for more handlers could still drain
+    /// its mailbox, so its self-sends may fail where waiting would have succeeded.
+    pub async fn send(&self, msg: A::Message) -> Result<(), WeakSendError<A::Message>> {
+        let Some(actor_ref) = self.upgrade() else {
+            return Err(WeakSendError::Closed(msg));
+        };
+        #[cfg(debug_assertions)]
+        if self.is_self && self.shared.is_in_handler() {
+            return actor_ref.try_send(msg).map_err(|err| match err {
+                TrySendError::Full(msg) => WeakSendError::WouldBlock(SelfSendWouldBlock(msg)),
+                TrySendError::Closed(msg) | TrySendError::Shed(msg) => WeakSendError::Closed(msg),
+            });
+        }
+        actor_ref.send(msg).await.map_err(WeakSendError::Closed)
+    }
+
+    /// Marks this as the reference handed to the actor's own handlers, see
+    /// [`WeakActorRef::send`].
+    #[cfg(debug_assertions)]
+    pub(crate) fn into_self(mut self) -> Self {
+        self.is_self = true;
+        self
+    }
+
+    /// Emits `out` on the actor's output channel, see
+    /// [`Mailbox::set_outputs`](crate::Mailbox::set_outputs), waiting for space if the channel
+    /// is bounded and full. If the actor was not started with
+    /// outputs of type `Out`, or has stopped, `out` is returned in [`Err`].
+    pub async fn emit<Out: Send + 'static>(&self, out: Out) -> Result<(), Out> {
+        match self.shared.outputs.get().and_then(|o| o.channel::<Out>()) {
+            Some((sender, _)) => sender.send(out).await.map_err(|e| e.0),
+            None => Err(out),
+        }
+    }
+
+    /// Releases the messages stashed by [`Actor::should_stash`] once the running handler
+    /// returns successfully, see [`Mailbox::unstash`](crate::Mailbox::unstash). Called from
+    /// outside the actor, the stash is released after the next message it handles.
+    pub fn unstash(&self) {
+        self.shared.request_unstash();
+    }
+
+    /// Attempts to send a message to the actor without waiting. If the actor has been dropped,
+    /// this returns [`TrySendError::Closed`].
+    pub fn try_send(&self, msg: A::Message) -> Result<(), TrySendError<A::Message>> {
         match self.upgrade() {
-            Some(actor_ref) => actor_ref.send(msg).await,
-            None => Err(msg),
+            Some(actor_ref) => actor_ref.try_send(msg),
+            None => Err(TrySendError::Closed(msg)),
         }
     }
 
+    /// Sends anything that converts into the actor's message type, see [`ActorRef::send_into`].
+    /// Fails like [`WeakActorRef::send`], with the converted message.
+    pub async fn send_into<T: Into<A::Message>>(
+        &self,
+        msg: T,
+    ) -> Result<(), WeakSendError<A::Message>> {
+        self.send(msg.into()).await
+    }
+
+    /// Returns `true` if `other` refers to the same actor as this [`WeakActorRef`], see
+    /// [`ActorRef::same_actor`]. This keeps working after the actor has been dropped.
+    pub fn same_actor(&self, other: &WeakActorRef<A>) -> bool {
+        Arc::ptr_eq(&self.shared, &other.shared)
+    }
+
+    /// Returns `true` once the actor has stopped running, see [`ActorRef::is_finished`].
+    pub fn is_finished(&self) -> bool {
+        self.shared.is_finished()
+    }
+
+    /// Returns `true` once the actor has been asked to stop, with a stop message sent through
+    /// any of its references. A handler that runs for a long time can check this from time to
+    /// time and return early, so that the run loop gets to the stop sooner: the stop message is
+    /// only received once the handler has returned. Returning early is up to the handler, and
+    /// the messages still queued are drained as usual.
+    pub fn stop_requested(&self) -> bool {
+        self.shared.is_stop_requested()
+    }
+
     /// Stops the actor by sending a stop message to it. If the actor has been dropped, or the mailbox is full,
     /// the stop message will be returned in [`Err`].
     pub fn stop(&self, stop: A::Message) -> Result<(), A::Message> {
+        if !self.shared.request_stop() {
+            return Err(stop);
+        }
         self.stop.send(stop)
     }
 }
@@ -84,14 +999,168 @@ impl<A: Actor> Clone for WeakActorRef<A> {
         Self {
             sender: self.sender.clone(),
             stop: self.stop.clone(),
+            control: self.control.clone(),
+            shared: self.shared.clone(),
+            // Clones may be moved to other tasks, which can safely wait for the actor.
+            #[cfg(debug_assertions)]
+            is_self: false,
+        }
+    }
+}
+
+/// Weak references compare equal if they refer to the same actor, see
+/// [`WeakActorRef::same_actor`], which keeps working after the actor has been dropped. This makes
+/// them usable as keys in registries of subscribers, and a weak reference also compares equal to
+/// the [`ActorRef`]s of the same actor.
+impl<A: Actor> PartialEq for WeakActorRef<A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.same_actor(other)
+    }
+}
+
+impl<A: Actor> Eq for WeakActorRef<A> {}
+
+impl<A: Actor> PartialEq<ActorRef<A>> for WeakActorRef<A> {
+    fn eq(&self, other: &ActorRef<A>) -> bool {
+        other.same_actor_weak(self)
+    }
+}
+
+/// Hashes the actor's identity: the state shared by all references to the actor, which lives
+/// as long as any of them does.
+impl<A: Actor> std::hash::Hash for WeakActorRef<A> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.shared).hash(state);
+    }
+}
+
+/// Sends a packet through `sender`, applying the actor's load shedding. Shared by
+/// [`ActorRef`] and [`MessageSender`].
+async fn send_packet<A: Actor>(
+    sender: &MultiSender<A::Message>,
+    shared: &Shared,
+    packet: Packet<A::Message>,
+) -> Result<(), Packet<A::Message>> {
+    if shed::<A>(sender, shared, &packet.msg) {
+        return Err(packet);
+    }
+    sender.send(packet).await.map_err(|e| e.0)
+}
+
+/// Attempts to send a packet through `sender` without waiting, see [`send_packet`].
+fn try_send_packet<A: Actor>(
+    sender: &MultiSender<A::Message>,
+    shared: &Shared,
+    packet: Packet<A::Message>,
+) -> Result<(), TrySendError<Packet<A::Message>>> {
+    if shed::<A>(sender, shared, &packet.msg) {
+        return Err(TrySendError::Shed(packet));
+    }
+    sender.try_send(packet).map_err(|e| match e {
+        async_channel::TrySendError::Full(packet) => TrySendError::Full(packet),
+        async_channel::TrySendError::Closed(packet) => TrySendError::Closed(packet),
+    })
+}
+
+/// Returns `true` if `msg` should be rejected because the actor is shedding load, in which
+/// case [`Actor::on_shed`] has been called for it.
+fn shed<A: Actor>(sender: &MultiSender<A::Message>, shared: &Shared, msg: &A::Message) -> bool {
+    match shared.shed_above.get() {
+        Some(&shed_above) if sender.len() >= shed_above => {
+            A::on_shed(msg);
+            true
+        }
+        _ => false,
+    }
+}
+
+#[derive(Debug)]
+/// The message sending half of an [`ActorRef`], created with [`ActorRef::into_parts`]. It keeps
+/// the actor's mailbox open, but cannot stop the actor.
+pub struct MessageSender<A: Actor> {
+    sender: MultiSender<A::Message>,
+    control: ControlSender<Control<A>>,
+    weak: WeakActorRef<A>,
+}
+
+impl<A: Actor> MessageSender<A> {
+    /// Sends a message to the actor, see [`ActorRef::send`].
+    pub async fn send(&self, msg: A::Message) -> Result<(), A::Message> {
+        send_packet::<A>(&self.sender, &self.weak.shared, Packet::new(msg))
+            .await
+            .map_err(|packet| packet.msg)
+    }
+
+    /// Attempts to send a message to the actor without waiting, see [`ActorRef::try_send`].
+    pub fn try_send(&self, msg: A::Message) -> Result<(), TrySendError<A::Message>> {
+        try_send_packet::<A>(&self.sender, &self.weak.shared, Packet::new(msg))
+            .map_err(|e| e.map(|packet| packet.msg))
+    }
+
+    /// Returns a [`WeakActorRef`] to the actor.
+    pub fn downgrade(&self) -> WeakActorRef<A> {
+        self.weak.clone()
+    }
+}
+
+impl<A: Actor> Clone for MessageSender<A> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            control: self.control.clone(),
+            weak: self.weak.clone(),
         }
     }
 }
 
+/// A weak reference to an actor that can only send messages to it, created with
+/// [`ActorRef::downgrade_sender_only`]. Useful for handing out to producers that should not be
+/// able to stop the actor.
+pub struct SendRef<A: Actor>(WeakActorRef<A>);
+
+impl<A: Actor> SendRef<A> {
+    /// Sends a message to the actor. If the actor has been dropped, or the mailbox is full,
+    /// the message will be returned in [`Err`].
+    pub async fn send(&self, msg: A::Message) -> Result<(), A::Message> {
+        self.0.send(msg).await.map_err(WeakSendError::into_inner)
+    }
+
+    /// Attempts to send a message to the actor without waiting. If the actor has been dropped,
+    /// this returns [`TrySendError::Closed`].
+    pub fn try_send(&self, msg: A::Message) -> Result<(), TrySendError<A::Message>> {
+        self.0.try_send(msg)
+    }
+}
+
+impl<A: Actor> Clone for SendRef<A> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+/// A weak reference to an actor that can only stop it, created with [`ActorRef::stop_only`].
+/// Useful for handing out to supervisors or shutdown hooks that should not be able to send
+/// messages to the actor.
+pub struct StopRef<A: Actor>(WeakActorRef<A>);
+
+impl<A: Actor> StopRef<A> {
+    /// Stops the actor by sending a stop message to it. If the actor has been dropped, or a stop
+    /// message has already been sent, the stop message will be returned in [`Err`].
+    pub fn stop(&self, stop: A::Message) -> Result<(), A::Message> {
+        self.0.stop(stop)
+    }
+}
+
+impl<A: Actor> Clone for StopRef<A> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
 impl<A: Actor> TryInto<ActorRef<A>> for WeakActorRef<A> {
-    type Error = ();
+    type Error = UpgradeError;
 
     fn try_into(self) -> Result<ActorRef<A>, Self::Error> {
-        self.upgrade().ok_or(())
+        self.upgrade_err()
     }
 }
@@ -1,36 +1,275 @@
 use std::{
+    fmt,
     future::Future,
     pin::Pin,
+    sync::Arc,
     task::{Context, Poll},
 };
 
-use crate::{Actor, ActorRef, Mailbox};
+use crate::{shared::Shared, AbortHandle, Actor, ActorRef, Mailbox};
+
+/// The ways in which an [`ActorRun`] can fail.
+pub enum RunError<A: Actor> {
+    /// The actor failed while running, e.g. because [`Actor::on_start`] or a handler returned
+    /// an error, and it should be treated as crashed.
+    Failed(A::Error),
+    /// The actor stopped as requested, but [`Actor::on_stop`] returned `error` while cleaning up.
+    /// The actor itself terminated normally, so a supervisor should not restart it. Errors from
+    /// `on_stop` are only told apart by the default [`Actor::run_with`]; custom run loops
+    /// report them as [`RunError::Failed`].
+    Stop { actor: A, error: A::Error },
+}
+
+impl<A: Actor> RunError<A> {
+    /// Returns the error, discarding the actor if it stopped.
+    pub fn into_error(self) -> A::Error {
+        match self {
+            Self::Failed(error) | Self::Stop { error, .. } => error,
+        }
+    }
+}
+
+impl<A: Actor> fmt::Debug for RunError<A>
+where
+    A::Error: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Failed(error) => f.debug_tuple("Failed").field(error).finish(),
+            Self::Stop { error, .. } => f
+                .debug_struct("Stop")
+                .field("error", error)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+#[derive(Debug)]
+/// An error along with the message whose handler caused it, see
+/// [`ActorRun::with_failed_message`].
+pub struct HandlerError<E> {
+    /// The error the actor failed with.
+    pub error: E,
+    /// The failing message, formatted with `Debug`, if it is known.
+    pub message: Option<String>,
+}
 
 /// A future that drives an actor from start to completion.
 /// Once awaited, it will run the actor, process all messages,
 /// and eventually resolve with either the actor (on success) or an error.
 pub struct ActorRun<A: Actor> {
-    future: Pin<Box<dyn Future<Output = Result<A, A::Error>> + Send>>,
+    future: Pin<Box<dyn Future<Output = Result<A, RunError<A>>> + Send>>,
+    /// Told how the actor finished, see [`ShutdownGroup`](crate::ShutdownGroup).
+    pub(crate) shared: Arc<Shared>,
+    /// Set once the future has been polled, to warn about actors that were never run.
+    #[cfg(debug_assertions)]
+    polled: bool,
 }
 
 impl<A: Actor + Send + 'static> ActorRun<A> {
     /// Creates a new [`ActorRef`] and [`ActorRun`] future for `actor` with optional mailbox size.
-    pub fn new(mut actor: A, mailbox_size: Option<usize>) -> (ActorRef<A>, Self) {
-        let (mailbox, actor_ref) = Mailbox::new(mailbox_size);
+    pub fn new(actor: A, mailbox_size: Option<usize>) -> (ActorRef<A>, Self) {
+        Self::with_mailbox(actor, Mailbox::new(mailbox_size))
+    }
 
+    /// Creates an [`ActorRun`] future for `actor` that runs with an already constructed mailbox,
+    /// such as one created by [`Mailbox::lifo`].
+    pub fn with_mailbox(
+        mut actor: A,
+        (mailbox, actor_ref): (Mailbox<A>, ActorRef<A>),
+    ) -> (ActorRef<A>, Self) {
+        let shared: Arc<Shared> = mailbox.this.shared.clone();
+        shared.track_run();
+        let finished = shared.clone();
         let future = Box::pin(async move {
-            actor.run_with(mailbox).await?;
-            Ok(actor)
+            match actor.run_with(mailbox).await {
+                Ok(()) => Ok(actor),
+                Err(error) if shared.is_stop_failed() => Err(RunError::Stop { actor, error }),
+                Err(error) => Err(RunError::Failed(error)),
+            }
         });
 
-        (actor_ref, ActorRun { future })
+        let run = ActorRun {
+            future,
+            shared: finished,
+            #[cfg(debug_assertions)]
+            polled: false,
+        };
+        (actor_ref, run)
+    }
+
+    /// Returns a handle that can be used to cooperatively abort the actor, see [`AbortHandle`].
+    /// Unlike aborting the task the actor was spawned on, this still runs [`Actor::on_stop`]
+    /// and resolves the [`ActorRun`] with the actor. The abort is recorded, see
+    /// [`ActorRef::is_aborted`].
+    pub fn abort_handle(&self) -> AbortHandle {
+        self.shared.abort.clone()
+    }
+
+    /// Runs the actor on the current task until its [`Actor::on_start`] has completed, and
+    /// returns the rest of the run, to be spawned as usual. This way setup in `on_start`, such
+    /// as registering the actor somewhere, is done before its [`ActorRef`] is handed to other
+    /// code. Fails with the error of `on_start` if it fails.
+    ///
+    /// If the actor already finished while starting, e.g. because `on_start` stopped it, the
+    /// returned [`ActorRun`] resolves right away with the outcome.
+    pub async fn run_until_started(mut self) -> Result<Self, A::Error> {
+        #[cfg(debug_assertions)]
+        {
+            self.polled = true;
+        }
+        let shared = self.shared.clone();
+        let future = &mut self.future;
+        // The actor is only polled here, so `on_start` can only complete during a poll.
+        let res = std::future::poll_fn(|cx| match future.as_mut().poll(cx) {
+            Poll::Ready(res) => Poll::Ready(Some(res)),
+            Poll::Pending if shared.is_started() => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        })
+        .await;
+        match res {
+            None => Ok(self),
+            Some(Err(error)) if !self.shared.is_started() => {
+                self.shared.set_finished(true);
+                Err(error.into_error())
+            }
+            Some(res) => {
+                self.future = Box::pin(std::future::ready(res));
+                Ok(self)
+            }
+        }
+    }
+
+    /// Maps the actor returned on success with `f`, e.g. to extract its final state at the spawn
+    /// site without an extra `async` block.
+    pub fn map_run<T, F: FnOnce(A) -> T>(self, f: F) -> MapRun<A, F> {
+        MapRun {
+            run: self,
+            f: Some(f),
+        }
+    }
+
+    /// Adds the message whose handler failed the actor to the error, for actors whose mailbox
+    /// was set up with [`Mailbox::set_traced`]. The message is [`None`] for other actors, and for
+    /// failures that were not caused by a handler, such as an error from [`Actor::on_start`].
+    #[allow(clippy::type_complexity)]
+    pub fn with_failed_message(
+        self,
+    ) -> MapErrRun<A, impl FnOnce(RunError<A>) -> HandlerError<RunError<A>> + Send> {
+        let shared = self.shared.clone();
+        self.map_err_run(move |error| HandlerError {
+            error,
+            message: shared.failed_message(),
+        })
+    }
+
+    /// Maps the error with `f`, so that actors with different error types can be awaited
+    /// together, e.g. with `try_join!`. Use [`RunError::into_error`] in `f` to get at the
+    /// actor's own error.
+    pub fn map_err_run<E, F: FnOnce(RunError<A>) -> E>(self, f: F) -> MapErrRun<A, F> {
+        MapErrRun {
+            run: self,
+            f: Some(f),
+        }
+    }
+}
+
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+/// Future returned by [`ActorRun::map_run`].
+pub struct MapRun<A: Actor, F> {
+    run: ActorRun<A>,
+    f: Option<F>,
+}
+
+impl<A: Actor, F> MapRun<A, F> {
+    /// Returns a handle to abort the actor, see [`ActorRun::abort_handle`].
+    pub fn abort_handle(&self) -> AbortHandle {
+        self.run.abort_handle()
+    }
+}
+
+// The closure is never polled in place, and `ActorRun` is boxed.
+impl<A: Actor, F> Unpin for MapRun<A, F> {}
+
+impl<A: Actor + Send + 'static, T, F: FnOnce(A) -> T> Future for MapRun<A, F> {
+    type Output = Result<T, RunError<A>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let res = std::task::ready!(Pin::new(&mut self.run).poll(cx));
+        let f = self.f.take().expect("`MapRun` polled after completion");
+        Poll::Ready(res.map(f))
+    }
+}
+
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+/// Future returned by [`ActorRun::map_err_run`].
+pub struct MapErrRun<A: Actor, F> {
+    run: ActorRun<A>,
+    f: Option<F>,
+}
+
+impl<A: Actor, F> MapErrRun<A, F> {
+    /// Returns a handle to abort the actor, see [`ActorRun::abort_handle`].
+    pub fn abort_handle(&self) -> AbortHandle {
+        self.run.abort_handle()
+    }
+}
+
+// The closure is never polled in place, and `ActorRun` is boxed.
+impl<A: Actor, F> Unpin for MapErrRun<A, F> {}
+
+impl<A: Actor + Send + 'static, E, F: FnOnce(RunError<A>) -> E> Future for MapErrRun<A, F> {
+    type Output = Result<A, E>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let res = std::task::ready!(Pin::new(&mut self.run).poll(cx));
+        let f = self.f.take().expect("`MapErrRun` polled after completion");
+        Poll::Ready(res.map_err(f))
     }
 }
 
 impl<A: Actor + Send + 'static> Future for ActorRun<A> {
-    type Output = Result<A, A::Error>;
+    type Output = Result<A, RunError<A>>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        self.future.as_mut().poll(cx)
+        #[cfg(debug_assertions)]
+        {
+            self.polled = true;
+        }
+        let res = std::task::ready!(self.future.as_mut().poll(cx));
+        self.shared
+            .set_finished(res.is_err() || self.shared.is_aborted());
+        Poll::Ready(res)
+    }
+}
+
+impl<A: Actor> ActorRun<A> {
+    /// Returns `true` if the actor has never been polled, in which case dropping the run warns
+    /// that the actor never ran.
+    #[cfg(debug_assertions)]
+    pub(crate) fn never_polled(&self) -> bool {
+        !self.polled
+    }
+}
+
+impl<A: Actor> Drop for ActorRun<A> {
+    /// Records the actor as aborted if it had not finished, and warns in debug builds if the
+    /// actor never ran, which usually means that the future returned by
+    /// [`Actor::into_future`] was never spawned.
+    fn drop(&mut self) {
+        // Dropping the future drops the mailbox, if the actor was still running.
+        self.future = Box::pin(std::future::pending());
+        if !self.shared.is_finished() {
+            self.shared.set_aborted();
+            self.shared.set_finished(true);
+        }
+        #[cfg(debug_assertions)]
+        if self.never_polled() && !std::thread::panicking() {
+            eprintln!(
+                "warning: an `ActorRun` for `{}` was dropped without being polled, so the actor \
+                 never ran; did you forget to spawn it?",
+                std::any::type_name::<A>()
+            );
+        }
     }
 }
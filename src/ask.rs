@@ -0,0 +1,461 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use async_oneshot_channel::{Receiver as OneshotReceiver, Recv, Sender as OneshotSender};
+use either::Either;
+
+use crate::{select::biased, Actor, ActorRef, TrySendError, WeakActorRef};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The ways in which a request made with [`ActorRef::ask`] can fail.
+pub enum AskError {
+    /// The request could not be delivered, because the actor's mailbox is closed.
+    Closed,
+    /// The actor dropped the reply sender without answering the request.
+    Dropped,
+    /// No reply arrived before the timeout elapsed.
+    Timeout,
+    /// The request was made through a [`WeakActorRef`] whose actor has been dropped.
+    ActorGone,
+}
+
+impl<A: Actor> ActorRef<A> {
+    /// Sends a request to the actor and waits for its reply.
+    ///
+    /// `make_msg` is given the sending half of a oneshot channel, and should wrap it into a
+    /// message that the actor answers by sending on it.
+    pub async fn ask<R>(
+        &self,
+        make_msg: impl FnOnce(OneshotSender<R>) -> A::Message,
+    ) -> Result<R, AskError> {
+        let (reply_sender, reply_receiver) = async_oneshot_channel::oneshot();
+        self.send(make_msg(reply_sender))
+            .await
+            .map_err(|_| AskError::Closed)?;
+        reply_receiver.recv().await.ok_or(AskError::Dropped)
+    }
+
+    /// Like [`ActorRef::ask`], but applies `f` to the reply, for callers that only need a
+    /// projection of it. `f` is not called if the request fails.
+    pub async fn ask_map<R, T>(
+        &self,
+        make_msg: impl FnOnce(OneshotSender<R>) -> A::Message,
+        f: impl FnOnce(R) -> T,
+    ) -> Result<T, AskError> {
+        self.ask(make_msg).await.map(f)
+    }
+
+    /// Sends a request that the actor answers with any number of progress updates followed by
+    /// a final reply, e.g. the percentage of a long upload and then its result. Once the request
+    /// is sent, returns the receiver of the updates and a future for the final reply.
+    ///
+    /// `make_msg` is given a [`ProgressSender`] to wrap into the message. The receiver ends once
+    /// the actor has sent the final reply or dropped the sender, and the future resolves to
+    /// [`None`] if the actor drops the sender without a final reply, or if the request could not
+    /// be delivered. Either half may be dropped early: the other keeps working, and the actor
+    /// can tell from [`ProgressSender::progress`] that nobody reads the updates anymore.
+    pub async fn ask_progress<P, R>(
+        &self,
+        make_msg: impl FnOnce(ProgressSender<P, R>) -> A::Message,
+    ) -> (
+        async_channel::Receiver<P>,
+        impl Future<Output = Option<R>> + Send + 'static,
+    )
+    where
+        P: Send + 'static,
+        R: Send + 'static,
+    {
+        let (progress, progress_receiver) = async_channel::unbounded();
+        let (reply, reply_receiver) = async_oneshot_channel::oneshot();
+        // On failure the message, and the sender in it, are dropped, which ends both halves.
+        let _ = self
+            .send(make_msg(ProgressSender { progress, reply }))
+            .await;
+        (
+            progress_receiver,
+            async move { reply_receiver.recv().await },
+        )
+    }
+
+    /// Like [`ActorRef::ask`], but checks for the reply once right after sending, without
+    /// waiting, and returns [`AskNow::Ready`] if the actor has already answered, e.g. because it
+    /// was idle on another thread and replied from a cache. Otherwise, it returns
+    /// [`AskNow::Pending`] with a future for the reply, so the caller only goes through the
+    /// scheduler when it has to.
+    pub async fn try_ask_now<R>(
+        &self,
+        make_msg: impl FnOnce(OneshotSender<R>) -> A::Message,
+    ) -> Result<AskNow<R>, AskError> {
+        let (reply_sender, receiver) = async_oneshot_channel::oneshot();
+        self.send(make_msg(reply_sender))
+            .await
+            .map_err(|_| AskError::Closed)?;
+        let mut recv = receiver.recv();
+        match Pin::new(&mut recv).poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(Some(reply)) => Ok(AskNow::Ready(reply)),
+            Poll::Ready(None) => Err(AskError::Dropped),
+            Poll::Pending => Ok(AskNow::Pending(PendingReply {
+                _receiver: receiver,
+                recv,
+            })),
+        }
+    }
+
+    /// Like [`ActorRef::ask`], but gives up with [`AskError::Timeout`] if no reply arrives within
+    /// `timeout`. As the crate is runtime-agnostic, `sleep_fn` must return a future that
+    /// completes after the given duration, such as `tokio::time::sleep`.
+    ///
+    /// On timeout the reply receiver is dropped, so a late answer from the actor is discarded.
+    /// The request itself is not withdrawn or retried: if it is still queued, the actor will
+    /// process it.
+    pub async fn ask_timeout<R, S: Future>(
+        &self,
+        make_msg: impl FnOnce(OneshotSender<R>) -> A::Message,
+        timeout: Duration,
+        sleep_fn: impl FnOnce(Duration) -> S,
+    ) -> Result<R, AskError> {
+        match biased(self.ask(make_msg), sleep_fn(timeout)).await {
+            Either::Left(res) => res,
+            Either::Right(_) => Err(AskError::Timeout),
+        }
+    }
+
+    /// Like [`ActorRef::ask`], but the actor answers through a [`Reply`], which tells it whether
+    /// the caller is still waiting. If the future returned by this method is dropped before the
+    /// reply arrives, e.g. because it lost a race against a timeout, [`Reply::is_cancelled`]
+    /// starts returning `true`, so the handler can skip expensive work for an answer nobody
+    /// will read.
+    ///
+    /// Cancellation is cooperative: the request is still delivered and handled, and it is up to
+    /// the handler to check the flag.
+    pub async fn ask_cancellable<R>(
+        &self,
+        make_msg: impl FnOnce(Reply<R>) -> A::Message,
+    ) -> Result<R, AskError> {
+        let cancelled = CancelOnDrop(Arc::new(AtomicBool::new(false)));
+        let reply = |sender| Reply {
+            sender,
+            cancelled: cancelled.0.clone(),
+            fallback: None,
+        };
+        self.ask(|sender| make_msg(reply(sender))).await
+    }
+
+    #[cfg(feature = "tokio")]
+    /// [`ActorRef::ask_timeout`] using `tokio::time::sleep` as the timer.
+    pub async fn ask_timeout_tokio<R>(
+        &self,
+        make_msg: impl FnOnce(OneshotSender<R>) -> A::Message,
+        timeout: Duration,
+    ) -> Result<R, AskError> {
+        self.ask_timeout(make_msg, timeout, tokio::time::sleep)
+            .await
+    }
+}
+
+impl<A: Actor> WeakActorRef<A> {
+    /// Sends a request to the actor and waits for its reply, see [`ActorRef::ask`]. Fails with
+    /// [`AskError::ActorGone`] if the actor has been dropped.
+    ///
+    /// The actor is only kept alive while the request is being sent, not while waiting for the
+    /// reply. An actor must not wait on a request to itself from within a handler, as it cannot
+    /// answer until the handler returns.
+    pub async fn ask<R>(
+        &self,
+        make_msg: impl FnOnce(OneshotSender<R>) -> A::Message,
+    ) -> Result<R, AskError> {
+        let (reply_sender, reply_receiver) = async_oneshot_channel::oneshot();
+        let actor_ref = self.upgrade().ok_or(AskError::ActorGone)?;
+        actor_ref
+            .send(make_msg(reply_sender))
+            .await
+            .map_err(|_| AskError::Closed)?;
+        drop(actor_ref);
+        reply_receiver.recv().await.ok_or(AskError::Dropped)
+    }
+}
+
+#[derive(Debug)]
+/// The outcome of [`ActorRef::try_ask_now`].
+pub enum AskNow<R> {
+    /// The actor had already answered.
+    Ready(R),
+    /// The reply has not arrived yet, and can be awaited.
+    Pending(PendingReply<R>),
+}
+
+#[derive(Debug)]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+/// A reply that has not arrived yet, see [`AskNow::Pending`]. Resolves to
+/// [`AskError::Dropped`] if the actor drops the reply sender without answering.
+pub struct PendingReply<R> {
+    /// Kept alive until the reply has resolved, as dropping it unregisters the waker.
+    _receiver: OneshotReceiver<R>,
+    recv: Recv<R>,
+}
+
+impl<R> Future for PendingReply<R> {
+    type Output = Result<R, AskError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.recv)
+            .poll(cx)
+            .map(|reply| reply.ok_or(AskError::Dropped))
+    }
+}
+
+#[derive(Debug)]
+/// The reply handle of a request made with [`ActorRef::ask_cancellable`].
+///
+/// Dropping a `Reply` without answering makes the request fail with [`AskError::Dropped`],
+/// unless a fallback answer has been set with [`Reply::set_fallback`].
+pub struct Reply<R> {
+    sender: OneshotSender<R>,
+    cancelled: Arc<AtomicBool>,
+    /// Sent on drop if no reply was sent before.
+    fallback: Option<R>,
+}
+
+impl<R> Reply<R> {
+    /// Answers the request, consuming the handle. If the caller is no longer waiting, the
+    /// answer is returned in [`Err`].
+    pub fn answer(self, answer: R) -> Result<(), R> {
+        self.send(answer)
+    }
+
+    /// Returns `true` while the caller is still waiting for the reply, see
+    /// [`Reply::is_cancelled`].
+    pub fn is_waiting(&self) -> bool {
+        !self.is_cancelled()
+    }
+
+    /// Sets an answer to send if the handle is dropped without replying, e.g. a default value
+    /// or [`None`] for a handler that returns early on an error.
+    pub fn set_fallback(&mut self, fallback: R) {
+        self.fallback = Some(fallback);
+    }
+
+    /// Sends the reply to the caller. If the caller is no longer waiting, or a reply has already
+    /// been sent, the reply is returned in [`Err`].
+    pub fn send(&self, reply: R) -> Result<(), R> {
+        if self.is_cancelled() {
+            return Err(reply);
+        }
+        self.sender.send(reply)
+    }
+
+    /// Returns `true` once the caller has stopped waiting for the reply.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Returns a handle to the request's cancellation flag, which stays usable after the reply
+    /// is sent or moved, e.g. to let work offloaded from the handler check it too.
+    pub fn cancellation(&self) -> Cancellation {
+        Cancellation(self.cancelled.clone())
+    }
+}
+
+#[derive(Debug)]
+/// The reply handle of a request made with [`ActorRef::ask_progress`], through which the actor
+/// sends progress updates of type `P` and then a final reply of type `R`.
+pub struct ProgressSender<P, R> {
+    progress: async_channel::Sender<P>,
+    reply: OneshotSender<R>,
+}
+
+impl<P, R> ProgressSender<P, R> {
+    /// Sends a progress update without waiting. If the caller dropped the receiver of the
+    /// updates, the update is returned in [`Err`], but the final reply can still be sent.
+    pub fn progress(&self, update: P) -> Result<(), P> {
+        self.progress
+            .try_send(update)
+            .map_err(|err| err.into_inner())
+    }
+
+    /// Sends the final reply, consuming the handle, which ends the caller's receiver of updates.
+    /// If the caller is no longer waiting for the reply, it is returned in [`Err`].
+    pub fn finish(self, reply: R) -> Result<(), R> {
+        self.progress.close();
+        self.reply.send(reply)
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Tells whether the caller of [`ActorRef::ask_cancellable`] is still waiting for a reply, see
+/// [`Reply::cancellation`].
+///
+/// Cancellation is cooperative: nothing is interrupted when the flag is set, so long-running
+/// work should check [`Cancellation::is_cancelled`] at convenient points and bail out.
+pub struct Cancellation(Arc<AtomicBool>);
+
+impl Cancellation {
+    /// Returns `true` once the caller has stopped waiting for the reply.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+impl<R> Drop for Reply<R> {
+    fn drop(&mut self) {
+        if let Some(fallback) = self.fallback.take() {
+            // Fails if a reply was already sent, which is fine.
+            let _ = self.send(fallback);
+        }
+    }
+}
+
+/// Marks a request as cancelled when the caller's future is dropped.
+struct CancelOnDrop(Arc<AtomicBool>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Release);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Returned by [`ask_any`] if no actor answered, because every request either could not be
+/// delivered or was dropped without a reply.
+pub struct AllFailed;
+
+/// Sends the same request to every actor in `refs`, e.g. replicas of a service, and resolves to
+/// the first reply along with the index of the actor that sent it. This is the hedged request
+/// pattern, which trades extra work for lower tail latency.
+///
+/// Each actor answers through a [`Reply`], like with [`ActorRef::ask_cancellable`]. Once the
+/// first reply arrives, the other requests are cancelled, so their handlers can check
+/// [`Reply::is_cancelled`] and skip the work. Requests go to every actor with room in its
+/// mailbox without waiting, so that a replica with a full mailbox does not hold up the others;
+/// replicas that are full are skipped. Only if every replica that is still running is full does
+/// this wait, for the first one with room.
+pub async fn ask_any<A: Actor, R>(
+    refs: &[ActorRef<A>],
+    make_msg: impl Fn(Reply<R>) -> A::Message,
+) -> Result<(usize, R), AllFailed> {
+    let mut requests = Vec::with_capacity(refs.len());
+    let mut full = Vec::new();
+    for (index, actor_ref) in refs.iter().enumerate() {
+        let (sender, receiver) = async_oneshot_channel::oneshot();
+        let cancelled = CancelOnDrop(Arc::new(AtomicBool::new(false)));
+        let reply = Reply {
+            sender,
+            cancelled: cancelled.0.clone(),
+            fallback: None,
+        };
+        match actor_ref.try_send(make_msg(reply)) {
+            Ok(()) => requests.push((index, receiver, cancelled)),
+            Err(TrySendError::Full(msg)) => full.push((index, msg, receiver, cancelled)),
+            Err(TrySendError::Closed(_) | TrySendError::Shed(_)) => {}
+        }
+    }
+    if requests.is_empty() {
+        // Dropping the sends that are still waiting drops their requests along with them.
+        let mut sends: Vec<_> = full
+            .into_iter()
+            .map(|(index, msg, receiver, cancelled)| {
+                let send = refs[index].send(msg);
+                Some(Box::pin(async move {
+                    send.await.ok().map(|()| (index, receiver, cancelled))
+                }))
+            })
+            .collect();
+        let sent = std::future::poll_fn(|cx| {
+            for pending in sends.iter_mut() {
+                let Some(send) = pending else { continue };
+                if let Poll::Ready(res) = send.as_mut().poll(cx) {
+                    *pending = None;
+                    if res.is_some() {
+                        return Poll::Ready(res);
+                    }
+                }
+            }
+            if sends.iter().all(Option::is_none) {
+                Poll::Ready(None)
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+        requests.extend(sent);
+    }
+
+    // Dropping `requests` on return cancels every request that is still waiting.
+    let mut pending: Vec<_> = requests.iter().map(|(_, r, _)| Some(r.recv())).collect();
+    std::future::poll_fn(|cx| {
+        for (pending, (index, _, _)) in pending.iter_mut().zip(&requests) {
+            let Some(recv) = pending else { continue };
+            if let Poll::Ready(res) = Pin::new(recv).poll(cx) {
+                *pending = None;
+                if let Some(reply) = res {
+                    return Poll::Ready(Ok((*index, reply)));
+                }
+            }
+        }
+        if pending.iter().all(Option::is_none) {
+            Poll::Ready(Err(AllFailed))
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+/// Waits for every reply in `receivers`, e.g. from requests sent to many actors, and resolves
+/// to the replies in the same order. A reply is [`None`] if its sender was dropped without
+/// answering, so the future does not hang on actors that stopped or never reply.
+pub fn join_replies<R>(receivers: Vec<OneshotReceiver<R>>) -> JoinReplies<R> {
+    let pending = receivers.iter().map(|r| Some(r.recv())).collect();
+    let replies = receivers.iter().map(|_| None).collect();
+    JoinReplies {
+        _receivers: receivers,
+        pending,
+        replies,
+    }
+}
+
+#[derive(Debug)]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+/// Future returned by [`join_replies`].
+pub struct JoinReplies<R> {
+    /// Kept alive until every reply has resolved, as dropping a receiver unregisters its waker.
+    _receivers: Vec<OneshotReceiver<R>>,
+    pending: Vec<Option<Recv<R>>>,
+    replies: Vec<Option<R>>,
+}
+
+// Nothing is polled in place: the `Recv` futures are `Unpin`, and the replies are only moved.
+impl<R> Unpin for JoinReplies<R> {}
+
+impl<R> Future for JoinReplies<R> {
+    type Output = Vec<Option<R>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut done = true;
+        for (pending, reply) in this.pending.iter_mut().zip(&mut this.replies) {
+            if let Some(recv) = pending {
+                match Pin::new(recv).poll(cx) {
+                    Poll::Ready(res) => {
+                        *reply = res;
+                        *pending = None;
+                    }
+                    Poll::Pending => done = false,
+                }
+            }
+        }
+        if done {
+            Poll::Ready(std::mem::take(&mut this.replies))
+        } else {
+            Poll::Pending
+        }
+    }
+}
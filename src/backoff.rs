@@ -0,0 +1,180 @@
+use std::{
+    collections::hash_map::RandomState,
+    future::Future,
+    hash::BuildHasher,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use crate::{Actor, ActorRun, RunError};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The restart timing of a [`BackoffSupervisor`].
+pub struct Backoff {
+    /// The delay before the first restart.
+    pub base: Duration,
+    /// The longest delay between restarts.
+    pub max: Duration,
+    /// The factor by which the delay grows after each restart.
+    pub multiplier: f64,
+    /// The fraction of each delay, between 0 and 1, that is randomly taken off, so that actors
+    /// failing together don't restart in lockstep.
+    pub jitter: f64,
+    /// How long the actor has to run before the delay is reset to `base`.
+    pub reset_after: Duration,
+    /// The number of restarts after which the supervisor gives up, or [`None`] to keep
+    /// restarting.
+    pub max_restarts: Option<u32>,
+}
+
+impl Backoff {
+    /// Creates a backoff from `base` to `max` that doubles the delay after each restart,
+    /// without jitter, and resets once the actor has run for `max`.
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Self {
+            base,
+            max,
+            multiplier: 2.0,
+            jitter: 0.0,
+            reset_after: max,
+            max_restarts: None,
+        }
+    }
+
+    /// Sets the factor by which the delay grows after each restart.
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// Sets the fraction of each delay that is randomly taken off.
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets how long the actor has to run before the delay is reset.
+    pub fn with_reset_after(mut self, reset_after: Duration) -> Self {
+        self.reset_after = reset_after;
+        self
+    }
+
+    /// Sets the number of restarts after which the supervisor gives up.
+    pub fn with_max_restarts(mut self, max_restarts: u32) -> Self {
+        self.max_restarts = Some(max_restarts);
+        self
+    }
+
+    fn grow(&self, delay: Duration) -> Duration {
+        delay.mul_f64(self.multiplier).min(self.max)
+    }
+
+    fn jittered(&self, delay: Duration) -> Duration {
+        if self.jitter == 0.0 {
+            return delay;
+        }
+        // A fresh `RandomState` is randomly seeded, which is enough randomness for jitter.
+        let random = RandomState::new().hash_one(Instant::now()) as f64 / u64::MAX as f64;
+        delay.mul_f64(1.0 - self.jitter * random)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Status {
+    restarts: u32,
+    backoff: Duration,
+}
+
+#[derive(Debug, Clone)]
+/// A handle for observing a [`BackoffSupervisor`], created with [`BackoffSupervisor::status`].
+pub struct BackoffStatus(Arc<Mutex<Status>>);
+
+impl BackoffStatus {
+    /// Returns the number of times the actor has been restarted.
+    pub fn restarts(&self) -> u32 {
+        self.0.lock().unwrap().restarts
+    }
+
+    /// Returns the delay before the next restart, before jitter is applied.
+    pub fn current_backoff(&self) -> Duration {
+        self.0.lock().unwrap().backoff
+    }
+}
+
+/// A future that keeps an actor running, restarting it with exponential backoff whenever it
+/// fails.
+///
+/// Each run of the actor is created by a factory function, as a failed actor's state is gone;
+/// the factory is also where the new [`ActorRef`](crate::ActorRef) should be published, e.g. into
+/// a shared slot, as every run has its own mailbox. A run that fails with [`RunError::Failed`]
+/// is restarted after the current delay, which then grows by the backoff's multiplier up to its
+/// maximum. A run that stops, including with a [`RunError::Stop`] cleanup error, is not
+/// restarted, and the supervisor resolves with its result.
+///
+/// As the crate is runtime-agnostic, `sleep_fn` must return a future that completes after the
+/// given duration, such as `tokio::time::sleep`.
+pub struct BackoffSupervisor<A: Actor> {
+    future: Pin<Box<dyn Future<Output = Result<A, RunError<A>>> + Send>>,
+    status: BackoffStatus,
+}
+
+impl<A: Actor> BackoffSupervisor<A> {
+    /// Creates a supervisor that runs the actors created by `factory` with the given `backoff`.
+    pub fn new<S>(
+        mut factory: impl FnMut() -> ActorRun<A> + Send + 'static,
+        backoff: Backoff,
+        sleep_fn: impl Fn(Duration) -> S + Send + 'static,
+    ) -> Self
+    where
+        S: Future + Send,
+    {
+        let status = BackoffStatus(Arc::new(Mutex::new(Status {
+            restarts: 0,
+            backoff: backoff.base,
+        })));
+        let shared = status.clone();
+        let future = Box::pin(async move {
+            loop {
+                let started = Instant::now();
+                let err = match factory().await {
+                    Err(RunError::Failed(err)) => err,
+                    res => return res,
+                };
+                let delay = {
+                    let mut status = shared.0.lock().unwrap();
+                    if backoff
+                        .max_restarts
+                        .is_some_and(|max| status.restarts >= max)
+                    {
+                        return Err(RunError::Failed(err));
+                    }
+                    if started.elapsed() >= backoff.reset_after {
+                        status.backoff = backoff.base;
+                    }
+                    let delay = status.backoff;
+                    status.backoff = backoff.grow(delay);
+                    status.restarts += 1;
+                    delay
+                };
+                sleep_fn(backoff.jittered(delay)).await;
+            }
+        });
+        Self { future, status }
+    }
+
+    /// Returns a handle for observing the restart count and current backoff, which stays
+    /// usable after the supervisor has been spawned.
+    pub fn status(&self) -> BackoffStatus {
+        self.status.clone()
+    }
+}
+
+impl<A: Actor> Future for BackoffSupervisor<A> {
+    type Output = Result<A, RunError<A>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.future.as_mut().poll(cx)
+    }
+}
@@ -0,0 +1,210 @@
+use std::{
+    convert::Infallible,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::{Actor, ActorRef, TrySendError, WeakActorRef};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Identifies a subscription to a [`Bus`].
+pub struct SubscriberId(u64);
+
+impl SubscriberId {
+    /// Returns a new, process-wide unique subscriber id.
+    pub fn new() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl Default for SubscriberId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A weak, type-erased handle to an actor that accepts messages of type `M`, used as a
+/// subscriber of a [`Bus`].
+pub struct Recipient<M> {
+    deliver: Box<dyn Fn(M) -> Result<(), TrySendError<()>> + Send + Sync>,
+}
+
+impl<M> Recipient<M> {
+    /// Creates a recipient that delivers to `actor`, converting each message into the actor's
+    /// message type. Delivery never waits: a message that doesn't fit in the actor's mailbox is
+    /// dropped for that actor.
+    pub fn new<A: Actor>(actor: WeakActorRef<A>) -> Self
+    where
+        M: Into<A::Message>,
+    {
+        Self {
+            deliver: Box::new(move |msg| {
+                actor.try_send(msg.into()).map_err(|e| match e {
+                    TrySendError::Full(_) | TrySendError::Shed(_) => TrySendError::Full(()),
+                    TrySendError::Closed(_) => TrySendError::Closed(()),
+                })
+            }),
+        }
+    }
+
+    fn try_send(&self, msg: M) -> Result<(), TrySendError<()>> {
+        (self.deliver)(msg)
+    }
+}
+
+impl<M> std::fmt::Debug for Recipient<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Recipient").finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+/// Messages understood by a [`Bus`].
+pub enum BusMessage<M> {
+    /// Registers a subscriber under the given id.
+    Subscribe(SubscriberId, Recipient<M>),
+    /// Removes the subscriber with the given id, if it is still registered.
+    Unsubscribe(SubscriberId),
+    /// Sends a clone of the message to every subscriber.
+    Publish(M),
+}
+
+#[derive(Debug)]
+/// A publish/subscribe actor that fans out every published message to all of its subscribers.
+///
+/// Subscribers are held weakly, so subscribing doesn't keep an actor alive, and subscribers that
+/// have stopped are pruned the next time a message is published. Messages are delivered with
+/// [`WeakActorRef::try_send`], so a slow subscriber with a full mailbox misses the message
+/// instead of blocking the bus.
+pub struct Bus<M> {
+    subscribers: Vec<(SubscriberId, Recipient<M>)>,
+}
+
+impl<M> Bus<M> {
+    /// Creates a bus with no subscribers.
+    pub fn new() -> Self {
+        Self {
+            subscribers: Vec::new(),
+        }
+    }
+
+    /// Returns the number of registered subscribers.
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.len()
+    }
+}
+
+impl<M> Default for Bus<M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M: Clone + Send + 'static> Actor for Bus<M> {
+    type Error = Infallible;
+    type Message = BusMessage<M>;
+
+    async fn on_msg(
+        &mut self,
+        _: &WeakActorRef<Self>,
+        msg: Self::Message,
+    ) -> Result<(), Self::Error> {
+        match msg {
+            BusMessage::Subscribe(id, recipient) => self.subscribers.push((id, recipient)),
+            BusMessage::Unsubscribe(id) => self.subscribers.retain(|(sub, _)| *sub != id),
+            BusMessage::Publish(msg) => self.subscribers.retain(|(_, recipient)| {
+                !matches!(
+                    recipient.try_send(msg.clone()),
+                    Err(TrySendError::Closed(()))
+                )
+            }),
+        }
+        Ok(())
+    }
+}
+
+impl<M: Clone + Send + 'static> ActorRef<Bus<M>> {
+    /// Subscribes `actor` to the bus, returning the id to unsubscribe it with. If the bus has
+    /// stopped, this returns [`None`].
+    pub async fn subscribe<A: Actor>(&self, actor: WeakActorRef<A>) -> Option<SubscriberId>
+    where
+        M: Into<A::Message>,
+    {
+        let id = SubscriberId::new();
+        self.send(BusMessage::Subscribe(id, Recipient::new(actor)))
+            .await
+            .ok()?;
+        Some(id)
+    }
+
+    /// Like [`ActorRef::subscribe`], but returns a [`Subscription`] that unsubscribes `actor`
+    /// when dropped. If the bus has stopped, this returns [`None`].
+    pub async fn subscription<A: Actor>(&self, actor: WeakActorRef<A>) -> Option<Subscription<M>>
+    where
+        M: Into<A::Message>,
+    {
+        let id = self.subscribe(actor).await?;
+        Some(Subscription {
+            bus: self.downgrade(),
+            id,
+        })
+    }
+
+    /// Unsubscribes the subscriber with the given id. Unsubscribing an id that is not registered
+    /// has no effect.
+    pub async fn unsubscribe(&self, id: SubscriberId) -> Result<(), BusMessage<M>> {
+        self.send(BusMessage::Unsubscribe(id)).await
+    }
+
+    /// Publishes `msg` to every subscriber of the bus. If the bus has stopped, the message is
+    /// returned in [`Err`].
+    pub async fn publish(&self, msg: M) -> Result<(), M> {
+        self.send(BusMessage::Publish(msg))
+            .await
+            .map_err(|e| match e {
+                BusMessage::Publish(msg) => msg,
+                _ => unreachable!(),
+            })
+    }
+}
+
+/// A subscription to a [`Bus`], returned by [`ActorRef::subscription`], that unsubscribes when
+/// dropped.
+///
+/// Only a weak reference to the bus is held, so a subscription doesn't keep the bus alive.
+/// Unsubscribing on drop is best-effort: the request is sent without waiting, and is lost if the
+/// bus has stopped or its mailbox is full. In the latter case the subscriber stays registered
+/// until it stops and is pruned.
+pub struct Subscription<M: Clone + Send + 'static> {
+    bus: WeakActorRef<Bus<M>>,
+    id: SubscriberId,
+}
+
+impl<M: Clone + Send + 'static> Subscription<M> {
+    /// Returns the id of the subscriber.
+    pub fn id(&self) -> SubscriberId {
+        self.id
+    }
+
+    /// Consumes the subscription without unsubscribing, returning the id to unsubscribe with
+    /// [`ActorRef::unsubscribe`] instead.
+    pub fn detach(self) -> SubscriberId {
+        let id = self.id;
+        std::mem::forget(self);
+        id
+    }
+}
+
+impl<M: Clone + Send + 'static> Drop for Subscription<M> {
+    fn drop(&mut self) {
+        let _ = self.bus.try_send(BusMessage::Unsubscribe(self.id));
+    }
+}
+
+impl<M: Clone + Send + 'static> std::fmt::Debug for Subscription<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Subscription")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
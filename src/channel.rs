@@ -0,0 +1,428 @@
+//! The message channel underlying a [`Mailbox`](crate::Mailbox), which is either a plain
+//! `async_channel` or a [`queue`](crate::queue) with custom ordering.
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering::SeqCst},
+    Arc, Mutex,
+};
+
+use async_channel::{RecvError, SendError, TryRecvError, TrySendError};
+use event_listener::Event;
+
+use crate::{mailbox::Packet, queue};
+
+/// Slot reservations of a bounded `async_channel`, see [`Sender::try_reserve`].
+///
+/// While no slot is reserved, sends go straight to the channel. Such a send is counted in
+/// `sending` before it checks `reserved`, and a reservation is counted in `reserved` before it
+/// checks `sending`, so either the send sees the reservation and checks for room under the lock
+/// instead, or the reservation leaves room for the send. A reserved slot therefore stays free
+/// until it is used or released.
+#[derive(Debug, Default)]
+pub(crate) struct Gate {
+    /// Number of reserved slots.
+    reserved: AtomicUsize,
+    /// Number of sends going straight to the channel that have not finished yet.
+    sending: AtomicUsize,
+    /// Held while granting or using a reservation, and by sends checking for room.
+    lock: Mutex<()>,
+    /// Notified when a message is received, a slot is given up, or the channel closes.
+    space: Event,
+}
+
+impl Gate {
+    /// Starts a send that goes straight to the channel, or returns [`None`] if a slot is
+    /// reserved, in which case the send has to check for room under the lock.
+    fn start_send<M>(&self, sender: &async_channel::Sender<M>) -> Option<Sending<'_>> {
+        if sender.capacity().is_none() {
+            return Some(Sending(None));
+        }
+        self.sending.fetch_add(1, SeqCst);
+        let sending = Sending(Some(self));
+        (self.reserved.load(SeqCst) == 0).then_some(sending)
+    }
+
+    /// Returns the number of slots taken beyond the channel's length, to be checked under the
+    /// lock.
+    fn taken(&self) -> usize {
+        self.reserved.load(SeqCst) + self.sending.load(SeqCst)
+    }
+}
+
+/// Counts a send to a bounded channel in [`Gate::sending`] until it finishes or gives up.
+struct Sending<'a>(Option<&'a Gate>);
+
+impl Drop for Sending<'_> {
+    fn drop(&mut self) {
+        if let Some(gate) = self.0 {
+            gate.sending.fetch_sub(1, SeqCst);
+            gate.space.notify(1);
+        }
+    }
+}
+
+pub(crate) enum Sender<M> {
+    Channel(async_channel::Sender<Packet<M>>, Arc<Gate>),
+    Queue(queue::Sender<M>),
+}
+
+impl<M> Sender<M> {
+    /// Sends a packet, or a plain message without any delivery tracking, waiting for room.
+    pub(crate) async fn send(
+        &self,
+        packet: impl Into<Packet<M>>,
+    ) -> Result<(), SendError<Packet<M>>> {
+        let mut packet = packet.into();
+        match self {
+            Self::Channel(sender, gate) => {
+                if let Some(_sending) = gate.start_send(sender) {
+                    return sender.send(packet).await;
+                }
+                loop {
+                    match self.try_send(packet) {
+                        Ok(()) => return Ok(()),
+                        Err(TrySendError::Closed(p)) => return Err(SendError(p)),
+                        Err(TrySendError::Full(p)) => packet = p,
+                    }
+                    let listener = gate.space.listen();
+                    match self.try_send(packet) {
+                        Ok(()) => return Ok(()),
+                        Err(TrySendError::Closed(p)) => return Err(SendError(p)),
+                        Err(TrySendError::Full(p)) => packet = p,
+                    }
+                    listener.await;
+                }
+            }
+            Self::Queue(sender) => sender.send(packet).await,
+        }
+    }
+
+    /// Sends a packet, or a plain message, if there is room, see [`Sender::send`].
+    pub(crate) fn try_send(
+        &self,
+        packet: impl Into<Packet<M>>,
+    ) -> Result<(), TrySendError<Packet<M>>> {
+        let packet = packet.into();
+        match self {
+            Self::Channel(sender, gate) => {
+                if let Some(_sending) = gate.start_send(sender) {
+                    return sender.try_send(packet);
+                }
+                let _lock = gate.lock.lock().unwrap();
+                let full = sender
+                    .capacity()
+                    .is_some_and(|cap| sender.len() + gate.taken() >= cap);
+                if full && !sender.is_closed() {
+                    return Err(TrySendError::Full(packet));
+                }
+                sender.try_send(packet)
+            }
+            Self::Queue(sender) => sender.try_send(packet),
+        }
+    }
+
+    /// Reserves a slot for a message to be sent with [`Sender::send_reserved`], failing if the
+    /// channel is full or closed. Reservations on unbounded channels always succeed.
+    pub(crate) fn try_reserve(&self) -> Result<(), TrySendError<()>> {
+        match self {
+            Self::Channel(sender, gate) => {
+                let _lock = gate.lock.lock().unwrap();
+                if sender.is_closed() {
+                    return Err(TrySendError::Closed(()));
+                }
+                if let Some(cap) = sender.capacity() {
+                    gate.reserved.fetch_add(1, SeqCst);
+                    if sender.len() + gate.taken() > cap {
+                        gate.reserved.fetch_sub(1, SeqCst);
+                        return Err(TrySendError::Full(()));
+                    }
+                }
+                Ok(())
+            }
+            Self::Queue(sender) => sender.try_reserve(),
+        }
+    }
+
+    /// Waits until a slot can be reserved, see [`Sender::try_reserve`].
+    pub(crate) async fn reserve(&self) -> Result<(), SendError<()>> {
+        let space = match self {
+            Self::Channel(_, gate) => &gate.space,
+            Self::Queue(sender) => sender.send_ops(),
+        };
+        loop {
+            match self.try_reserve() {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Closed(())) => return Err(SendError(())),
+                Err(TrySendError::Full(())) => {}
+            }
+            let listener = space.listen();
+            match self.try_reserve() {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Closed(())) => return Err(SendError(())),
+                Err(TrySendError::Full(())) => {}
+            }
+            listener.await;
+        }
+    }
+
+    /// Returns whether a packet would fit without waiting, or [`Err`] if the channel is closed.
+    fn has_room(&self) -> Result<bool, ()> {
+        match self {
+            Self::Channel(sender, gate) => {
+                let _lock = gate.lock.lock().unwrap();
+                if sender.is_closed() {
+                    return Err(());
+                }
+                Ok(sender
+                    .capacity()
+                    .is_none_or(|cap| sender.len() + gate.taken() < cap))
+            }
+            Self::Queue(sender) => sender.has_room(),
+        }
+    }
+
+    /// Waits until a packet would fit without waiting, without reserving the slot.
+    ///
+    /// A freed slot only wakes one waiter, which may be this one rather than a blocked send, so
+    /// the wakeup is passed on once room is found, as the slot is left for someone else to use.
+    pub(crate) async fn wait_for_room(&self) -> Result<(), SendError<()>> {
+        let space = match self {
+            Self::Channel(_, gate) => &gate.space,
+            Self::Queue(sender) => sender.send_ops(),
+        };
+        let mut woken = false;
+        loop {
+            match self.has_room() {
+                Ok(true) => break,
+                Err(()) => return Err(SendError(())),
+                Ok(false) => {}
+            }
+            let listener = space.listen();
+            match self.has_room() {
+                Ok(true) => break,
+                Err(()) => return Err(SendError(())),
+                Ok(false) => {}
+            }
+            listener.await;
+            woken = true;
+        }
+        if woken {
+            space.notify(1);
+        }
+        Ok(())
+    }
+
+    /// Sends a packet into a slot reserved with [`Sender::try_reserve`], which never waits. The
+    /// packet is only returned if the channel has been closed since.
+    pub(crate) fn send_reserved(&self, packet: Packet<M>) -> Result<(), Packet<M>> {
+        match self {
+            Self::Channel(sender, gate) => {
+                let _lock = gate.lock.lock().unwrap();
+                let sent = sender.try_send(packet).map_err(TrySendError::into_inner);
+                if sender.capacity().is_some() {
+                    gate.reserved.fetch_sub(1, SeqCst);
+                }
+                sent
+            }
+            Self::Queue(sender) => sender.send_reserved(packet),
+        }
+    }
+
+    /// Gives up a slot reserved with [`Sender::try_reserve`] without sending.
+    pub(crate) fn release(&self) {
+        match self {
+            Self::Channel(sender, gate) => {
+                if sender.capacity().is_some() {
+                    gate.reserved.fetch_sub(1, SeqCst);
+                    gate.space.notify(1);
+                }
+            }
+            Self::Queue(sender) => sender.release(),
+        }
+    }
+
+    pub(crate) fn close(&self) -> bool {
+        match self {
+            Self::Channel(sender, gate) => {
+                let closed = sender.close();
+                gate.space.notify(usize::MAX);
+                closed
+            }
+            Self::Queue(sender) => sender.close(),
+        }
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        match self {
+            Self::Channel(sender, _) => sender.is_closed(),
+            Self::Queue(sender) => sender.is_closed(),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Self::Channel(sender, _) => sender.len(),
+            Self::Queue(sender) => sender.len(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Clones the queued messages, or returns [`None`] for the FIFO backend, which cannot be
+    /// inspected without receiving from it.
+    pub(crate) fn snapshot(&self) -> Option<Vec<M>>
+    where
+        M: Clone,
+    {
+        match self {
+            Self::Channel(..) => None,
+            Self::Queue(sender) => Some(sender.snapshot()),
+        }
+    }
+
+    pub(crate) fn capacity(&self) -> Option<usize> {
+        match self {
+            Self::Channel(sender, _) => sender.capacity(),
+            Self::Queue(sender) => sender.capacity(),
+        }
+    }
+
+    pub(crate) fn downgrade(&self) -> WeakSender<M> {
+        match self {
+            Self::Channel(sender, gate) => WeakSender::Channel(sender.downgrade(), gate.clone()),
+            Self::Queue(sender) => WeakSender::Queue(sender.downgrade()),
+        }
+    }
+}
+
+impl<M> Clone for Sender<M> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Channel(sender, gate) => Self::Channel(sender.clone(), gate.clone()),
+            Self::Queue(sender) => Self::Queue(sender.clone()),
+        }
+    }
+}
+
+impl<M> std::fmt::Debug for Sender<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sender")
+            .field("len", &self.len())
+            .field("capacity", &self.capacity())
+            .finish()
+    }
+}
+
+pub(crate) enum WeakSender<M> {
+    Channel(async_channel::WeakSender<Packet<M>>, Arc<Gate>),
+    Queue(queue::WeakSender<M>),
+}
+
+impl<M> WeakSender<M> {
+    pub(crate) fn upgrade(&self) -> Option<Sender<M>> {
+        match self {
+            Self::Channel(sender, gate) => sender
+                .upgrade()
+                .map(|sender| Sender::Channel(sender, gate.clone())),
+            Self::Queue(sender) => sender.upgrade().map(Sender::Queue),
+        }
+    }
+}
+
+impl<M> Clone for WeakSender<M> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Channel(sender, gate) => Self::Channel(sender.clone(), gate.clone()),
+            Self::Queue(sender) => Self::Queue(sender.clone()),
+        }
+    }
+}
+
+impl<M> std::fmt::Debug for WeakSender<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WeakSender").finish_non_exhaustive()
+    }
+}
+
+pub(crate) enum Receiver<M> {
+    Channel(async_channel::Receiver<Packet<M>>, Arc<Gate>),
+    Queue(queue::Receiver<M>),
+}
+
+impl<M> Receiver<M> {
+    pub(crate) async fn recv(&self) -> Result<Packet<M>, RecvError> {
+        match self {
+            Self::Channel(receiver, gate) => {
+                let packet = receiver.recv().await?;
+                gate.space.notify(1);
+                Ok(packet)
+            }
+            Self::Queue(receiver) => receiver.recv().await,
+        }
+    }
+
+    pub(crate) fn try_recv(&self) -> Result<Packet<M>, TryRecvError> {
+        match self {
+            Self::Channel(receiver, gate) => {
+                let packet = receiver.try_recv()?;
+                gate.space.notify(1);
+                Ok(packet)
+            }
+            Self::Queue(receiver) => receiver.try_recv(),
+        }
+    }
+
+    pub(crate) fn close(&self) -> bool {
+        match self {
+            Self::Channel(receiver, gate) => {
+                let closed = receiver.close();
+                gate.space.notify(usize::MAX);
+                closed
+            }
+            Self::Queue(receiver) => receiver.close(),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Self::Channel(receiver, _) => receiver.len(),
+            Self::Queue(receiver) => receiver.len(),
+        }
+    }
+}
+
+/// Panics if `size` is [`None`] and the `bounded-only` feature is enabled, see
+/// [`Mailbox::new`](crate::Mailbox::new).
+pub(crate) fn require_bounded(size: Option<usize>) {
+    if cfg!(feature = "bounded-only") && size.is_none() {
+        panic!(
+            "unbounded mailboxes are forbidden by the `bounded-only` feature, pass `Some(size)`"
+        );
+    }
+}
+
+/// Creates a FIFO channel backed by `async_channel`, bounded if `size` is given.
+pub(crate) fn fifo<M>(size: Option<usize>) -> (Sender<M>, Receiver<M>) {
+    require_bounded(size);
+    let (sender, receiver) = match size {
+        Some(size) => async_channel::bounded(size),
+        None => async_channel::unbounded(),
+    };
+    let gate = Arc::new(Gate::default());
+    (
+        Sender::Channel(sender, gate.clone()),
+        Receiver::Channel(receiver, gate),
+    )
+}
+
+/// Creates a queue-backed channel with custom message ordering, bounded if `size` is given.
+pub(crate) fn ordered<M>(
+    size: Option<usize>,
+    order: impl queue::Order<M> + 'static,
+) -> (Sender<M>, Receiver<M>) {
+    require_bounded(size);
+    let (sender, receiver) = queue::queue(size, order);
+    (Sender::Queue(sender), Receiver::Queue(receiver))
+}
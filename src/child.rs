@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use crate::{shared::Shared, Actor, ActorRef, ActorRun, WeakActorRef};
+
+/// A child registered with its parent with [`WeakActorRef::spawn_child`], type-erased so that a
+/// parent can own children of any actor type.
+pub(crate) struct Child {
+    shared: Arc<Shared>,
+    /// Closes the child's mailbox, so that it stops once it has drained its queued messages.
+    close: Box<dyn Fn() + Send + Sync>,
+}
+
+impl Child {
+    /// Registers the actor behind `actor_ref` without keeping it alive.
+    fn of<C: Actor>(actor_ref: &ActorRef<C>) -> Self {
+        let sender = actor_ref.sender.downgrade();
+        Self {
+            shared: actor_ref.weak.shared.clone(),
+            close: Box::new(move || {
+                if let Some(sender) = sender.upgrade() {
+                    sender.close();
+                }
+            }),
+        }
+    }
+
+    pub(crate) fn stop(&self) {
+        (self.close)();
+    }
+
+    pub(crate) fn is_exited(&self) -> bool {
+        self.shared.is_exited()
+    }
+}
+
+impl std::fmt::Debug for Child {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Child")
+            .field("exited", &self.is_exited())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A handle to a child actor, returned by [`WeakActorRef::spawn_child`]. The child is stopped
+/// automatically when its parent stops, see there for details.
+pub struct ChildHandle<C: Actor> {
+    actor_ref: ActorRef<C>,
+}
+
+impl<C: Actor> ChildHandle<C> {
+    /// Returns a reference to the child actor.
+    pub fn actor_ref(&self) -> &ActorRef<C> {
+        &self.actor_ref
+    }
+
+    /// Waits until the child actor has stopped running. A parent can call this from its
+    /// [`Actor::on_stop`] to wait for the child to finish, as children are stopped before the
+    /// parent's `on_stop` is called.
+    pub async fn wait_exited(&self) {
+        self.actor_ref.weak.shared.wait_exited().await
+    }
+}
+
+impl<A: Actor> WeakActorRef<A> {
+    /// Creates a child actor owned by this actor, and hands its [`ActorRun`] future to
+    /// `spawn_fn` to be spawned on the caller's runtime, e.g. `|run| { tokio::spawn(run); }`.
+    ///
+    /// When this actor stops, the default run loop closes the mailboxes of all of its children
+    /// that are still running before calling [`Actor::on_stop`], so they stop once they have
+    /// handled the messages already queued. This happens regardless of other references to a
+    /// child. Children that stop on their own are pruned from the registry, and are not
+    /// stopped again.
+    pub fn spawn_child<C: Actor>(
+        &self,
+        child: C,
+        mailbox_size: Option<usize>,
+        spawn_fn: impl FnOnce(ActorRun<C>),
+    ) -> ChildHandle<C> {
+        let (actor_ref, run) = child.into_future(mailbox_size);
+        self.shared.add_child(Child::of(&actor_ref));
+        spawn_fn(run);
+        ChildHandle { actor_ref }
+    }
+}
+
+/// Links two actors, so that when either of them stops, the other one is stopped too, the way
+/// a parent stops its children, see [`WeakActorRef::spawn_child`]: its mailbox is closed, and it
+/// stops once it has handled the messages already queued. This also applies when an actor
+/// exits because it failed.
+///
+/// The cascade ends there: by the time the second actor stops, the first one has already
+/// unregistered its links. Linking doesn't keep either actor alive.
+pub fn link<A: Actor, B: Actor>(a: &ActorRef<A>, b: &ActorRef<B>) {
+    a.weak.shared.add_child(Child::of(b));
+    b.weak.shared.add_child(Child::of(a));
+}
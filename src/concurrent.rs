@@ -0,0 +1,318 @@
+use std::{future::Future, pin::Pin, task::Poll};
+
+use either::Either;
+
+use crate::{
+    control::Control,
+    mailbox::Event,
+    select::biased,
+    supervise::{finish, StopReason},
+    Actor, ActorRef, ActorRun, Mailbox, WeakActorRef,
+};
+
+/// An actor whose handler takes `&self`, so that the run loop can handle several messages at
+/// once, see [`ConcurrentActor::into_future_concurrent`]. Useful for actors that spend most of
+/// each message waiting on I/O, where handling one message at a time wastes throughput.
+///
+/// As handlers share the actor, any state they change must use interior mutability, e.g. a
+/// `Mutex` or atomics, which is why the actor must be `Sync`. The run loop makes no guarantee
+/// about the order in which messages complete, and a handler may observe the effects of
+/// messages sent after its own. [`ConcurrentActor::on_start`] and [`ConcurrentActor::on_stop`]
+/// still get exclusive access, as no handler runs at the same time.
+///
+/// The actor runs as a [`Concurrent`], so references to it are `ActorRef<Concurrent<Self>>`.
+pub trait ConcurrentActor: Send + Sync + Sized + 'static {
+    type Error: Send;
+    type Message: Send;
+
+    #[allow(unused_variables)]
+    /// Called before any messages are handled, see [`Actor::on_start`].
+    fn on_start(
+        &mut self,
+        this: &WeakActorRef<Concurrent<Self>>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        async { Ok(()) }
+    }
+
+    /// Called with each message received by the actor, possibly while other calls are still
+    /// running. An error fails the actor right away, dropping the handlers still running.
+    fn handle(
+        &self,
+        this: &WeakActorRef<Concurrent<Self>>,
+        msg: Self::Message,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+
+    #[allow(unused_variables)]
+    /// Called once every handler has completed after the actor was stopped, see
+    /// [`Actor::on_stop`].
+    fn on_stop(
+        &mut self,
+        stop: Option<Self::Message>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        async { Ok(()) }
+    }
+
+    /// Like [`Actor::into_future`], but the run loop runs up to `max_concurrent` calls to
+    /// [`ConcurrentActor::handle`] at the same time, on the actor's own task. Once that many
+    /// are running, it waits for one of them to complete before receiving the next message.
+    ///
+    /// On stop, the messages already queued are handled with the same concurrency, and
+    /// [`ConcurrentActor::on_stop`] runs once all of them have completed. Acknowledgements and
+    /// flushes fire as messages complete. Messages that outlived their TTL or missed their
+    /// deadline are dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_concurrent` is zero.
+    fn into_future_concurrent(
+        self,
+        mailbox_size: Option<usize>,
+        max_concurrent: usize,
+    ) -> (ActorRef<Concurrent<Self>>, ActorRun<Concurrent<Self>>) {
+        assert!(max_concurrent > 0, "`max_concurrent` must be at least 1");
+        let actor = Concurrent {
+            actor: self,
+            max_concurrent,
+        };
+        let (mailbox, actor_ref) = Mailbox::new(mailbox_size);
+        let _ = mailbox.this.shared.concurrency.set(max_concurrent);
+        ActorRun::with_mailbox(actor, (mailbox, actor_ref))
+    }
+}
+
+/// Runs a [`ConcurrentActor`], see [`ConcurrentActor::into_future_concurrent`].
+pub struct Concurrent<A> {
+    actor: A,
+    max_concurrent: usize,
+}
+
+impl<A> Concurrent<A> {
+    /// Returns a reference to the actor.
+    pub fn get_ref(&self) -> &A {
+        &self.actor
+    }
+
+    /// Returns a mutable reference to the actor.
+    pub fn get_mut(&mut self) -> &mut A {
+        &mut self.actor
+    }
+
+    /// Returns the actor, e.g. to inspect its final state once it has stopped.
+    pub fn into_inner(self) -> A {
+        self.actor
+    }
+}
+
+impl<A: ConcurrentActor> Actor for Concurrent<A> {
+    type Error = A::Error;
+    type Message = A::Message;
+
+    fn on_start(
+        &mut self,
+        this: &WeakActorRef<Self>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        self.actor.on_start(this)
+    }
+
+    /// Handles a single message, for callers that drive the actor one message at a time.
+    fn on_msg(
+        &mut self,
+        this: &WeakActorRef<Self>,
+        msg: Self::Message,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        self.actor.handle(this, msg)
+    }
+
+    fn on_stop(
+        &mut self,
+        stop: Option<Self::Message>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        self.actor.on_stop(stop)
+    }
+
+    async fn run_with(&mut self, mailbox: Mailbox<Self>) -> Result<(), Self::Error> {
+        let this = mailbox.this.clone();
+        self.on_start(&this).await?;
+        mailbox.set_started();
+
+        loop {
+            // Handlers borrow the actor, so a replacement waits until all of them are done.
+            match run_concurrent(self, &mailbox).await? {
+                Exit::Replace(new) => {
+                    self.on_stop(None).await?;
+                    *self = new;
+                    self.on_start(&this).await?;
+                    mailbox.check_watchers(self);
+                }
+                Exit::Stop(stop) => return finish(self, &mailbox, StopReason::Stop(stop)).await,
+                Exit::Aborted => return finish(self, &mailbox, StopReason::Aborted).await,
+            }
+        }
+    }
+}
+
+/// Why [`run_concurrent`] returned, once every handler has completed or was cancelled.
+enum Exit<A: ConcurrentActor> {
+    Replace(Concurrent<A>),
+    Stop(Option<A::Message>),
+    /// The actor was aborted, which cancels the handlers that were running.
+    Aborted,
+}
+
+/// A running call to [`ConcurrentActor::handle`], resolving to whether its message was queued
+/// in the mailbox, and so counts towards flushes.
+type Handler<'a, E> = Pin<Box<dyn Future<Output = Result<bool, E>> + Send + 'a>>;
+
+/// Handlers that are running, polled together on the actor's task.
+struct Running<'a, E>(Vec<Handler<'a, E>>);
+
+impl<E> Running<'_, E> {
+    /// Waits for the next handler to complete. Never completes if none is running.
+    async fn next(&mut self) -> Result<bool, E> {
+        std::future::poll_fn(|cx| {
+            for i in 0..self.0.len() {
+                if let Poll::Ready(res) = self.0[i].as_mut().poll(cx) {
+                    drop(self.0.swap_remove(i));
+                    return Poll::Ready(res);
+                }
+            }
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+/// Receives and handles messages concurrently until the actor stops or its state is replaced.
+async fn run_concurrent<'a, A: ConcurrentActor>(
+    actor: &'a Concurrent<A>,
+    mailbox: &'a Mailbox<Concurrent<A>>,
+) -> Result<Exit<A>, A::Error> {
+    let shared = &mailbox.this.shared;
+    let mut running = Running(Vec::new());
+    let mut exit = None;
+    // Whether queued messages are still being received after a stop.
+    let mut draining = true;
+    loop {
+        let draining_now =
+            matches!(exit, Some(Exit::Stop(_))) && draining && !shared.is_drain_skipped();
+        if running.0.is_empty() && !draining_now {
+            if let Some(exit) = exit.take() {
+                return Ok(exit);
+            }
+        }
+        let has_room = running.0.len() < actor.max_concurrent;
+        let started = match exit {
+            None if has_room => {
+                match biased(running.next(), mailbox.next_event(mailbox.recv_msg())).await {
+                    Either::Left(res) => {
+                        completed(actor, mailbox, &running, res?);
+                        continue;
+                    }
+                    Either::Right(Event::Control(Control::Flush(ack))) => {
+                        mailbox.add_barrier(ack);
+                        continue;
+                    }
+                    Either::Right(Event::Control(Control::Escalate(stop, more_severe))) => {
+                        mailbox.escalate(stop, more_severe);
+                        continue;
+                    }
+                    Either::Right(Event::Control(Control::Watch(watcher, ack))) => {
+                        mailbox.add_watcher(actor, watcher, ack);
+                        continue;
+                    }
+                    Either::Right(Event::Express(msg)) => msg,
+                    Either::Right(Event::Control(Control::Express(_))) => {
+                        unreachable!("express messages arrive as `Event::Express`")
+                    }
+                    Either::Right(Event::Control(Control::TakeBacklog(reply))) => {
+                        let _ = reply.send(mailbox.take_backlog());
+                        exit = Some(Exit::Stop(None));
+                        continue;
+                    }
+                    Either::Right(Event::Control(Control::Replace(new))) => {
+                        exit = Some(Exit::Replace(new));
+                        continue;
+                    }
+                    // Only mailboxes created with a trigger have external events.
+                    Either::Right(Event::External) => continue,
+                    Either::Right(Event::Stop(stop)) => {
+                        mailbox.close();
+                        exit = Some(Exit::Stop(stop));
+                        continue;
+                    }
+                    Either::Right(Event::Message(Some(msg))) => msg,
+                    Either::Right(Event::Message(None)) => {
+                        exit = Some(Exit::Stop(None));
+                        continue;
+                    }
+                    Either::Right(Event::Abort) => return Ok(Exit::Aborted),
+                }
+            }
+            Some(_) if has_room && draining_now => {
+                let msg = async {
+                    match mailbox.try_recv_express() {
+                        Some(msg) => Some(msg),
+                        None => mailbox.recv_msg().await,
+                    }
+                };
+                match biased(shared.abort.aborted(), biased(running.next(), msg)).await {
+                    Either::Left(()) => return Ok(Exit::Aborted),
+                    Either::Right(Either::Left(res)) => {
+                        completed(actor, mailbox, &running, res?);
+                        continue;
+                    }
+                    Either::Right(Either::Right(Some(msg))) => msg,
+                    Either::Right(Either::Right(None)) => {
+                        draining = false;
+                        continue;
+                    }
+                }
+            }
+            // Waiting for room, or for the last handlers before exiting.
+            _ => match biased(shared.abort.aborted(), running.next()).await {
+                Either::Left(()) => return Ok(Exit::Aborted),
+                Either::Right(queued) => {
+                    completed(actor, mailbox, &running, queued?);
+                    continue;
+                }
+            },
+        };
+
+        mailbox.take_meta();
+        if mailbox.is_expired() {
+            mailbox.abandon();
+            mailbox.settle_barriers();
+            continue;
+        }
+        let ack = mailbox.take_in_flight();
+        let queued = mailbox.is_queued();
+        running.0.push(Box::pin(async move {
+            actor.actor.handle(&mailbox.this, started).await?;
+            if let Some(ack) = ack {
+                ack.fire();
+            }
+            Ok(queued)
+        }));
+        shared.set_in_handler(true);
+    }
+}
+
+/// Settles flushes and watchers after a handler completed, and marks the actor idle if nothing
+/// is left to do. Only messages that were `queued` in the mailbox count towards flushes.
+fn completed<A: ConcurrentActor>(
+    actor: &Concurrent<A>,
+    mailbox: &Mailbox<Concurrent<A>>,
+    running: &Running<'_, A::Error>,
+    queued: bool,
+) {
+    if queued {
+        mailbox.settle_barriers();
+    }
+    mailbox.check_watchers(actor);
+    if running.0.is_empty() {
+        mailbox.this.shared.set_in_handler(false);
+        if mailbox.is_empty() {
+            mailbox.this.shared.set_idle();
+        }
+    }
+}
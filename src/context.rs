@@ -0,0 +1,110 @@
+use std::{future::Future, sync::Arc};
+
+use crate::{Actor, ActorRef, ActorRun, Mailbox, WeakActorRef};
+
+/// An actor with read-only context, such as configuration or connection pools, that is passed
+/// to its handlers by reference, see [`ContextActor::into_future_with_ctx`]. This keeps shared,
+/// immutable dependencies out of the actor's own state; the same `Arc` can be handed to many
+/// actors.
+///
+/// The actor runs as a [`WithContext`], so references to it are
+/// `ActorRef<WithContext<Self>>`.
+pub trait ContextActor: Send + Sized + 'static {
+    type Ctx: Send + Sync + 'static;
+    type Error: Send;
+    type Message: Send;
+
+    #[allow(unused_variables)]
+    /// Called before any messages are handled, see [`Actor::on_start`].
+    fn on_start(
+        &mut self,
+        this: &WeakActorRef<WithContext<Self>>,
+        ctx: &Self::Ctx,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        async { Ok(()) }
+    }
+
+    /// Called with each message received by the actor, see [`Actor::on_msg`].
+    fn on_msg(
+        &mut self,
+        this: &WeakActorRef<WithContext<Self>>,
+        msg: Self::Message,
+        ctx: &Self::Ctx,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+
+    #[allow(unused_variables)]
+    /// Called once the actor has stopped, see [`Actor::on_stop`].
+    fn on_stop(
+        &mut self,
+        stop: Option<Self::Message>,
+        ctx: &Self::Ctx,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        async { Ok(()) }
+    }
+
+    /// Like [`Actor::into_future`], but passes `ctx` to every call of
+    /// [`ContextActor::on_start`], [`ContextActor::on_msg`] and [`ContextActor::on_stop`].
+    fn into_future_with_ctx(
+        self,
+        mailbox_size: Option<usize>,
+        ctx: Arc<Self::Ctx>,
+    ) -> (ActorRef<WithContext<Self>>, ActorRun<WithContext<Self>>) {
+        let actor = WithContext { actor: self, ctx };
+        ActorRun::with_mailbox(actor, Mailbox::new(mailbox_size))
+    }
+}
+
+/// Runs a [`ContextActor`], see [`ContextActor::into_future_with_ctx`].
+pub struct WithContext<A: ContextActor> {
+    actor: A,
+    ctx: Arc<A::Ctx>,
+}
+
+impl<A: ContextActor> WithContext<A> {
+    /// Returns a reference to the actor.
+    pub fn get_ref(&self) -> &A {
+        &self.actor
+    }
+
+    /// Returns a mutable reference to the actor.
+    pub fn get_mut(&mut self) -> &mut A {
+        &mut self.actor
+    }
+
+    /// Returns the context the actor was started with.
+    pub fn ctx(&self) -> &Arc<A::Ctx> {
+        &self.ctx
+    }
+
+    /// Returns the actor, e.g. to inspect its final state once it has stopped.
+    pub fn into_inner(self) -> A {
+        self.actor
+    }
+}
+
+impl<A: ContextActor> Actor for WithContext<A> {
+    type Error = A::Error;
+    type Message = A::Message;
+
+    fn on_start(
+        &mut self,
+        this: &WeakActorRef<Self>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        self.actor.on_start(this, &self.ctx)
+    }
+
+    fn on_msg(
+        &mut self,
+        this: &WeakActorRef<Self>,
+        msg: Self::Message,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        self.actor.on_msg(this, msg, &self.ctx)
+    }
+
+    fn on_stop(
+        &mut self,
+        stop: Option<Self::Message>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        self.actor.on_stop(stop, &self.ctx)
+    }
+}
@@ -0,0 +1,176 @@
+use async_oneshot_channel::Sender as OneshotSender;
+
+use crate::{
+    ack::{Ack, Unacked},
+    Actor, ActorRef,
+};
+
+/// An operation on the actor's state, sent through the mailbox's control channel and handled by
+/// the default run loop between messages.
+pub(crate) enum Control<A: Actor> {
+    /// Replace the actor's state, see [`ActorRef::replace_state`].
+    Replace(A),
+    /// Fire the acknowledgement once every message queued at that point has been handled, see
+    /// [`ActorRef::flush`].
+    Flush(Ack),
+    /// Fire the acknowledgement once the predicate holds for the actor's state, see
+    /// [`ActorRef::wait_until`].
+    Watch(Watcher<A>, Ack),
+    /// Use this stop message instead of the one the actor was stopped with, if it is more
+    /// severe, see [`ActorRef::escalate_stop`].
+    Escalate(A::Message, MoreSevere<A::Message>),
+    /// Handle this message ahead of the mailbox, see [`ActorRef::send_priority`].
+    Express(A::Message),
+    /// Close the mailbox, hand back the messages still queued, and stop, see
+    /// [`ActorRef::take_backlog`].
+    TakeBacklog(OneshotSender<Vec<A::Message>>),
+}
+
+/// A predicate over the actor's state, evaluated by the run loop.
+pub(crate) type Watcher<A> = Box<dyn Fn(&A) -> bool + Send>;
+
+/// Returns `true` if the first stop message is more severe than the second.
+pub(crate) type MoreSevere<M> = fn(&M, &M) -> bool;
+
+/// The most severe stop message escalated so far, with its comparison.
+pub(crate) type Escalated<M> = (M, MoreSevere<M>);
+
+impl<A: Actor> ActorRef<A> {
+    /// Replaces the running actor's state with `new`, keeping its mailbox and all references to
+    /// it. The run loop tears down the old state with [`Actor::on_stop`] (with a stop message of
+    /// [`None`]), swaps in `new`, and then calls [`Actor::on_start`] on it. If either hook returns
+    /// an error, the actor terminates with that error, as it would for a failing handler.
+    ///
+    /// The swap happens at the next message boundary: a message that is being handled when the
+    /// swap is requested completes on the old state, and every message handled afterwards,
+    /// including those that were already queued, is handled by the new state.
+    ///
+    /// If the actor is no longer running, `new` is returned in [`Err`]. State replacement is
+    /// performed by the default [`Actor::run_with`]; custom run loops do not process it.
+    pub fn replace_state(&self, new: A) -> Result<(), A> {
+        self.control
+            .try_send(Control::Replace(new))
+            .map_err(|e| match e.into_inner() {
+                Control::Replace(new) => new,
+                _ => unreachable!(),
+            })
+    }
+
+    /// Stops the actor like [`ActorRef::stop`], or if it has already been asked to stop, upgrades
+    /// its stop reason to `stop` if that is more severe, e.g. from `Graceful` to `Forced`.
+    /// Severity is the order of the stop messages, so an enum of reasons can derive it.
+    ///
+    /// The stop message that [`Actor::on_stop`] receives is decided once the actor has drained
+    /// its mailbox: it is the most severe of the original stop message and every upgrade
+    /// requested by then. Upgrades are not undone by less severe requests, and do not change
+    /// how the actor drains. If the actor is no longer running, `stop` is returned in [`Err`].
+    /// Upgrades are processed by the default [`Actor::run_with`] only.
+    pub fn escalate_stop(&self, stop: A::Message) -> Result<(), A::Message>
+    where
+        A::Message: PartialOrd,
+    {
+        let stop = match self.request_stop(stop) {
+            Ok(()) => return Ok(()),
+            Err(already) => already.into_inner(),
+        };
+        self.control
+            .try_send(Control::Escalate(stop, |a, b| a > b))
+            .map_err(|e| match e.into_inner() {
+                Control::Escalate(stop, _) => stop,
+                _ => unreachable!(),
+            })
+    }
+
+    /// Sends a message through the actor's express lane, which is never full, so this never
+    /// waits, even if the actor's mailbox is bounded and full. The run loop handles messages
+    /// from the express lane before the ones queued in the mailbox, so this suits control
+    /// messages such as pausing or reconfiguring the actor, which must not be stuck behind a
+    /// backlog of data.
+    ///
+    /// Express messages are handled in the order they were sent, along with the other
+    /// operations of [`ActorRef::flush`] and the like; they are not subject to load shedding or
+    /// ordering by the mailbox, and as they are not queued in the mailbox, [`ActorRef::flush`]
+    /// does not wait for them. A stop takes priority over the express lane: express messages
+    /// that were not handled yet when the actor is asked to stop are handled while it drains
+    /// its mailbox, still ahead of the queued messages, or returned by
+    /// [`ActorRef::take_backlog`]. Once the actor has been asked to stop, new express messages
+    /// are returned in [`Err`], as they are if the actor is no longer running. Only the default
+    /// [`Actor::run_with`] and [`ConcurrentActor`](crate::ConcurrentActor)s process the express
+    /// lane.
+    pub fn send_priority(&self, msg: A::Message) -> Result<(), A::Message> {
+        if self.weak.shared.is_stop_requested() {
+            return Err(msg);
+        }
+        self.control
+            .try_send(Control::Express(msg))
+            .map_err(|e| match e.into_inner() {
+                Control::Express(msg) => msg,
+                _ => unreachable!(),
+            })
+    }
+
+    /// Stops the actor and returns the messages still queued in its mailbox instead of
+    /// handling them, e.g. to hand an overloaded worker's backlog to other workers. The run
+    /// loop takes the backlog between messages: the message being handled, if any, completes
+    /// first, and every queued message is then either returned here or was already handled,
+    /// never both.
+    ///
+    /// The mailbox is closed when the backlog is taken, so that later sends fail, and the actor
+    /// then stops without draining, calling [`Actor::on_stop`] with [`None`]. Messages are
+    /// returned in the order the mailbox would have delivered them, starting with those sent
+    /// with [`ActorRef::send_priority`]. Messages stashed by [`Actor::should_stash`] are
+    /// returned too, ahead of the queued ones. Senders waiting on an acknowledgement get
+    /// [`Unacked`].
+    ///
+    /// Returns an empty backlog if the actor had already been asked to stop, in which case it
+    /// drains its mailbox as usual, or is no longer running. Like [`ActorRef::flush`], this is
+    /// processed by the default [`Actor::run_with`] and
+    /// [`ConcurrentActor`](crate::ConcurrentActor)s only.
+    pub async fn take_backlog(&self) -> Vec<A::Message> {
+        let (sender, receiver) = async_oneshot_channel::oneshot();
+        if self.weak.shared.is_stop_requested()
+            || self.control.try_send(Control::TakeBacklog(sender)).is_err()
+        {
+            return Vec::new();
+        }
+        receiver.recv().await.unwrap_or_default()
+    }
+
+    /// Waits until the actor has handled every message that was queued when it received the
+    /// flush, which includes every message sent before calling this. Useful for synchronizing
+    /// tests, or checkpointing, without polling [`ActorRef::len`].
+    ///
+    /// The flush is delivered ahead of queued messages, like [`ActorRef::replace_state`], and
+    /// then waits for as many messages as the mailbox held at that point. With the default FIFO
+    /// mailbox, these are exactly the messages sent before the flush; with other orderings,
+    /// messages sent later may be handled in their place.
+    ///
+    /// Resolves to [`Unacked`] if the actor stops before handling those messages, or is no
+    /// longer running. Flushes are processed by the default [`Actor::run_with`]; custom run loops
+    /// do not process them.
+    pub async fn flush(&self) -> Result<(), Unacked> {
+        let (ack, acked) = Ack::new();
+        self.control
+            .try_send(Control::Flush(ack))
+            .map_err(|_| Unacked)?;
+        acked.recv().await.ok_or(Unacked)
+    }
+
+    /// Waits until `predicate` holds for the actor's state, e.g. until a counter reaches a
+    /// given value in a test. The run loop evaluates it right away, and then again after every
+    /// message it handles, so no state in between is missed and no polling is needed.
+    ///
+    /// Resolves to [`Unacked`] if the actor stops before the predicate holds, or is no longer
+    /// running. Like [`ActorRef::flush`], this is processed by the default [`Actor::run_with`]
+    /// only.
+    pub async fn wait_until(
+        &self,
+        predicate: impl Fn(&A) -> bool + Send + 'static,
+    ) -> Result<(), Unacked> {
+        let (ack, acked) = Ack::new();
+        self.control
+            .try_send(Control::Watch(Box::new(predicate), ack))
+            .map_err(|_| Unacked)?;
+        acked.recv().await.ok_or(Unacked)
+    }
+}
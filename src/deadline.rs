@@ -0,0 +1,60 @@
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// A point in time by which a request must be handled, see [`ActorRef::send_by`](crate::ActorRef::send_by).
+///
+/// Unlike a TTL, a deadline is absolute, so it composes across a chain of actors: the handler
+/// of a message sent with a deadline finds it in [`HandlerCtx::deadline`](crate::HandlerCtx::deadline),
+/// and passes it on with the messages it sends downstream, possibly narrowed with
+/// [`Deadline::narrow`] to keep some of the remaining time for itself.
+pub struct Deadline(Instant);
+
+impl Deadline {
+    /// Creates a deadline at `instant`.
+    pub fn at(instant: Instant) -> Self {
+        Self(instant)
+    }
+
+    /// Creates a deadline `budget` from now. Budgets too large to represent never expire in
+    /// practice and are capped at a century.
+    pub fn after(budget: Duration) -> Self {
+        let now = Instant::now();
+        Self(
+            now.checked_add(budget)
+                .unwrap_or_else(|| now + Duration::from_secs(100 * 365 * 24 * 3600)),
+        )
+    }
+
+    /// Returns the point in time of the deadline.
+    pub fn instant(&self) -> Instant {
+        self.0
+    }
+
+    /// Returns the time left until the deadline, or [`Duration::ZERO`] if it has passed.
+    pub fn remaining(&self) -> Duration {
+        self.0.saturating_duration_since(Instant::now())
+    }
+
+    /// Returns `true` if the deadline has passed.
+    pub fn is_expired(&self) -> bool {
+        self.0 <= Instant::now()
+    }
+
+    /// Returns the earlier of this deadline and `budget` from now, e.g. to give a downstream
+    /// call at most `budget` of the time that is left.
+    pub fn narrow(&self, budget: Duration) -> Self {
+        (*self).min(Self::after(budget))
+    }
+}
+
+impl From<Instant> for Deadline {
+    fn from(instant: Instant) -> Self {
+        Self(instant)
+    }
+}
+
+impl From<Deadline> for Instant {
+    fn from(deadline: Deadline) -> Self {
+        deadline.0
+    }
+}
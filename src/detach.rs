@@ -0,0 +1,54 @@
+use std::{future::Future, pin::Pin};
+
+use crate::{Actor, WeakActorRef};
+
+/// Background work handed to a spawner, see [`Mailbox::set_spawner`](crate::Mailbox::set_spawner).
+pub type Detached = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// A type-erased spawner set with [`Mailbox::set_spawner`](crate::Mailbox::set_spawner).
+pub(crate) struct Spawner(pub(crate) Box<dyn Fn(Detached) + Send + Sync>);
+
+impl std::fmt::Debug for Spawner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Spawner").finish_non_exhaustive()
+    }
+}
+
+impl<A: Actor> WeakActorRef<A> {
+    /// Runs `work` in the background, detached from the actor, so that a handler can start
+    /// work that outlives it without holding up the mailbox. The work is spawned with the
+    /// spawner the actor was started with, see
+    /// [`Mailbox::set_spawner`](crate::Mailbox::set_spawner); without one, it is returned in
+    /// [`Err`].
+    ///
+    /// Detached work is not tied to the actor's lifecycle: it keeps running after the actor
+    /// stops. To report back, send the actor a message from within `work`.
+    pub fn detach(&self, work: impl Future<Output = ()> + Send + 'static) -> Result<(), Detached> {
+        let work: Detached = Box::pin(work);
+        match self.shared.spawner.get() {
+            Some(spawner) => {
+                (spawner.0)(work);
+                Ok(())
+            }
+            None => Err(work),
+        }
+    }
+
+    /// Sends `msg` from a task spawned with `spawn_fn`, e.g. `|fut| { tokio::spawn(fut); }`,
+    /// and runs `on_sent` there once the message has been enqueued. This returns right away,
+    /// so it can be used from synchronous code, or from a handler sending to a bounded mailbox
+    /// that may be full. If the message cannot be sent, it is dropped and `on_sent` is not run.
+    pub fn send_then(
+        &self,
+        msg: A::Message,
+        on_sent: impl FnOnce() + Send + 'static,
+        spawn_fn: impl FnOnce(Detached),
+    ) {
+        let this = self.clone();
+        spawn_fn(Box::pin(async move {
+            if this.send(msg).await.is_ok() {
+                on_sent();
+            }
+        }));
+    }
+}
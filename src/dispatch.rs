@@ -0,0 +1,65 @@
+use std::{future::Future, pin::Pin};
+
+use crate::{Actor, Mailbox};
+
+/// Decides which message the default run loop hands to the actor next, set with
+/// [`Mailbox::set_dispatcher`].
+///
+/// The run loop still receives stop messages and control operations itself, and only asks the
+/// dispatcher for messages. The mailbox's own ordering, such as [`Mailbox::lifo`], applies
+/// before the dispatcher sees a message, so the two compose.
+///
+/// The future returned by [`Dispatcher::next`] may be dropped before it completes, whenever a
+/// stop message or control operation arrives first, so it must not lose messages it has already
+/// received when cancelled: keep them in `self` instead.
+///
+/// Acknowledgements and metadata are tracked only for the message most recently received from
+/// the mailbox, so a dispatcher that holds messages back and returns them later leaves them
+/// unacknowledged, see [`Mailbox::complete`].
+pub trait Dispatcher<A: Actor>: Send {
+    /// Returns the next message to handle, or [`None`] once there are no more messages, i.e.
+    /// when the mailbox is closed and empty, and the dispatcher holds no messages either.
+    fn next<'a>(
+        &'a mut self,
+        mailbox: &'a Mailbox<A>,
+    ) -> impl Future<Output = Option<A::Message>> + Send + 'a;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+/// Hands out messages in the order the mailbox delivers them. This is what the run loop does
+/// when no dispatcher is set.
+pub struct FifoDispatcher;
+
+impl<A: Actor> Dispatcher<A> for FifoDispatcher {
+    fn next<'a>(
+        &'a mut self,
+        mailbox: &'a Mailbox<A>,
+    ) -> impl Future<Output = Option<A::Message>> + Send + 'a {
+        mailbox.recv_msg()
+    }
+}
+
+type NextFuture<'a, M> = Pin<Box<dyn Future<Output = Option<M>> + Send + 'a>>;
+
+/// Object-safe version of [`Dispatcher`], so that the mailbox can hold any dispatcher.
+pub(crate) trait DynDispatcher<A: Actor>: Send {
+    fn next_boxed<'a>(&'a mut self, mailbox: &'a Mailbox<A>) -> NextFuture<'a, A::Message>;
+}
+
+impl<A: Actor, D: Dispatcher<A>> DynDispatcher<A> for D {
+    fn next_boxed<'a>(&'a mut self, mailbox: &'a Mailbox<A>) -> NextFuture<'a, A::Message> {
+        Box::pin(self.next(mailbox))
+    }
+}
+
+/// Receives the next message through `dispatcher`, or straight from the mailbox if there is none,
+/// which saves boxing a future per message.
+pub(crate) async fn dispatch<A: Actor>(
+    dispatcher: &mut Option<Box<dyn DynDispatcher<A>>>,
+    mailbox: &Mailbox<A>,
+) -> Option<A::Message> {
+    match dispatcher {
+        Some(dispatcher) => dispatcher.next_boxed(mailbox).await,
+        None => mailbox.recv_msg().await,
+    }
+}
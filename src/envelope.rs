@@ -0,0 +1,86 @@
+use std::time::Instant;
+
+use crate::{mailbox::Packet, Actor, ActorRef, Deadline};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Metadata attached to a message sent with [`ActorRef::send_enveloped`], and passed to
+/// [`Actor::on_msg_meta`]. This lets middleware such as logging or metrics work the same way
+/// across actor types.
+pub struct Meta {
+    /// When the message was wrapped into its [`Envelope`].
+    pub sent_at: Instant,
+    /// An identifier relating the message to other messages, e.g. of the same request.
+    pub correlation_id: Option<u64>,
+    /// An opaque token identifying where a reply to this message should go.
+    pub reply_token: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+/// What the run loop knows about a message as it hands it to [`Actor::on_msg_ctx`].
+pub struct HandlerCtx {
+    /// The number of messages still queued in the mailbox behind this one. Handlers can use it
+    /// to adapt to load, e.g. by skipping non-essential work while backed up.
+    pub backlog: usize,
+    /// The position of this message among the messages handled by the actor, starting at 0.
+    pub seq: u64,
+    /// The message's metadata, as passed to [`Actor::on_msg_meta`].
+    pub meta: Option<Meta>,
+    /// The message's deadline, if it was sent with [`ActorRef::send_by`](crate::ActorRef::send_by),
+    /// to be passed on with the messages the handler sends downstream.
+    pub deadline: Option<Deadline>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A message along with its [`Meta`]data, see [`ActorRef::send_enveloped`].
+pub struct Envelope<M> {
+    /// The message itself.
+    pub msg: M,
+    /// The message's metadata.
+    pub meta: Meta,
+}
+
+impl<M> Envelope<M> {
+    /// Wraps `msg`, stamping it with the current time.
+    pub fn new(msg: M) -> Self {
+        Self {
+            msg,
+            meta: Meta {
+                sent_at: Instant::now(),
+                correlation_id: None,
+                reply_token: None,
+            },
+        }
+    }
+
+    /// Sets the correlation id of the envelope.
+    pub fn with_correlation_id(mut self, id: u64) -> Self {
+        self.meta.correlation_id = Some(id);
+        self
+    }
+
+    /// Sets the reply token of the envelope.
+    pub fn with_reply_token(mut self, token: u64) -> Self {
+        self.meta.reply_token = Some(token);
+        self
+    }
+}
+
+impl<A: Actor> ActorRef<A> {
+    /// Sends a message along with its metadata. The actor receives the metadata in
+    /// [`Actor::on_msg_meta`]; messages sent in any other way carry none. If the mailbox is
+    /// full, the envelope is returned in [`Err`].
+    pub async fn send_enveloped(
+        &self,
+        env: Envelope<A::Message>,
+    ) -> Result<(), Envelope<A::Message>> {
+        let packet = Packet {
+            meta: Some(Box::new(env.meta)),
+            ..Packet::new(env.msg)
+        };
+        self.send_packet(packet).await.map_err(|packet| Envelope {
+            msg: packet.msg,
+            meta: env.meta,
+        })
+    }
+}
@@ -0,0 +1,138 @@
+use std::{convert::Infallible, future::Future, task::Poll};
+
+use either::Either;
+
+use crate::{
+    select::biased,
+    supervise::{finish, StopReason},
+    Actor, ActorRef, ActorRun, Mailbox, WeakActorRef,
+};
+
+/// Creates an actor that produces values on its own rather than in response to messages:
+/// it calls `step` on its state `init` over and over, and sends each value it returns to the
+/// receiver returned along with the actor, until `step` returns [`None`].
+///
+/// The actor yields to the executor after every step, so that a generator that never waits
+/// doesn't monopolize its task. It stops once `step` returns `None`, once the receiver is
+/// dropped, or when it is sent a stop message, whichever comes first; the receiver then ends
+/// after the values that are still buffered. Dropping every [`ActorRef`] doesn't stop it, so
+/// the receiver alone can be kept. Messages sent to the actor are ignored.
+///
+/// `output_size` bounds the channel like [`Mailbox::set_outputs`] does: once it is
+/// full, the generator waits until a value is received before taking its next step.
+#[allow(clippy::type_complexity)]
+pub fn generator_actor<S, Out, F>(
+    init: S,
+    step: F,
+    output_size: Option<usize>,
+) -> (
+    ActorRef<Generator<S, Out, F>>,
+    ActorRun<Generator<S, Out, F>>,
+    async_channel::Receiver<Out>,
+)
+where
+    S: Send + 'static,
+    Out: Send + 'static,
+    F: FnMut(&mut S) -> Option<Out> + Send + 'static,
+{
+    let (outputs, receiver) = match output_size {
+        Some(size) => async_channel::bounded(size),
+        None => async_channel::unbounded(),
+    };
+    let generator = Generator {
+        state: init,
+        step,
+        outputs,
+    };
+    let (actor_ref, run) = generator.into_future(None);
+    (actor_ref, run, receiver)
+}
+
+/// An actor created by [`generator_actor`].
+pub struct Generator<S, Out, F> {
+    state: S,
+    step: F,
+    outputs: async_channel::Sender<Out>,
+}
+
+impl<S, Out, F> Generator<S, Out, F> {
+    /// Returns a reference to the generator's state.
+    pub fn get_ref(&self) -> &S {
+        &self.state
+    }
+
+    /// Returns the generator's state, e.g. to inspect it once the generator has stopped.
+    pub fn into_inner(self) -> S {
+        self.state
+    }
+}
+
+impl<S, Out, F> Actor for Generator<S, Out, F>
+where
+    S: Send + 'static,
+    Out: Send + 'static,
+    F: FnMut(&mut S) -> Option<Out> + Send + 'static,
+{
+    type Error = Infallible;
+    type Message = ();
+
+    async fn on_msg(&mut self, _: &WeakActorRef<Self>, _: ()) -> Result<(), Infallible> {
+        Ok(())
+    }
+
+    async fn run_with(&mut self, mailbox: Mailbox<Self>) -> Result<(), Infallible> {
+        let this = mailbox.this.clone();
+        self.on_start(&this).await?;
+        mailbox.set_started();
+
+        // Resolves once the actor is aborted, or to the stop message, if one is sent. Never
+        // resolves otherwise once every reference is gone, as no stop message can be sent then.
+        let stopped = || async {
+            let stop = biased(mailbox.this.shared.abort.aborted(), mailbox.stop.recv());
+            match stop.await {
+                Either::Left(()) => StopReason::Aborted,
+                Either::Right(Some(stop)) => StopReason::Stop(Some(stop)),
+                Either::Right(None) => std::future::pending().await,
+            }
+        };
+        let reason = loop {
+            let Some(out) = (self.step)(&mut self.state) else {
+                break StopReason::Stop(None);
+            };
+            match biased(stopped(), self.outputs.send(out)).await {
+                Either::Left(reason) => break reason,
+                // The receiver was dropped, so nobody wants the values anymore.
+                Either::Right(Err(_)) => break StopReason::Stop(None),
+                Either::Right(Ok(())) => {}
+            }
+            if let Either::Left(reason) = biased(stopped(), yield_now()).await {
+                break reason;
+            }
+        };
+        self.outputs.close();
+        mailbox.close();
+        finish(self, &mailbox, reason).await
+    }
+}
+
+impl<S, Out, F> std::fmt::Debug for Generator<S, Out, F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Generator")
+            .field("buffered", &self.outputs.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Returns to the executor once, waking the task right away so that it is polled again after
+/// the other tasks that are ready.
+fn yield_now() -> impl Future<Output = ()> {
+    let mut yielded = false;
+    std::future::poll_fn(move |cx| {
+        if yielded {
+            return Poll::Ready(());
+        }
+        yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    })
+}
@@ -0,0 +1,94 @@
+use std::{future::Future, pin::Pin};
+
+use crate::{Actor, HandlerCtx, WeakActorRef};
+
+type Handled<'a, A> = Pin<
+    Box<dyn Future<Output = Result<Vec<<A as Actor>::Message>, <A as Actor>::Error>> + Send + 'a>,
+>;
+
+/// Middleware wrapped around the actor's handler by the default run loop, added with
+/// [`Mailbox::layer`](crate::Mailbox::layer). Useful for concerns that apply to every message,
+/// such as logging, metrics, retries or authorization, without touching the handler itself.
+///
+/// A layer receives every message before the handler does, and calls [`Next::run`] to pass it
+/// on to the next layer, or to [`Actor::on_msg_ctx`] once there are no more layers. A layer
+/// that returns without calling `next` drops the message, which counts as handled. It may also
+/// change the message, or inspect the result of the handler before returning it.
+///
+/// Layers run inside the handler, so they count towards the handler timeout, and an error they
+/// return fails the actor like an error from the handler.
+pub trait Layer<A: Actor>: Send + Sync + 'static {
+    /// Handles `msg`, usually by calling `next.run(actor, this, msg)` at some point.
+    fn call<'a>(
+        &'a self,
+        actor: &'a mut A,
+        this: &'a WeakActorRef<A>,
+        msg: A::Message,
+        next: Next<'a, A>,
+    ) -> impl Future<Output = Result<Vec<A::Message>, A::Error>> + Send + 'a;
+}
+
+/// Object-safe version of [`Layer`], so that the mailbox can hold any layers.
+pub(crate) trait DynLayer<A: Actor>: Send + Sync {
+    fn call_boxed<'a>(
+        &'a self,
+        actor: &'a mut A,
+        this: &'a WeakActorRef<A>,
+        msg: A::Message,
+        next: Next<'a, A>,
+    ) -> Handled<'a, A>;
+}
+
+impl<A: Actor, L: Layer<A>> DynLayer<A> for L {
+    fn call_boxed<'a>(
+        &'a self,
+        actor: &'a mut A,
+        this: &'a WeakActorRef<A>,
+        msg: A::Message,
+        next: Next<'a, A>,
+    ) -> Handled<'a, A> {
+        Box::pin(self.call(actor, this, msg, next))
+    }
+}
+
+/// The rest of the layer stack, handed to a [`Layer`] to pass the message on.
+pub struct Next<'a, A: Actor> {
+    layers: &'a [Box<dyn DynLayer<A>>],
+    ctx: HandlerCtx,
+}
+
+impl<'a, A: Actor> Next<'a, A> {
+    pub(crate) fn new(layers: &'a [Box<dyn DynLayer<A>>], ctx: HandlerCtx) -> Self {
+        Self { layers, ctx }
+    }
+
+    /// Returns what the run loop knows about the message, as passed to [`Actor::on_msg_ctx`].
+    pub fn ctx(&self) -> &HandlerCtx {
+        &self.ctx
+    }
+
+    /// Passes `msg` to the next layer, or to the handler if this was the innermost layer.
+    pub fn run(
+        self,
+        actor: &'a mut A,
+        this: &'a WeakActorRef<A>,
+        msg: A::Message,
+    ) -> impl Future<Output = Result<Vec<A::Message>, A::Error>> + Send + 'a {
+        match self.layers.split_first() {
+            Some((layer, layers)) => {
+                let next = Next::new(layers, self.ctx);
+                either::Either::Left(layer.call_boxed(actor, this, msg, next))
+            }
+            None => either::Either::Right(actor.on_msg_ctx(this, msg, self.ctx)),
+        }
+    }
+}
+
+impl<A: Actor> std::fmt::Debug for Next<'_, A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Next")
+            .field("layers", &self.layers.len())
+            .field("ctx", &self.ctx)
+            .finish()
+    }
+}
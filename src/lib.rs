@@ -26,7 +26,7 @@
 //! #[tokio::main]
 //! async fn main() {
 //!     let actor = CounterActor(0);
-//!     let (actor_ref, fut) = actor.into_future(None);
+//!     let (actor_ref, fut) = actor.into_future(Some(8));
 //!     let handle = tokio::spawn(fut);
 //!
 //!     actor_ref.send(3).await.unwrap();
@@ -38,169 +38,3694 @@
 //! }
 //! ```
 
+// Lets code generated by `#[derive(Actor)]` refer to `::async_actor` from within the crate.
+#[cfg(feature = "derive")]
+extern crate self as async_actor;
+
+mod abort;
+mod ack;
 mod actor;
 mod actor_ref;
 mod actor_run;
+mod ask;
+mod backoff;
+mod bus;
+mod channel;
+mod child;
+mod concurrent;
+mod context;
+mod control;
+mod deadline;
+mod detach;
+mod dispatch;
+mod envelope;
+mod generator;
+mod layer;
 mod mailbox;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod multi;
+mod observe;
+mod persist;
+mod pipe;
+mod queue;
+mod rate;
+mod recorder;
+mod relocate;
+#[cfg(feature = "remote")]
+mod remote;
+mod router;
+mod scope;
+mod select;
+mod shared;
+mod shutdown;
+mod stash;
+#[cfg(feature = "futures")]
+mod stream;
+mod supervise;
+mod timeout;
+mod trigger;
+
+pub use abort::AbortHandle;
+pub use ack::Unacked;
+pub use actor::*;
+pub use actor_ref::*;
+pub use actor_run::*;
+pub use ask::{
+    ask_any, join_replies, AllFailed, AskError, AskNow, Cancellation, JoinReplies, PendingReply,
+    ProgressSender, Reply,
+};
+#[cfg(feature = "derive")]
+pub use async_actor_derive::Actor;
+pub use backoff::{Backoff, BackoffStatus, BackoffSupervisor};
+pub use bus::{Bus, BusMessage, Recipient, SubscriberId, Subscription};
+pub use child::{link, ChildHandle};
+pub use concurrent::{Concurrent, ConcurrentActor};
+pub use context::{ContextActor, WithContext};
+pub use deadline::Deadline;
+pub use detach::Detached;
+pub use dispatch::{Dispatcher, FifoDispatcher};
+pub use envelope::{Envelope, HandlerCtx, Meta};
+pub use generator::{generator_actor, Generator};
+pub use layer::{Layer, Next};
+pub use mailbox::{Mailbox, MailboxEvent, MessageReceiver};
+#[cfg(feature = "metrics")]
+pub use metrics::{ActorMetrics, LatencySnapshot};
+pub use multi::MultiActor;
+pub use observe::Observable;
+pub use persist::{SnapshotFile, SnapshotSink};
+pub use queue::Tier;
+pub use recorder::{replay, Recorded, Recorder};
+pub use relocate::{Parked, Relocatable, RelocateHandle, Relocated};
+#[cfg(feature = "remote")]
+pub use remote::{DecodeError, RemoteActorRef, RemoteMessage, RemoteServer};
+pub use router::{Router, Strategy};
+pub use scope::{Scope, ScopedActor, ScopedRef};
+pub use shutdown::{ShutdownFailed, ShutdownGroup};
+pub use stash::UnstashMode;
+#[cfg(feature = "futures")]
+pub use stream::{Duplex, MailboxStream};
+pub use supervise::{DrainErrorPolicy, ErrorAction, Supervision};
+
+#[cfg(test)]
+// Most tests need unbounded mailboxes and are skipped with `bounded-only`, leaving their
+// helpers unused.
+#[cfg_attr(feature = "bounded-only", allow(dead_code, unused_imports))]
+mod test {
+    use super::*;
+
+    struct MyActor(usize);
+
+    impl Actor for MyActor {
+        type Error = ();
+        type Message = usize;
+
+        async fn on_msg(
+            &mut self,
+            _: &WeakActorRef<Self>,
+            msg: Self::Message,
+        ) -> Result<(), Self::Error> {
+            self.0 += msg;
+            println!("Received message: {}. Current state: {}", msg, self.0);
+
+            Ok(())
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_actor() {
+        let actor = MyActor(0);
+        let (actor_ref, fut) = actor.into_future(None);
+        let handle = tokio::spawn(fut);
+
+        actor_ref.send(3).await.unwrap();
+        actor_ref.send(7).await.unwrap();
+
+        actor_ref.stop(0).unwrap();
+
+        let res = handle.await;
+        assert!(res.is_ok());
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_actor_long() {
+        let actor = MyActor(0);
+        let (actor_ref, fut) = actor.into_future(None);
+        let handle = tokio::spawn(fut);
+
+        for i in 0..1000 {
+            actor_ref.send(i).await.unwrap();
+        }
+
+        actor_ref.stop(0).unwrap();
+
+        let res = handle.await;
+        assert!(res.is_ok());
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_drop() {
+        let actor = MyActor(0);
+        let (actor_ref, fut) = actor.into_future(None);
+        let handle = tokio::spawn(fut);
+
+        actor_ref.send(3).await.unwrap();
+        actor_ref.send(7).await.unwrap();
+
+        drop(actor_ref);
+
+        let res = handle.await;
+        assert!(res.is_ok());
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_drop_partial() {
+        let actor = MyActor(0);
+        let (actor_ref, fut) = actor.into_future(None);
+        let handle = tokio::spawn(fut);
+
+        let ActorRef { sender, stop, .. } = actor_ref;
+        sender.send(3).await.unwrap();
+        sender.send(7).await.unwrap();
+
+        drop(stop);
+
+        let res = handle.await;
+        assert!(res.is_ok());
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_into_parts() {
+        let actor = MyActor(0);
+        let (actor_ref, fut) = actor.into_future(None);
+        let handle = tokio::spawn(fut);
+
+        let (sender, stop) = actor_ref.into_parts();
+        sender.send(3).await.unwrap();
+        let actor_ref = ActorRef::from_parts(sender, stop);
+        actor_ref.send(7).await.unwrap();
+
+        let (sender, stop) = actor_ref.into_parts();
+        stop.send(0).unwrap();
+        let res = handle.await;
+        assert_eq!(res.unwrap().unwrap().0, 10);
+        assert_eq!(sender.try_send(1), Err(TrySendError::Closed(1)));
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_drop_partial_sender() {
+        let actor = MyActor(0);
+        let (actor_ref, fut) = actor.into_future(None);
+        let handle = tokio::spawn(fut);
+
+        let ActorRef { sender, .. } = actor_ref;
+        sender.send(3).await.unwrap();
+        sender.send(7).await.unwrap();
+
+        drop(sender);
+
+        let res = handle.await;
+        assert!(res.is_ok());
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_send_and_stop_refs() {
+        let actor = MyActor(0);
+        let (actor_ref, fut) = actor.into_future(None);
+        let handle = tokio::spawn(fut);
+
+        let send_ref = actor_ref.downgrade_sender_only();
+        let stop_ref = actor_ref.stop_only();
+        send_ref.send(3).await.unwrap();
+        stop_ref.stop(7).unwrap();
+        assert_eq!(stop_ref.stop(1), Err(1));
+
+        let res = handle.await;
+        assert_eq!(res.unwrap().unwrap().0, 3);
+        assert_eq!(send_ref.send(5).await, Err(5));
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_send_ref_liveness() {
+        let actor = MyActor(0);
+        let (actor_ref, fut) = actor.into_future(None);
+        let handle = tokio::spawn(fut);
+
+        let send_ref = actor_ref.downgrade_sender_only();
+        let stop_ref = actor_ref.stop_only();
+        drop(actor_ref);
+
+        assert!(handle.await.unwrap().is_ok());
+        assert_eq!(send_ref.send(3).await, Err(3));
+        assert_eq!(stop_ref.stop(7), Err(7));
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_close() {
+        let actor = MyActor(0);
+        let (actor_ref, fut) = actor.into_future(None);
+        let handle = tokio::spawn(fut);
+
+        actor_ref.send(3).await.unwrap();
+        actor_ref.send(7).await.unwrap();
+        assert!(actor_ref.clone().close());
+        assert!(!actor_ref.close());
+        assert_eq!(actor_ref.send(1).await, Err(1));
+
+        let res = handle.await;
+        assert_eq!(res.unwrap().unwrap().0, 10);
+    }
+
+    #[tokio::test]
+    async fn test_reserve() {
+        for mailbox in [Mailbox::new(Some(1)), Mailbox::lifo(Some(1))] {
+            let (actor_ref, fut) = ActorRun::with_mailbox(MyActor(0), mailbox);
+
+            let permit = actor_ref.reserve().await.unwrap();
+            assert_eq!(actor_ref.try_send(1), Err(TrySendError::Full(1)));
+            drop(permit);
+            actor_ref.try_send(1).unwrap();
+
+            let handle = tokio::spawn(fut);
+            let permit = actor_ref.reserve().await.unwrap();
+            permit.send(2).unwrap();
+            actor_ref.flush().await.unwrap();
+
+            actor_ref.close();
+            assert_eq!(actor_ref.reserve().await.unwrap_err(), Closed);
+            assert_eq!(handle.await.unwrap().unwrap().0, 3);
+        }
+    }
+
+    #[cfg(feature = "bounded-only")]
+    #[test]
+    #[should_panic(expected = "`bounded-only`")]
+    fn test_bounded_only() {
+        let (actor_ref, _fut) = MyActor(0).into_future(Some(1));
+        drop(actor_ref);
+        let _ = MyActor(0).into_future(None);
+    }
+
+    #[tokio::test]
+    async fn test_notify_on_drop() {
+        let (actor_ref, fut) = MyActor(0).into_future(Some(1));
+        let handle = tokio::spawn(fut);
+
+        let notifier = actor_ref.notify_on_drop(2);
+        assert_eq!(actor_ref.notify_on_drop(5).cancel(), 5);
+        drop(actor_ref);
+        // The notifier's reference keeps the actor running until it has sent the message.
+        drop(notifier);
+        assert_eq!(handle.await.unwrap().unwrap().0, 2);
+
+        let (actor_ref, _fut) = MyActor(0).into_future(Some(1));
+        actor_ref.try_send(1).unwrap();
+        // The mailbox is full, so the message is dropped.
+        drop(actor_ref.notify_on_drop(2));
+        assert_eq!(actor_ref.len(), 1);
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    // References hash by identity only, which their interior mutability doesn't affect.
+    #[allow(clippy::mutable_key_type)]
+    async fn test_ref_identity() {
+        let (a, _run_a) = MyActor(0).into_future(None);
+        let (b, _run_b) = MyActor(0).into_future(None);
+        let weak_a = a.downgrade();
+        assert!(a == a.clone());
+        assert!(a == weak_a);
+        assert!(weak_a == a);
+        assert!(weak_a == weak_a.clone());
+        assert!(a != b);
+        assert!(weak_a != b);
+
+        let mut subscribers = std::collections::HashSet::new();
+        subscribers.insert(weak_a.clone());
+        subscribers.insert(b.downgrade());
+        assert!(!subscribers.insert(a.downgrade()));
+        assert!(subscribers.remove(&weak_a));
+        assert_eq!(subscribers.len(), 1);
+
+        let hash = |value: &dyn Fn(&mut std::hash::DefaultHasher)| {
+            let mut hasher = std::hash::DefaultHasher::new();
+            value(&mut hasher);
+            std::hash::Hasher::finish(&hasher)
+        };
+        use std::hash::Hash;
+        assert_eq!(hash(&|h| a.hash(h)), hash(&|h| weak_a.hash(h)));
+        for actor_ref in [a, b] {
+            actor_ref.stop(0).unwrap();
+        }
+    }
+
+    /// Tracks how many of its handlers are running at once.
+    #[derive(Default)]
+    struct SerialActor {
+        active: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+        max_active: usize,
+        handled: Vec<usize>,
+    }
+
+    impl Actor for SerialActor {
+        type Error = ();
+        type Message = usize;
+
+        async fn on_msg(&mut self, _: &WeakActorRef<Self>, msg: usize) -> Result<(), ()> {
+            use std::sync::atomic::Ordering;
+            let active = self.active.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_active = self.max_active.max(active);
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+            self.handled.push(msg);
+            self.active.fetch_sub(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_handlers_serialized() {
+        let (actor_ref, fut) = SerialActor::default().into_future(None);
+        assert_eq!(actor_ref.concurrency(), 1);
+        let handle = tokio::spawn(fut);
+        let senders: Vec<_> = (0..4)
+            .map(|sender| {
+                let actor_ref = actor_ref.clone();
+                tokio::spawn(async move {
+                    for i in 0..10 {
+                        actor_ref.send(sender * 10 + i).await.unwrap();
+                    }
+                })
+            })
+            .collect();
+        for sender in senders {
+            sender.await.unwrap();
+        }
+        actor_ref.stop(0).unwrap();
+
+        let actor = handle.await.unwrap().unwrap();
+        assert_eq!(actor.max_active, 1);
+        assert_eq!(actor.handled.len(), 40);
+        // Each sender's messages are handled in the order they were sent.
+        for sender in 0..4 {
+            let own: Vec<_> = actor
+                .handled
+                .iter()
+                .filter(|&&m| m / 10 == sender)
+                .collect();
+            assert!(own.windows(2).all(|w| w[0] < w[1]));
+        }
+    }
+
+    /// Sleeps in every handler, tracking how many run at once.
+    #[derive(Default)]
+    struct FetchActor {
+        active: std::sync::atomic::AtomicUsize,
+        max_active: std::sync::atomic::AtomicUsize,
+        handled: std::sync::Mutex<Vec<u64>>,
+    }
+
+    impl ConcurrentActor for FetchActor {
+        type Error = ();
+        type Message = u64;
+
+        async fn handle(&self, _: &WeakActorRef<Concurrent<Self>>, ms: u64) -> Result<(), ()> {
+            use std::sync::atomic::Ordering;
+            let active = self.active.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_active.fetch_max(active, Ordering::SeqCst);
+            tokio::time::sleep(std::time::Duration::from_millis(ms)).await;
+            self.active.fetch_sub(1, Ordering::SeqCst);
+            self.handled.lock().unwrap().push(ms);
+            Ok(())
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_concurrent_actor() {
+        let (actor_ref, fut) = FetchActor::default().into_future_concurrent(None, 3);
+        assert_eq!(actor_ref.concurrency(), 3);
+        let handle = tokio::spawn(fut);
+        for ms in [30, 10, 20, 5, 5, 5] {
+            actor_ref.send(ms).await.unwrap();
+        }
+        actor_ref.send_ack(1).await.unwrap();
+        actor_ref.flush().await.unwrap();
+        // Queued behind the stop, these are handled by the drain.
+        for ms in [1, 1] {
+            actor_ref.send(ms).await.unwrap();
+        }
+        actor_ref.stop(0).unwrap();
+
+        let actor = handle.await.unwrap().unwrap().into_inner();
+        let handled = actor.handled.into_inner().unwrap();
+        assert_eq!(handled.len(), 9);
+        assert_eq!(handled.iter().sum::<u64>(), 78);
+        // Shorter requests overtook the first one.
+        assert_ne!(handled[0], 30);
+        assert_eq!(actor.max_active.into_inner(), 3);
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_rate_limited() {
+        let (mut mailbox, actor_ref) = Mailbox::new(None);
+        mailbox.set_rate_limit(50, tokio::time::sleep);
+        let (actor_ref, fut) = ActorRun::with_mailbox(MyActor(0), (mailbox, actor_ref));
+        assert_eq!(actor_ref.rate_limit_tokens(), Some(50));
+        assert_eq!(MyActor(0).into_future(None).0.rate_limit_tokens(), None);
+
+        let started = std::time::Instant::now();
+        for _ in 0..55 {
+            actor_ref.send(1).await.unwrap();
+        }
+        let handle = tokio::spawn(fut);
+        actor_ref.flush().await.unwrap();
+        // The burst of 50 is handled right away, the other 5 at 20ms intervals.
+        let elapsed = started.elapsed();
+        assert!(
+            elapsed >= std::time::Duration::from_millis(90),
+            "{elapsed:?}"
+        );
+        assert!(
+            elapsed < std::time::Duration::from_millis(500),
+            "{elapsed:?}"
+        );
+        assert_eq!(actor_ref.rate_limit_tokens(), Some(0));
+
+        actor_ref.stop(0).unwrap();
+        assert_eq!(handle.await.unwrap().unwrap().0, 55);
+    }
+
+    /// Drops messages above a limit without handing them on.
+    struct MaxLayer(usize);
+
+    impl Layer<MyActor> for MaxLayer {
+        async fn call<'a>(
+            &'a self,
+            actor: &'a mut MyActor,
+            this: &'a WeakActorRef<MyActor>,
+            msg: usize,
+            next: Next<'a, MyActor>,
+        ) -> Result<Vec<usize>, ()> {
+            if msg > self.0 {
+                return Ok(Vec::new());
+            }
+            next.run(actor, this, msg).await
+        }
+    }
+
+    /// Logs each message and the actor's state once the layers below have handled it.
+    struct LogLayer(std::sync::Arc<std::sync::Mutex<Vec<(u64, usize, usize)>>>);
+
+    impl Layer<MyActor> for LogLayer {
+        async fn call<'a>(
+            &'a self,
+            actor: &'a mut MyActor,
+            this: &'a WeakActorRef<MyActor>,
+            msg: usize,
+            next: Next<'a, MyActor>,
+        ) -> Result<Vec<usize>, ()> {
+            let seq = next.ctx().seq;
+            let res = next.run(actor, this, msg).await;
+            self.0.lock().unwrap().push((seq, msg, actor.0));
+            res
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_layers() {
+        let log = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let (mut mailbox, actor_ref) = Mailbox::new(None);
+        mailbox.layer(LogLayer(log.clone()));
+        mailbox.layer(MaxLayer(10));
+        let (_, fut) = ActorRun::with_mailbox(MyActor(0), (mailbox, actor_ref.clone()));
+        let handle = tokio::spawn(fut);
+
+        for msg in [3, 50, 7] {
+            actor_ref.send(msg).await.unwrap();
+        }
+        actor_ref.stop(0).unwrap();
+        assert_eq!(handle.await.unwrap().unwrap().0, 10);
+        // The outer layer sees dropped messages too.
+        assert_eq!(*log.lock().unwrap(), [(0, 3, 3), (1, 50, 3), (2, 7, 10)]);
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_is_finished() {
+        let (actor_ref, fut) = SleepyActor::default().into_future(None);
+        let weak = actor_ref.downgrade();
+        let handle = tokio::spawn(fut);
+        actor_ref.send(20).await.unwrap();
+        actor_ref.close();
+        // The mailbox is closed, but the actor is still handling its last message.
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        assert!(actor_ref.is_closed());
+        assert!(!actor_ref.is_finished());
+
+        handle.await.unwrap().unwrap();
+        assert!(actor_ref.is_finished());
+        assert!(weak.is_finished());
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_capacity() {
+        for mailbox in [Mailbox::new(Some(1)), Mailbox::lifo(Some(1))] {
+            let (actor_ref, fut) = ActorRun::with_mailbox(MyActor(0), mailbox);
+            actor_ref.wait_for_capacity().await.unwrap();
+            actor_ref.try_send(1).unwrap();
+            let waiting = tokio::time::timeout(
+                std::time::Duration::from_millis(20),
+                actor_ref.wait_for_capacity(),
+            );
+            assert!(waiting.await.is_err());
+
+            let handle = tokio::spawn(fut);
+            actor_ref.wait_for_capacity().await.unwrap();
+            actor_ref.try_send(2).unwrap();
+
+            actor_ref.close();
+            assert_eq!(actor_ref.wait_for_capacity().await, Err(Closed));
+            assert_eq!(handle.await.unwrap().unwrap().0, 3);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_capacity_with_blocked_send() {
+        for mailbox in [Mailbox::new(Some(1)), Mailbox::lifo(Some(1))] {
+            let (actor_ref, fut) = ActorRun::with_mailbox(MyActor(0), mailbox);
+            actor_ref.try_send(1).unwrap();
+            // The waiter listens first, so it takes the wakeup from the slot freed below.
+            let waiter = tokio::spawn({
+                let actor_ref = actor_ref.clone();
+                async move { actor_ref.wait_for_capacity().await }
+            });
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            let sender = tokio::spawn({
+                let actor_ref = actor_ref.clone();
+                async move { actor_ref.send(2).await }
+            });
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+
+            let handle = tokio::spawn(fut);
+            waiter.await.unwrap().unwrap();
+            let sent = tokio::time::timeout(std::time::Duration::from_secs(1), sender);
+            sent.await.unwrap().unwrap().unwrap();
+
+            actor_ref.close();
+            assert_eq!(handle.await.unwrap().unwrap().0, 3);
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_trigger() {
+        let (trigger, triggered) = async_channel::unbounded::<()>();
+        let (done, fired) = async_channel::unbounded();
+        let actor = MyActor(0);
+        let (mut mailbox, actor_ref) = Mailbox::<MyActor>::new(None);
+        mailbox.set_trigger(
+            move || {
+                let triggered = triggered.clone();
+                async move {
+                    let _ = triggered.recv().await;
+                }
+            },
+            move |actor, _| {
+                actor.0 += 100;
+                let _ = done.try_send(());
+            },
+        );
+        let (actor_ref, fut) = ActorRun::with_mailbox(actor, (mailbox, actor_ref));
+        let handle = tokio::spawn(fut);
+
+        for _ in 0..2 {
+            trigger.send(()).await.unwrap();
+            fired.recv().await.unwrap();
+        }
+        actor_ref.send(3).await.unwrap();
+        actor_ref.stop(0).unwrap();
+
+        let res = handle.await;
+        assert_eq!(res.unwrap().unwrap().0, 203);
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_weighted_router() {
+        let (refs, _runs): (Vec<_>, Vec<_>) = (0..2).map(|_| MyActor(0).into_future(None)).unzip();
+        let router = Router::with_strategy(refs.clone(), Strategy::WeightedRoundRobin(vec![2, 1]));
+
+        for _ in 0..6 {
+            router.send(1).await.unwrap();
+        }
+        assert_eq!(refs[0].len(), 4);
+        assert_eq!(refs[1].len(), 2);
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_consistent_hash_router() {
+        let (refs, _runs): (Vec<_>, Vec<_>) = (0..4).map(|_| MyActor(0).into_future(None)).unzip();
+        let router = Router::with_strategy(refs.clone(), Strategy::consistent_hash(|msg| *msg));
+
+        let before: Vec<_> = (0..200)
+            .map(|key| router.route(&key).unwrap().clone())
+            .collect();
+        for (key, worker) in before.iter().enumerate() {
+            assert!(router.route(&key).unwrap().same_actor(worker));
+        }
+        assert!(refs.iter().all(|r| before.iter().any(|b| b.same_actor(r))));
+
+        assert!(router.remove_worker(&refs[1]));
+        for (key, worker) in before.iter().enumerate() {
+            let after = router.route(&key).unwrap();
+            assert!(!after.same_actor(&refs[1]));
+            if !worker.same_actor(&refs[1]) {
+                assert!(after.same_actor(worker));
+            }
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_router_resize() {
+        let (refs, _runs): (Vec<_>, Vec<_>) = (0..3).map(|_| MyActor(0).into_future(None)).unzip();
+        let router = std::sync::Arc::new(Router::new(vec![refs[0].clone()]));
+
+        let sender = router.clone();
+        let sending = tokio::spawn(async move {
+            for _ in 0..300 {
+                sender.send(1).await.unwrap();
+                tokio::task::yield_now().await;
+            }
+        });
+        router.add_worker(refs[1].clone());
+        router.add_worker(refs[2].clone());
+        tokio::task::yield_now().await;
+        assert!(router.remove_worker(&refs[0]));
+        assert!(!router.remove_worker(&refs[0]));
+        let len = refs[0].len();
+        sending.await.unwrap();
+
+        assert_eq!(router.len(), 2);
+        // Nothing was routed to the removed worker after it was removed.
+        assert_eq!(refs[0].len(), len);
+        assert_eq!(refs.iter().map(ActorRef::len).sum::<usize>(), 300);
+        assert!(!refs[1].is_empty() && !refs[2].is_empty());
+    }
+
+    #[cfg(feature = "remote")]
+    enum SumMessage {
+        Add(u8),
+        Sum(u32, async_oneshot_channel::Sender<u32>),
+    }
+
+    #[cfg(feature = "remote")]
+    impl From<u8> for SumMessage {
+        fn from(num: u8) -> Self {
+            Self::Add(num)
+        }
+    }
+
+    #[cfg(feature = "remote")]
+    struct SumActor(u32);
+
+    #[cfg(feature = "remote")]
+    impl Actor for SumActor {
+        type Error = ();
+        type Message = SumMessage;
+
+        async fn on_msg(&mut self, _: &WeakActorRef<Self>, msg: SumMessage) -> Result<(), ()> {
+            match msg {
+                SumMessage::Add(num) => self.0 += num as u32,
+                SumMessage::Sum(factor, reply) => {
+                    let _ = reply.send(self.0 * factor);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "remote")]
+    impl RemoteMessage for u8 {
+        fn encode(&self, buf: &mut Vec<u8>) {
+            buf.push(*self);
+        }
+
+        fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+            match bytes {
+                [num] => Ok(*num),
+                _ => Err(DecodeError),
+            }
+        }
+    }
+
+    #[cfg(feature = "remote")]
+    impl RemoteMessage for u32 {
+        fn encode(&self, buf: &mut Vec<u8>) {
+            buf.extend_from_slice(&self.to_be_bytes());
+        }
+
+        fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+            Ok(u32::from_be_bytes(
+                bytes.try_into().map_err(|_| DecodeError)?,
+            ))
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[cfg(feature = "remote")]
+    #[tokio::test]
+    async fn test_remote_actor() {
+        let (actor_ref, fut) = SumActor(0).into_future(None);
+        let handle = tokio::spawn(fut);
+        let server = RemoteServer::<_, u8>::new(actor_ref.clone()).on_ask(SumMessage::Sum);
+
+        let (client_io, server_io) = tokio::io::duplex(64);
+        let (server_read, server_write) = tokio::io::split(server_io);
+        let serving = tokio::spawn(async move { server.serve(server_read, server_write).await });
+        let (client_read, client_write) = tokio::io::split(client_io);
+        let (remote, connection) = RemoteActorRef::<u8>::connect(client_read, client_write);
+        let connection = tokio::spawn(connection);
+
+        for num in 1..=10 {
+            remote.send(num).await.unwrap();
+        }
+        let (first, second) = tokio::join!(remote.ask::<u32, u32>(2), remote.ask::<u32, u32>(3));
+        assert_eq!((first, second), (Ok(110), Ok(165)));
+
+        drop(remote);
+        connection.await.unwrap().unwrap();
+        serving.await.unwrap().unwrap();
+        assert!(actor_ref.stop(SumMessage::Add(0)).is_ok());
+        assert_eq!(handle.await.unwrap().unwrap().0, 55);
+    }
+
+    /// Collects into a buffer borrowed from the test.
+    struct Collector<'a>(&'a mut Vec<u32>);
+
+    impl ScopedActor for Collector<'_> {
+        type Error = ();
+        type Message = u32;
+
+        async fn on_msg(&mut self, msg: u32) -> Result<(), ()> {
+            self.0.push(msg);
+            Ok(())
+        }
+    }
+
+    /// Doubles each message and forwards it, and fails on 0.
+    struct Doubler<'a> {
+        next: ScopedRef<u32>,
+        factor: &'a u32,
+    }
+
+    impl ScopedActor for Doubler<'_> {
+        type Error = ();
+        type Message = u32;
+
+        async fn on_msg(&mut self, msg: u32) -> Result<(), ()> {
+            if msg == 0 {
+                return Err(());
+            }
+            self.next.send(msg * self.factor).await.map_err(drop)
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_scope() {
+        let factor = 2;
+        let mut collected = Vec::new();
+        let mut scope = Scope::new();
+        let collector = scope.spawn(Collector(&mut collected), None);
+        let doubler = scope.spawn(
+            Doubler {
+                next: collector,
+                factor: &factor,
+            },
+            Some(1),
+        );
+        let res = scope
+            .run(async move {
+                for num in 1..=3 {
+                    doubler.send(num).await.unwrap();
+                }
+                "done"
+            })
+            .await;
+        assert_eq!(res, Ok("done"));
+        // Every message made it through the pipeline before the scope ended.
+        assert_eq!(collected, [2, 4, 6]);
+
+        let mut scope = Scope::new();
+        let collector = scope.spawn(Collector(&mut collected), None);
+        let doubler = scope.spawn(
+            Doubler {
+                next: collector,
+                factor: &factor,
+            },
+            None,
+        );
+        let res = scope
+            .run(async move {
+                doubler.send(0).await.unwrap();
+                std::future::pending::<()>().await
+            })
+            .await;
+        assert_eq!(res, Err(()));
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_coalescing_mailbox() {
+        let (actor_ref, fut) =
+            ActorRun::with_mailbox(RecordActor(Vec::new()), Mailbox::coalescing(Some(3)));
+        // Readings of two sensors, each as sensor * 100 + reading.
+        actor_ref.send_coalesced("a", 101).await.unwrap();
+        actor_ref.send_coalesced("b", 201).await.unwrap();
+        actor_ref.send(7).await.unwrap();
+        // The mailbox is full, but updates still replace queued readings.
+        actor_ref.send_coalesced("a", 102).await.unwrap();
+        actor_ref.send_coalesced("a", 103).await.unwrap();
+        assert!(matches!(actor_ref.try_send(8), Err(TrySendError::Full(8))));
+        assert_eq!(actor_ref.len(), 3);
+
+        let handle = tokio::spawn(fut);
+        actor_ref.flush().await.unwrap();
+        // Once handled, a key is queued anew.
+        actor_ref.send_coalesced("a", 104).await.unwrap();
+        drop(actor_ref);
+        assert_eq!(handle.await.unwrap().unwrap().0, [103, 201, 7, 104]);
+
+        // Keys with the same hash only replace each other if they are equal.
+        #[derive(PartialEq, Eq)]
+        struct Colliding(u8);
+        impl std::hash::Hash for Colliding {
+            fn hash<H: std::hash::Hasher>(&self, _: &mut H) {}
+        }
+        let (actor_ref, fut) =
+            ActorRun::with_mailbox(RecordActor(Vec::new()), Mailbox::coalescing(None));
+        actor_ref.send_coalesced(Colliding(1), 1).await.unwrap();
+        actor_ref.send_coalesced(Colliding(2), 2).await.unwrap();
+        actor_ref.send_coalesced(Colliding(1), 3).await.unwrap();
+        actor_ref.send_coalesced("a", 4).await.unwrap();
+        drop(actor_ref);
+        assert_eq!(fut.await.unwrap().0, [3, 2, 4]);
+    }
+
+    /// Works in small steps on each message, until it is done or asked to stop.
+    struct SteppingActor(usize);
+
+    impl Actor for SteppingActor {
+        type Error = ();
+        type Message = usize;
+
+        async fn on_msg(&mut self, this: &WeakActorRef<Self>, steps: usize) -> Result<(), ()> {
+            for _ in 0..steps {
+                if this.stop_requested() {
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+                self.0 += 1;
+            }
+            Ok(())
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_stop_requested() {
+        let (actor_ref, fut) = SteppingActor(0).into_future(None);
+        let handle = tokio::spawn(fut);
+        actor_ref.send(10_000).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        assert!(!actor_ref.stop_requested());
+
+        actor_ref.stop(0).unwrap();
+        assert!(actor_ref.stop_requested());
+        let steps = handle.await.unwrap().unwrap().0;
+        assert!(steps > 0 && steps < 10_000, "{steps}");
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_generator_actor() {
+        let fib = |(a, b): &mut (u64, u64)| {
+            let next = *a;
+            (*a, *b) = (*b, *a + *b);
+            (next < 50).then_some(next)
+        };
+        let (actor_ref, run, outputs) = generator_actor((0, 1), fib, Some(2));
+        drop(actor_ref);
+        let handle = tokio::spawn(run);
+        let mut values = Vec::new();
+        while let Ok(value) = outputs.recv().await {
+            values.push(value);
+        }
+        assert_eq!(values, [0, 1, 1, 2, 3, 5, 8, 13, 21, 34]);
+        assert!(handle.await.unwrap().is_ok());
+
+        // An endless generator stops once its consumer is gone, or when it is stopped.
+        let count = |n: &mut u64| {
+            *n += 1;
+            Some(*n)
+        };
+        let (_actor_ref, run, outputs) = generator_actor(0, count, Some(1));
+        let handle = tokio::spawn(run);
+        assert_eq!(outputs.recv().await, Ok(1));
+        drop(outputs);
+        assert!(handle.await.unwrap().unwrap().into_inner() <= 3);
+
+        let (actor_ref, run, outputs) = generator_actor(0, count, None);
+        let handle = tokio::spawn(run);
+        assert_eq!(outputs.recv().await, Ok(1));
+        actor_ref.stop(()).unwrap();
+        let generated = handle.await.unwrap().unwrap().into_inner();
+        assert_eq!(outputs.len() as u64, generated - 1);
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_as_weak() {
+        let actor = MyActor(0);
+        let (actor_ref, fut) = actor.into_future(None);
+        let handle = tokio::spawn(fut);
+
+        actor_ref.as_weak().send(3).await.unwrap();
+        let weak = actor_ref.as_weak().clone();
+        assert!(weak.upgrade().is_some());
+
+        drop(actor_ref);
+
+        let res = handle.await;
+        assert_eq!(res.unwrap().unwrap().0, 3);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_upgrade_err() {
+        let actor = MyActor(0);
+        let (actor_ref, fut) = actor.into_future(None);
+        let handle = tokio::spawn(fut);
+        let weak = actor_ref.as_weak().clone();
+        assert!(weak.upgrade_err().is_ok());
+
+        let (sender, stop) = actor_ref.into_parts();
+        drop(stop);
+        assert_eq!(weak.upgrade_err().err(), Some(UpgradeError::StopSenderGone));
+        drop(sender);
+        assert!(handle.await.is_ok());
+
+        let actor = MyActor(0);
+        let (actor_ref, fut) = actor.into_future(None);
+        let handle = tokio::spawn(fut);
+        let weak = actor_ref.as_weak().clone();
+        let (sender, stop) = actor_ref.into_parts();
+        drop(sender);
+        let res: Result<ActorRef<MyActor>, _> = weak.try_into();
+        assert_eq!(res.err(), Some(UpgradeError::MessageSenderGone));
+        assert!(stop.send(0).is_ok());
+        assert!(handle.await.is_ok());
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_send_ack() {
+        let actor = MyActor(0);
+        let (actor_ref, fut) = actor.into_future(None);
+        let handle = tokio::spawn(fut);
+
+        assert_eq!(actor_ref.send_ack(3).await, Ok(()));
+        assert_eq!(actor_ref.send_ack(7).await, Ok(()));
+
+        actor_ref.stop(0).unwrap();
+
+        let res = handle.await;
+        assert_eq!(res.unwrap().unwrap().0, 10);
+        assert_eq!(actor_ref.send_ack(1).await, Err(Unacked));
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_send_ack_drain() {
+        let actor = MyActor(0);
+        let (actor_ref, fut) = actor.into_future(None);
+
+        let sender = actor_ref.clone();
+        let acked = tokio::spawn(async move { sender.send_ack(3).await });
+        while actor_ref.sender.is_empty() {
+            tokio::task::yield_now().await;
+        }
+        actor_ref.stop(0).unwrap();
+
+        let handle = tokio::spawn(fut);
+        assert_eq!(acked.await.unwrap(), Ok(()));
+        assert_eq!(handle.await.unwrap().unwrap().0, 3);
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    #[allow(deprecated)]
+    async fn test_mailbox_receiver() {
+        let (mailbox, actor_ref) = Mailbox::<MyActor>::new(None);
+        actor_ref.send(1).await.unwrap();
+        actor_ref.send(2).await.unwrap();
+        assert_eq!(mailbox.receiver.len(), 2);
+        assert_eq!(mailbox.receiver.recv().await, Ok(1));
+        assert_eq!(mailbox.recv_msg().await, Some(2));
+        assert!(mailbox.receiver.is_empty());
+
+        mailbox.receiver.close();
+        assert!(actor_ref.send(3).await.is_err());
+        assert!(mailbox.receiver.try_recv().is_err());
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_replace_state() {
+        let actor = MyActor(0);
+        let (actor_ref, fut) = actor.into_future(None);
+        let handle = tokio::spawn(fut);
+
+        actor_ref.send_ack(3).await.unwrap();
+        assert!(actor_ref.replace_state(MyActor(100)).is_ok());
+        actor_ref.send_ack(1).await.unwrap();
+        actor_ref.stop(0).unwrap();
+
+        let res = handle.await.unwrap().unwrap();
+        assert_eq!(res.0, 101);
+
+        let res = actor_ref.replace_state(MyActor(0));
+        assert_eq!(res.unwrap_err().0, 0);
+    }
+
+    #[tokio::test]
+    async fn test_send_iter() {
+        let actor = RecordActor(Vec::new());
+        let (actor_ref, fut) = actor.into_future(Some(2));
+
+        let report = actor_ref.send_iter(1..=5, SendMode::BestEffort).await;
+        assert_eq!(report.sent, 2);
+        assert_eq!(report.remaining, vec![3, 4, 5]);
+
+        let handle = tokio::spawn(fut);
+        let report = actor_ref
+            .send_iter(report.remaining, SendMode::BlockAll)
+            .await;
+        assert_eq!(report.sent, 3);
+        assert!(report.remaining.is_empty());
+
+        actor_ref.stop(0).unwrap();
+        let res = handle.await.unwrap().unwrap();
+        assert_eq!(res.0, vec![1, 2, 3, 4, 5]);
+
+        let report = actor_ref.send_iter(6..=7, SendMode::BlockAll).await;
+        assert_eq!(report.sent, 0);
+        assert_eq!(report.remaining, vec![6, 7]);
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_mailbox_recv() {
+        let (mailbox, actor_ref) = Mailbox::<MyActor>::new(None);
+        actor_ref.send(1).await.unwrap();
+        assert!(matches!(
+            mailbox.recv().await,
+            MailboxEvent::Message(Some(1))
+        ));
+
+        actor_ref.send(2).await.unwrap();
+        actor_ref.stop(3).unwrap();
+        assert!(matches!(mailbox.recv().await, MailboxEvent::Stop(Some(3))));
+
+        drop(actor_ref);
+        mailbox.close();
+        assert!(matches!(mailbox.recv_msg().await, Some(2)));
+        assert!(matches!(mailbox.recv().await, MailboxEvent::Stop(None)));
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_same_actor() {
+        let (a, _fut_a) = MyActor(0).into_future(None);
+        let (b, _fut_b) = MyActor(0).into_future(None);
+
+        let weak = a.downgrade();
+        assert!(a.same_actor(&a.clone()));
+        assert!(a.same_actor(&weak.upgrade().unwrap()));
+        assert!(a.same_actor_weak(&weak));
+        assert!(!a.same_actor(&b));
+        assert!(!b.same_actor_weak(&weak));
+        assert!(!weak.same_actor(b.as_weak()));
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_upgrade_all() {
+        let (a, _fut_a) = MyActor(0).into_future(None);
+        let (b, _fut_b) = MyActor(0).into_future(None);
+
+        let weaks = downgrade_all(&[a.clone(), b]);
+        let upgraded = upgrade_all(&weaks);
+        assert!(upgraded[0].as_ref().unwrap().same_actor(&a));
+        assert!(upgraded[1].is_none());
+
+        let alive = upgrade_alive(&weaks);
+        assert_eq!(alive.len(), 1);
+        assert!(alive[0].same_actor(&a));
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_stop_idempotent() {
+        let (actor_ref, fut) = RecordActor(Vec::new()).into_future(None);
+        actor_ref.send(1).await.unwrap();
+        actor_ref.stop_idempotent(2);
+        actor_ref.stop_idempotent(3);
+        assert_eq!(actor_ref.stop(4), Err(4));
+
+        let res = fut.await.unwrap();
+        assert_eq!(res.0, vec![1]);
+        actor_ref.stop_idempotent(5);
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_try_stop() {
+        let (actor_ref, fut) = RecordActor(Vec::new()).into_future(None);
+        actor_ref.send(1).await.unwrap();
+        assert!(actor_ref.try_stop());
+        assert!(!actor_ref.try_stop());
+        assert_eq!(fut.await.unwrap().0, vec![1]);
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_snapshot_mailbox() {
+        let (actor_ref, fut) = ActorRun::with_mailbox(RecordActor(Vec::new()), Mailbox::lifo(None));
+        actor_ref.send(1).await.unwrap();
+        actor_ref.send(2).await.unwrap();
+        assert_eq!(actor_ref.snapshot_mailbox(), Ok(vec![2, 1]));
+        assert_eq!(actor_ref.len(), 2);
+        drop(fut);
+
+        let (actor_ref, _fut) = RecordActor(Vec::new()).into_future(None);
+        assert_eq!(actor_ref.snapshot_mailbox(), Err(SnapshotUnsupported));
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_replay() {
+        let (mut mailbox, actor_ref) = Mailbox::new(None);
+        let recorder = mailbox.start_recording();
+        let (actor_ref, fut) =
+            ActorRun::with_mailbox(RecordActor(Vec::new()), (mailbox, actor_ref));
+        let handle = tokio::spawn(fut);
+        actor_ref.send(1).await.unwrap();
+        actor_ref.send(2).await.unwrap();
+        actor_ref.stop(0).unwrap();
+        actor_ref.send(3).await.ok();
+        let original = handle.await.unwrap().unwrap();
+
+        let log = recorder.entries();
+        let replayed = replay(RecordActor(Vec::new()), log.clone()).await.unwrap();
+        assert_eq!(replayed.0, original.0);
+        assert_eq!(log.last(), Some(&Recorded::Stop(Some(0))));
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_send_receipt() {
+        let (actor_ref, fut) = RecordActor(Vec::new()).into_future(None);
+
+        let receipt = actor_ref.send_receipt(1).await.unwrap();
+        assert_eq!(receipt.depth_at_enqueue, 1);
+        let receipt = actor_ref.send_receipt(2).await.unwrap();
+        assert_eq!(receipt.depth_at_enqueue, 2);
+
+        actor_ref.stop(0).unwrap();
+        let res = tokio::spawn(fut).await.unwrap().unwrap();
+        assert_eq!(res.0, vec![1, 2]);
+        assert_eq!(actor_ref.send_receipt(3).await, Err(3));
+    }
+
+    /// Waits for a signal before completing `on_start`, and fails to start if the signal is dropped.
+    struct SlowStartActor(Option<async_oneshot_channel::Receiver<()>>);
+
+    impl Actor for SlowStartActor {
+        type Error = ();
+        type Message = ();
+
+        async fn on_start(&mut self, _: &WeakActorRef<Self>) -> Result<(), Self::Error> {
+            match self.0.take() {
+                Some(signal) => signal.recv().await.ok_or(()),
+                None => Ok(()),
+            }
+        }
+
+        async fn on_msg(&mut self, _: &WeakActorRef<Self>, _: ()) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_wait_started() {
+        let (signal, started) = async_oneshot_channel::oneshot();
+        let actor = SlowStartActor(Some(started));
+        let (actor_ref, fut) = actor.into_future(None);
+        let handle = tokio::spawn(fut);
+
+        actor_ref.send(()).await.unwrap();
+        tokio::task::yield_now().await;
+        assert!(!actor_ref.is_started());
+
+        signal.send(()).unwrap();
+        assert_eq!(actor_ref.wait_started().await, Ok(()));
+        assert!(actor_ref.is_started());
+
+        drop(actor_ref);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_wait_started_failed() {
+        let (signal, started) = async_oneshot_channel::oneshot::<()>();
+        let actor = SlowStartActor(Some(started));
+        let (actor_ref, fut) = actor.into_future(None);
+        let handle = tokio::spawn(fut);
+
+        drop(signal);
+        assert_eq!(actor_ref.wait_started().await, Err(NotStarted));
+        assert!(!actor_ref.is_started());
+        assert!(handle.await.unwrap().is_err());
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_drain_and_stop() {
+        let actor = RecordActor(Vec::new());
+        let (actor_ref, fut) = actor.into_future(None);
+
+        for i in 0..3 {
+            actor_ref.send(i).await.unwrap();
+        }
+        // Both calls are made before the actor stops, so both see it drain.
+        let other_ref = actor_ref.clone();
+        let (drained, other, res) =
+            tokio::join!(actor_ref.drain_and_stop(), other_ref.drain_and_stop(), fut);
+        assert_eq!(drained, Ok(()));
+        assert_eq!(other, Ok(()));
+
+        assert!(actor_ref.is_closed());
+        assert!(actor_ref.send(3).await.is_err());
+        assert_eq!(res.unwrap().0, vec![0, 1, 2]);
+        assert_eq!(
+            actor_ref.drain_and_stop().await,
+            Err(DrainStopError::Exited)
+        );
+
+        let (actor_ref, fut) = RecordActor(Vec::new()).into_future(None);
+        let handle = tokio::spawn(fut);
+        actor_ref.stop(0).unwrap();
+        let drained = actor_ref.drain_and_stop().await;
+        assert_eq!(drained, Err(DrainStopError::AlreadyStopping));
+        handle.await.unwrap().unwrap();
+
+        let (actor_ref, fut) = PlusOneActor.into_future(None);
+        let handle = tokio::spawn(fut);
+        actor_ref.send(PlusOneActorMessage::Stop).await.unwrap();
+        assert_eq!(
+            actor_ref.drain_and_stop().await,
+            Err(DrainStopError::Failed)
+        );
+        assert!(handle.await.unwrap().is_err());
+    }
+
+    struct Config {
+        step: usize,
+    }
+
+    /// Adds the configured step to its count for every message, and to the steps it saw once it
+    /// stops.
+    struct StepActor(usize, Vec<usize>);
+
+    impl ContextActor for StepActor {
+        type Ctx = Config;
+        type Error = ();
+        type Message = ();
+
+        async fn on_start(
+            &mut self,
+            _: &WeakActorRef<WithContext<Self>>,
+            ctx: &Config,
+        ) -> Result<(), Self::Error> {
+            self.1.push(ctx.step);
+            Ok(())
+        }
+
+        async fn on_msg(
+            &mut self,
+            _: &WeakActorRef<WithContext<Self>>,
+            _: (),
+            ctx: &Config,
+        ) -> Result<(), Self::Error> {
+            self.0 += ctx.step;
+            Ok(())
+        }
+
+        async fn on_stop(&mut self, _: Option<()>, ctx: &Config) -> Result<(), Self::Error> {
+            self.1.push(ctx.step);
+            Ok(())
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_context() {
+        let config = std::sync::Arc::new(Config { step: 5 });
+        let (actor_ref, fut) = StepActor(0, Vec::new()).into_future_with_ctx(None, config.clone());
+        let handle = tokio::spawn(fut);
+
+        actor_ref.send(()).await.unwrap();
+        actor_ref.send(()).await.unwrap();
+        drop(actor_ref);
+        let actor = handle.await.unwrap().unwrap();
+        assert!(std::sync::Arc::ptr_eq(actor.ctx(), &config));
+        let actor = actor.into_inner();
+        assert_eq!(actor.0, 10);
+        assert_eq!(actor.1, [5, 5]);
+    }
+
+    /// Counts its `on_stop` calls, and never finishes handling a message.
+    struct StuckActor(usize);
+
+    impl Actor for StuckActor {
+        type Error = ();
+        type Message = ();
+
+        async fn on_msg(&mut self, _: &WeakActorRef<Self>, _: ()) -> Result<(), Self::Error> {
+            std::future::pending().await
+        }
+
+        async fn on_stop(&mut self, _: Option<()>) -> Result<(), Self::Error> {
+            self.0 += 1;
+            Ok(())
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_abort() {
+        let (actor_ref, fut) = StuckActor(0).into_future(None);
+        let abort = fut.abort_handle();
+        let handle = tokio::spawn(fut);
+
+        actor_ref.send(()).await.unwrap();
+        actor_ref.send(()).await.unwrap();
+        assert!(!abort.is_aborted());
+        abort.abort();
+
+        let actor = handle.await.unwrap().unwrap();
+        assert!(abort.is_aborted());
+        assert_eq!(actor.0, 1);
+        assert!(actor_ref.send(()).await.is_err());
+        assert!(actor_ref.is_aborted());
+
+        // Dropping the run before it finishes aborts the actor too, unlike a clean stop.
+        let (actor_ref, fut) = StuckActor(0).into_future(None);
+        let handle = tokio::spawn(fut);
+        actor_ref.wait_started().await.unwrap();
+        handle.abort();
+        assert!(handle.await.is_err_and(|err| err.is_cancelled()));
+        assert_eq!(
+            actor_ref.drain_and_stop().await,
+            Err(DrainStopError::Exited)
+        );
+        assert!(actor_ref.is_aborted());
+        let (actor_ref, fut) = StuckActor(0).into_future(None);
+        actor_ref.stop(()).unwrap();
+        fut.await.unwrap();
+        assert!(!actor_ref.is_aborted());
+
+        // An abort that arrives while `on_stop` runs neither interrupts it nor runs it again.
+        let (entered, entered_rx) = async_channel::unbounded();
+        let (release_tx, release) = async_channel::unbounded();
+        let actor = SlowStopActor {
+            stops: 0,
+            entered,
+            release,
+        };
+        let (actor_ref, fut) = actor.into_future(None);
+        let abort = fut.abort_handle();
+        let handle = tokio::spawn(fut);
+        actor_ref.stop(()).unwrap();
+        entered_rx.recv().await.unwrap();
+        abort.abort();
+        release_tx.send(()).await.unwrap();
+        let actor = handle.await.unwrap().unwrap();
+        assert_eq!(actor.stops, 1);
+        assert!(!actor_ref.is_aborted());
+    }
+
+    /// Signals when its `on_stop` starts, then waits to be released, counting its calls.
+    struct SlowStopActor {
+        stops: usize,
+        entered: async_channel::Sender<()>,
+        release: async_channel::Receiver<()>,
+    }
+
+    impl Actor for SlowStopActor {
+        type Error = ();
+        type Message = ();
+
+        async fn on_msg(&mut self, _: &WeakActorRef<Self>, _: ()) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        async fn on_stop(&mut self, _: Option<()>) -> Result<(), Self::Error> {
+            self.stops += 1;
+            let _ = self.entered.send(()).await;
+            let _ = self.release.recv().await;
+            Ok(())
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[cfg(debug_assertions)]
+    #[tokio::test]
+    async fn test_unpolled_warning() {
+        let (_actor_ref, fut) = MyActor(0).into_future(None);
+        assert!(fut.never_polled());
+
+        let (_actor_ref, mut fut) = MyActor(0).into_future(None);
+        let polled = tokio::time::timeout(std::time::Duration::from_millis(1), &mut fut);
+        assert!(polled.await.is_err());
+        assert!(!fut.never_polled());
+
+        let (_actor_ref, fut) = MyActor(0).into_future(None);
+        assert!(!fut.run_until_started().await.unwrap().never_polled());
+    }
+
+    /// Sleeps for the given number of milliseconds for every message, and counts handler timeouts.
+    #[derive(Default)]
+    struct SleepyActor {
+        handled: Vec<u64>,
+        timeouts: usize,
+    }
+
+    impl Actor for SleepyActor {
+        type Error = ();
+        type Message = u64;
+
+        async fn on_msg(&mut self, _: &WeakActorRef<Self>, ms: u64) -> Result<(), Self::Error> {
+            tokio::time::sleep(std::time::Duration::from_millis(ms)).await;
+            self.handled.push(ms);
+            Ok(())
+        }
+
+        async fn on_msg_timeout(&mut self, _: std::time::Duration) -> Result<(), Self::Error> {
+            self.timeouts += 1;
+            Ok(())
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_handler_timeout() {
+        let (mut mailbox, actor_ref) = Mailbox::new(None);
+        mailbox.set_handler_timeout(std::time::Duration::from_millis(50), tokio::time::sleep);
+        let (actor_ref, fut) = ActorRun::with_mailbox(SleepyActor::default(), (mailbox, actor_ref));
+        let handle = tokio::spawn(fut);
+
+        assert_eq!(actor_ref.send_ack(1).await, Ok(()));
+        assert_eq!(actor_ref.send_ack(10_000).await, Err(Unacked));
+        actor_ref.send(2).await.unwrap();
+
+        actor_ref.stop(0).unwrap();
+        let res = handle.await.unwrap().unwrap();
+        assert_eq!(res.handled, vec![1, 2]);
+        assert_eq!(res.timeouts, 1);
+    }
+
+    /// Records every message, and counts down to zero with follow-ups for messages below 10.
+    struct CountdownActor(Vec<usize>);
+
+    impl Actor for CountdownActor {
+        type Error = ();
+        type Message = usize;
+
+        async fn on_msg(&mut self, _: &WeakActorRef<Self>, _: usize) -> Result<(), Self::Error> {
+            unreachable!("on_msg_emit is overridden")
+        }
+
+        async fn on_msg_emit(
+            &mut self,
+            _: &WeakActorRef<Self>,
+            msg: usize,
+        ) -> Result<Vec<usize>, Self::Error> {
+            self.0.push(msg);
+            match msg {
+                1..10 => Ok(vec![msg - 1, msg - 1]),
+                _ => Ok(Vec::new()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_on_msg_emit() {
+        let (actor_ref, fut) = CountdownActor(Vec::new()).into_future(Some(1));
+        actor_ref.send(2).await.unwrap();
+        let handle = tokio::spawn(fut);
+        actor_ref.send(10).await.unwrap();
+
+        actor_ref.stop(0).unwrap();
+        let res = handle.await.unwrap().unwrap();
+        assert_eq!(res.0, vec![2, 1, 1, 0, 0, 0, 0, 10]);
+    }
+
+    /// Sends itself two messages for every message above zero, recording which sends failed.
+    #[cfg(debug_assertions)]
+    #[derive(Default)]
+    struct SelfSendActor {
+        handled: Vec<usize>,
+        failed: Vec<usize>,
+    }
+
+    #[cfg(debug_assertions)]
+    impl Actor for SelfSendActor {
+        type Error = ();
+        type Message = usize;
+
+        async fn on_msg(
+            &mut self,
+            this: &WeakActorRef<Self>,
+            msg: usize,
+        ) -> Result<(), Self::Error> {
+            self.handled.push(msg);
+            if msg > 0 {
+                for _ in 0..2 {
+                    match this.send(msg - 1).await {
+                        Ok(()) => {}
+                        Err(WeakSendError::WouldBlock(SelfSendWouldBlock(msg))) => {
+                            self.failed.push(msg)
+                        }
+                        Err(WeakSendError::Closed(_)) => return Err(()),
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    // Release builds do not detect self-sends, so this would deadlock.
+    #[cfg(debug_assertions)]
+    #[tokio::test]
+    async fn test_self_send_would_block() {
+        let (actor_ref, fut) = SelfSendActor::default().into_future(Some(1));
+        let handle = tokio::spawn(fut);
+        assert_eq!(actor_ref.send_ack(2).await, Ok(()));
+        assert_eq!(actor_ref.send_ack(0).await, Ok(()));
+
+        actor_ref.stop(0).unwrap();
+        let res = handle.await.unwrap().unwrap();
+        assert_eq!(res.handled, vec![2, 1, 0, 0]);
+        assert_eq!(res.failed, vec![1, 0]);
+    }
+
+    /// Records every message along with its correlation id, if it was sent with metadata.
+    struct MetaActor(Vec<(usize, Option<Option<u64>>)>);
+
+    impl Actor for MetaActor {
+        type Error = ();
+        type Message = usize;
+
+        async fn on_msg(&mut self, _: &WeakActorRef<Self>, _: usize) -> Result<(), Self::Error> {
+            unreachable!("on_msg_meta is overridden")
+        }
+
+        async fn on_msg_meta(
+            &mut self,
+            _: &WeakActorRef<Self>,
+            msg: usize,
+            meta: Option<Meta>,
+        ) -> Result<Vec<usize>, Self::Error> {
+            self.0.push((msg, meta.map(|meta| meta.correlation_id)));
+            Ok(Vec::new())
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_send_enveloped() {
+        let (actor_ref, fut) = MetaActor(Vec::new()).into_future(None);
+        let before = std::time::Instant::now();
+        let env = Envelope::new(1).with_correlation_id(7);
+        assert!(env.meta.sent_at >= before);
+
+        actor_ref.send_enveloped(env).await.unwrap();
+        actor_ref.send(2).await.unwrap();
+        actor_ref.send_enveloped(Envelope::new(3)).await.unwrap();
+
+        actor_ref.stop(0).unwrap();
+        let res = fut.await.unwrap();
+        assert_eq!(res.0, vec![(1, Some(Some(7))), (2, None), (3, Some(None))]);
+        let err = actor_ref
+            .send_enveloped(Envelope::new(4).with_reply_token(5))
+            .await;
+        assert_eq!(err.unwrap_err().meta.reply_token, Some(5));
+    }
+
+    /// Spawns a child for every message and forwards the message to it, and collects what its
+    /// children recorded once they have stopped.
+    #[derive(Default)]
+    struct ParentActor {
+        children: Vec<ChildHandle<RecordActor>>,
+        runs: Vec<tokio::task::JoinHandle<Result<RecordActor, RunError<RecordActor>>>>,
+        recorded: Vec<Vec<usize>>,
+    }
+
+    impl Actor for ParentActor {
+        type Error = ();
+        type Message = usize;
+
+        async fn on_msg(
+            &mut self,
+            this: &WeakActorRef<Self>,
+            msg: usize,
+        ) -> Result<(), Self::Error> {
+            let mut run = None;
+            let child = this.spawn_child(RecordActor(Vec::new()), None, |fut| {
+                run = Some(tokio::spawn(fut))
+            });
+            child.actor_ref().send(msg).await.unwrap();
+            child.actor_ref().send(msg + 1).await.unwrap();
+            self.children.push(child);
+            self.runs.push(run.unwrap());
+            Ok(())
+        }
+
+        async fn on_stop(&mut self, _: Option<usize>) -> Result<(), Self::Error> {
+            for child in &self.children {
+                child.wait_exited().await;
+            }
+            for run in self.runs.drain(..) {
+                self.recorded
+                    .push(run.await.unwrap().map_err(RunError::into_error)?.0);
+            }
+            Ok(())
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_spawn_child() {
+        let (actor_ref, fut) = ParentActor::default().into_future(None);
+        let handle = tokio::spawn(fut);
+        actor_ref.send(1).await.unwrap();
+        assert_eq!(actor_ref.send_ack(10).await, Ok(()));
+
+        actor_ref.stop(0).unwrap();
+        let res = handle.await.unwrap().unwrap();
+        assert_eq!(res.recorded, vec![vec![1, 2], vec![10, 11]]);
+        assert!(res
+            .children
+            .iter()
+            .all(|child| child.actor_ref().is_closed()));
+    }
+
+    /// Emits every message doubled on its output channel.
+    struct DoublerActor;
+
+    impl Actor for DoublerActor {
+        type Error = ();
+        type Message = usize;
+
+        async fn on_msg(
+            &mut self,
+            this: &WeakActorRef<Self>,
+            msg: usize,
+        ) -> Result<(), Self::Error> {
+            this.emit(msg * 2).await.map_err(|_| ())
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_outputs() {
+        let (mut mailbox, actor_ref) = Mailbox::new(None);
+        mailbox.set_outputs::<usize>(Some(1));
+        let (actor_ref, fut) = ActorRun::with_mailbox(DoublerActor, (mailbox, actor_ref));
+        let outputs = actor_ref.outputs::<usize>().unwrap();
+        assert!(actor_ref.outputs::<u8>().is_none());
+        let handle = tokio::spawn(fut);
+
+        actor_ref.send(1).await.unwrap();
+        actor_ref.send(2).await.unwrap();
+        actor_ref.stop(0).unwrap();
+
+        let mut received = Vec::new();
+        while let Ok(out) = outputs.recv().await {
+            received.push(out);
+        }
+        assert_eq!(received, vec![2, 4]);
+        assert!(handle.await.unwrap().is_ok());
+
+        let (actor_ref, fut) = DoublerActor.into_future(None);
+        assert!(actor_ref.outputs::<usize>().is_none());
+        actor_ref.send(1).await.unwrap();
+        assert!(fut.await.is_err());
+    }
+
+    /// Broadcasts every message as an event, recording how many subscribers each one reached.
+    struct BroadcastActor(Vec<usize>);
+
+    impl Actor for BroadcastActor {
+        type Error = ();
+        type Message = usize;
+
+        async fn on_msg(&mut self, this: &WeakActorRef<Self>, msg: usize) -> Result<(), ()> {
+            self.0.push(this.broadcast(msg));
+            Ok(())
+        }
+    }
+
+    impl Observable for BroadcastActor {
+        type Event = usize;
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_subscribe_events() {
+        let (actor_ref, fut) = BroadcastActor(Vec::new()).into_future(None);
+        let first = actor_ref.subscribe_events();
+        let second = actor_ref.subscribe_events();
+        let handle = tokio::spawn(fut);
+
+        actor_ref.send(1).await.unwrap();
+        actor_ref.flush().await.unwrap();
+        drop(second);
+        actor_ref.send(2).await.unwrap();
+        actor_ref.stop(0).unwrap();
+
+        let mut received = Vec::new();
+        while let Ok(event) = first.recv().await {
+            received.push(event);
+        }
+        assert_eq!(received, [1, 2]);
+        assert_eq!(handle.await.unwrap().unwrap().0, [2, 1]);
+        assert!(actor_ref.subscribe_events().recv().await.is_err());
+    }
+
+    #[derive(Debug, Clone)]
+    /// Appends every batch of entries, failing partway through a batch with a zero in it.
+    struct LedgerActor(Vec<u32>);
+
+    impl Actor for LedgerActor {
+        type Error = ();
+        type Message = Vec<u32>;
+
+        async fn on_msg(&mut self, _: &WeakActorRef<Self>, batch: Vec<u32>) -> Result<(), ()> {
+            for entry in batch {
+                self.0.push(entry);
+                if entry == 0 {
+                    return Err(());
+                }
+            }
+            Ok(())
+        }
+
+        fn on_error(&mut self, _: &()) -> ErrorAction {
+            ErrorAction::Rollback
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_transactional() {
+        let (mut mailbox, actor_ref) = Mailbox::new(None);
+        mailbox.set_transactional();
+        let (actor_ref, fut) =
+            ActorRun::with_mailbox(LedgerActor(Vec::new()), (mailbox, actor_ref));
+        let handle = tokio::spawn(fut);
+        actor_ref.send(vec![1, 2]).await.unwrap();
+        assert_eq!(actor_ref.send_ack(vec![3, 0, 4]).await, Err(Unacked));
+        actor_ref.send(vec![5]).await.unwrap();
+        actor_ref.stop(vec![]).unwrap();
+        assert_eq!(handle.await.unwrap().unwrap().0, [1, 2, 5]);
+
+        // Without a snapshot to restore, rolling back fails the actor.
+        let (actor_ref, fut) = LedgerActor(Vec::new()).into_future(None);
+        actor_ref.send(vec![0]).await.unwrap();
+        assert!(fut.await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_send_priority() {
+        let (actor_ref, fut) = LedgerActor(Vec::new()).into_future(Some(1));
+        actor_ref.send(vec![1]).await.unwrap();
+        assert_eq!(
+            actor_ref.try_send(vec![2]),
+            Err(TrySendError::Full(vec![2]))
+        );
+        actor_ref.send_priority(vec![9]).unwrap();
+        let handle = tokio::spawn(fut);
+        actor_ref.flush().await.unwrap();
+        actor_ref.stop(vec![]).unwrap();
+        assert_eq!(actor_ref.send_priority(vec![8]), Err(vec![8]));
+        assert_eq!(handle.await.unwrap().unwrap().0, [9, 1]);
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_send_priority_flush() {
+        let (actor_ref, fut) = SleepyActor::default().into_future(None);
+        for _ in 0..3 {
+            actor_ref.send(5).await.unwrap();
+        }
+        let mut flush = Box::pin(actor_ref.flush());
+        let sent = tokio::time::timeout(std::time::Duration::from_millis(1), &mut flush);
+        assert!(sent.await.is_err());
+        actor_ref.send_priority(1).unwrap();
+        actor_ref.send_priority(1).unwrap();
+        let handle = tokio::spawn(fut);
+        // Express messages were not queued when the flush was received, so it still waits for
+        // every queued message.
+        flush.await.unwrap();
+        assert_eq!(actor_ref.len(), 0);
+        actor_ref.stop(0).unwrap();
+        assert_eq!(handle.await.unwrap().unwrap().handled, [1, 1, 5, 5, 5]);
+    }
+
+    #[derive(Default)]
+    /// Records every message, along with whether the mailbox was closed when it was handled.
+    struct ClosedActor(Vec<(u32, bool)>);
+
+    impl Actor for ClosedActor {
+        type Error = ();
+        type Message = u32;
+
+        async fn on_msg(&mut self, this: &WeakActorRef<Self>, msg: u32) -> Result<(), ()> {
+            let closed = this.upgrade().is_none_or(|actor_ref| actor_ref.is_closed());
+            self.0.push((msg, closed));
+            Ok(())
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_send_priority_stop() {
+        let (actor_ref, fut) = ClosedActor::default().into_future(None);
+        actor_ref.send(1).await.unwrap();
+        actor_ref.send_priority(2).unwrap();
+        actor_ref.stop(0).unwrap();
+        // The stop is received first, and the express message is drained ahead of the mailbox.
+        assert_eq!(fut.await.unwrap().0, [(2, true), (1, true)]);
+
+        let (actor_ref, fut) = ClosedActor::default().into_future(None);
+        actor_ref.send(1).await.unwrap();
+        actor_ref.send_priority(2).unwrap();
+        let (backlog, res) = tokio::join!(actor_ref.take_backlog(), fut);
+        assert_eq!(backlog, [2, 1]);
+        assert!(res.unwrap().0.is_empty());
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_take_backlog() {
+        let (actor_ref, fut) = LedgerActor(Vec::new()).into_future(None);
+        actor_ref.send(vec![1]).await.unwrap();
+        actor_ref.send(vec![2]).await.unwrap();
+        let handle = tokio::spawn(fut);
+        assert_eq!(actor_ref.take_backlog().await, [vec![1], vec![2]]);
+        assert!(handle.await.unwrap().unwrap().0.is_empty());
+        assert!(actor_ref.send(vec![3]).await.is_err());
+        assert!(actor_ref.take_backlog().await.is_empty());
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_traced() {
+        let (mut mailbox, actor_ref) = Mailbox::new(None);
+        mailbox.set_traced();
+        let (actor_ref, fut) =
+            ActorRun::with_mailbox(LedgerActor(Vec::new()), (mailbox, actor_ref));
+        actor_ref.send(vec![1]).await.unwrap();
+        actor_ref.send(vec![2, 0]).await.unwrap();
+        let err = fut.with_failed_message().await.unwrap_err();
+        assert!(matches!(err.error, RunError::Failed(())));
+        assert_eq!(err.message.as_deref(), Some("[2, 0]"));
+
+        let (actor_ref, fut) = LedgerActor(Vec::new()).into_future(None);
+        actor_ref.send(vec![0]).await.unwrap();
+        assert_eq!(fut.with_failed_message().await.unwrap_err().message, None);
+    }
+
+    static SHED: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+    /// Records every message, and counts shed messages in [`SHED`].
+    struct ShedActor(Vec<usize>);
+
+    impl Actor for ShedActor {
+        type Error = ();
+        type Message = usize;
+
+        async fn on_msg(&mut self, _: &WeakActorRef<Self>, msg: usize) -> Result<(), Self::Error> {
+            self.0.push(msg);
+            Ok(())
+        }
+
+        fn on_shed(_: &usize) {
+            SHED.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shed() {
+        let (mut mailbox, actor_ref) = Mailbox::new(Some(10));
+        mailbox.set_shed_above(2);
+        let (actor_ref, fut) = ActorRun::with_mailbox(ShedActor(Vec::new()), (mailbox, actor_ref));
+        actor_ref.send(1).await.unwrap();
+        actor_ref.send(2).await.unwrap();
+        assert_eq!(actor_ref.send(3).await, Err(3));
+        assert_eq!(actor_ref.try_send(4), Err(TrySendError::Shed(4)));
+        assert_eq!(SHED.load(std::sync::atomic::Ordering::Relaxed), 2);
+
+        let handle = tokio::spawn(fut);
+        while !actor_ref.is_empty() {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(actor_ref.send_ack(5).await, Ok(()));
+        actor_ref.stop(0).unwrap();
+        assert_eq!(handle.await.unwrap().unwrap().0, vec![1, 2, 5]);
+    }
+
+    #[tokio::test]
+    async fn test_would_accept() {
+        // Sending nothing that would be shed leaves `SHED` to `test_shed`.
+        let (mut mailbox, actor_ref) = Mailbox::new(Some(3));
+        mailbox.set_shed_above(1);
+        let (actor_ref, _fut) = ActorRun::with_mailbox(ShedActor(Vec::new()), (mailbox, actor_ref));
+        assert_eq!(actor_ref.would_accept(), Admission::Accept);
+        actor_ref.send(1).await.unwrap();
+        assert_eq!(actor_ref.would_accept(), Admission::Backpressure(1));
+
+        let (actor_ref, fut) = MyActor(0).into_future(Some(2));
+        actor_ref.send(1).await.unwrap();
+        assert_eq!(actor_ref.would_accept(), Admission::Accept);
+        actor_ref.send(2).await.unwrap();
+        assert_eq!(actor_ref.would_accept(), Admission::Backpressure(2));
+        actor_ref.stop(0).unwrap();
+        assert_eq!(actor_ref.would_accept(), Admission::Closed);
+        assert_eq!(fut.await.unwrap().0, 3);
+
+        let (actor_ref, fut) = ActorRun::with_mailbox(MyActor(0), Mailbox::lifo(Some(1)));
+        actor_ref.send(1).await.unwrap();
+        assert_eq!(actor_ref.would_accept(), Admission::Backpressure(1));
+        drop(fut);
+        assert_eq!(actor_ref.would_accept(), Admission::Closed);
+    }
+
+    /// Fails on `0`, and logs every handled message, then `None` once stopped.
+    struct DrainActor(std::sync::Arc<std::sync::Mutex<Vec<Option<usize>>>>);
+
+    impl Actor for DrainActor {
+        type Error = ();
+        type Message = usize;
+
+        async fn on_msg(&mut self, _: &WeakActorRef<Self>, msg: usize) -> Result<(), Self::Error> {
+            if msg == 0 {
+                return Err(());
+            }
+            self.0.lock().unwrap().push(Some(msg));
+            Ok(())
+        }
+
+        async fn on_stop(&mut self, _: Option<usize>) -> Result<(), Self::Error> {
+            self.0.lock().unwrap().push(None);
+            Ok(())
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_drain_error() {
+        let log = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let (actor_ref, fut) = DrainActor(log.clone()).into_future(None);
+        for msg in [1, 0, 2] {
+            actor_ref.send(msg).await.unwrap();
+        }
+        actor_ref.stop(3).unwrap();
+
+        assert!(fut.await.is_err());
+        assert_eq!(*log.lock().unwrap(), vec![Some(1), Some(2), None]);
+    }
+
+    #[tokio::test]
+    async fn test_drain_concurrent_send() {
+        let log = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let (actor_ref, fut) = DrainActor(log.clone()).into_future(Some(1));
+        actor_ref.send(1).await.unwrap();
+        let sender = actor_ref.clone();
+        let blocked = tokio::spawn(async move { sender.send(2).await });
+        tokio::task::yield_now().await;
+        actor_ref.stop(3).unwrap();
+
+        fut.await.unwrap();
+        assert!(blocked.await.unwrap().is_err());
+        assert_eq!(*log.lock().unwrap(), vec![Some(1), None]);
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_skip_drain() {
+        let log = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let (actor_ref, fut) = DrainActor(log.clone()).into_future(None);
+        for msg in [1, 2] {
+            actor_ref.send(msg).await.unwrap();
+        }
+        actor_ref.stop(3).unwrap();
+        actor_ref.skip_drain();
+
+        fut.await.unwrap();
+        assert_eq!(*log.lock().unwrap(), vec![None]);
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_flush() {
+        let log = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let (actor_ref, fut) = DrainActor(log.clone()).into_future(None);
+        for msg in [1, 2, 3] {
+            actor_ref.send(msg).await.unwrap();
+        }
+        let handle = tokio::spawn(fut);
+
+        actor_ref.flush().await.unwrap();
+        assert_eq!(*log.lock().unwrap(), vec![Some(1), Some(2), Some(3)]);
+        actor_ref.flush().await.unwrap();
+
+        actor_ref.stop(0).unwrap();
+        assert!(handle.await.unwrap().is_ok());
+        assert_eq!(actor_ref.flush().await, Err(Unacked));
+    }
+
+    /// Sums its messages, and fails to clean up when stopped.
+    struct FailStopActor(usize);
+
+    impl Actor for FailStopActor {
+        type Error = &'static str;
+        type Message = usize;
+
+        async fn on_msg(&mut self, _: &WeakActorRef<Self>, msg: usize) -> Result<(), Self::Error> {
+            self.0 += msg;
+            Ok(())
+        }
+
+        async fn on_stop(&mut self, _: Option<usize>) -> Result<(), Self::Error> {
+            Err("cleanup")
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_stop_error() {
+        let (actor_ref, fut) = FailStopActor(0).into_future(None);
+        actor_ref.send(3).await.unwrap();
+        actor_ref.stop(0).unwrap();
+
+        match fut.await {
+            Err(RunError::Stop { actor, error }) => {
+                assert_eq!(actor.0, 3);
+                assert_eq!(error, "cleanup");
+            }
+            res => panic!("expected a stop error, got {:?}", res.map(|actor| actor.0)),
+        }
+
+        let (actor_ref, fut) = DrainActor(Default::default()).into_future(None);
+        actor_ref.send(0).await.unwrap();
+        assert!(matches!(fut.await, Err(RunError::Failed(()))));
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_map_run() {
+        let (actor_ref, fut) = MyActor(0).into_future(None);
+        actor_ref.send(3).await.unwrap();
+        drop(actor_ref);
+        assert_eq!(fut.map_run(|actor| actor.0).await.unwrap(), 3);
+
+        let (a_ref, a_fut) = MyActor(0).into_future(None);
+        let (b_ref, b_fut) = FailStopActor(0).into_future(None);
+        drop(a_ref);
+        b_ref.stop(0).unwrap();
+        let res = tokio::try_join!(
+            a_fut.map_err_run(|_| String::from("failed")),
+            b_fut.map_err_run(|e| String::from(e.into_error())),
+        );
+        assert_eq!(res.map(|_| ()), Err(String::from("cleanup")));
+    }
+
+    #[tokio::test]
+    async fn test_wait_until() {
+        let actor = MyActor(0);
+        let (actor_ref, fut) = actor.into_future(Some(1));
+        let handle = tokio::spawn(fut);
+
+        let waiter = actor_ref.clone();
+        let reached = tokio::spawn(async move { waiter.wait_until(|actor| actor.0 >= 10).await });
+        for _ in 0..10 {
+            actor_ref.send(1).await.unwrap();
+        }
+        assert_eq!(reached.await.unwrap(), Ok(()));
+        assert_eq!(actor_ref.wait_until(|actor| actor.0 == 10).await, Ok(()));
+
+        let never = actor_ref.clone();
+        let never = tokio::spawn(async move { never.wait_until(|actor| actor.0 > 100).await });
+        actor_ref.flush().await.unwrap();
+        actor_ref.stop(0).unwrap();
+        assert!(handle.await.unwrap().is_ok());
+        assert_eq!(never.await.unwrap(), Err(Unacked));
+    }
+
+    /// Fails to start unless its number is at least 2.
+    struct FlakyActor(usize);
+
+    impl Actor for FlakyActor {
+        type Error = ();
+        type Message = usize;
+
+        async fn on_start(&mut self, _: &WeakActorRef<Self>) -> Result<(), ()> {
+            if self.0 < 2 {
+                return Err(());
+            }
+            Ok(())
+        }
+
+        async fn on_msg(&mut self, _: &WeakActorRef<Self>, _: usize) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_backoff_supervisor() {
+        use std::sync::{Arc, Mutex};
+        use std::time::Duration;
+
+        let slot = Arc::new(Mutex::new(None));
+        let published = slot.clone();
+        let mut runs = 0;
+        let factory = move || {
+            let (actor_ref, run) = FlakyActor(runs).into_future(None);
+            runs += 1;
+            *published.lock().unwrap() = Some(actor_ref);
+            run
+        };
+        let backoff = Backoff::new(Duration::from_millis(1), Duration::from_millis(100))
+            .with_jitter(0.5)
+            .with_reset_after(Duration::from_secs(1));
+        let supervisor = BackoffSupervisor::new(factory, backoff, tokio::time::sleep);
+        let status = supervisor.status();
+        let handle = tokio::spawn(supervisor);
+
+        let actor_ref = loop {
+            let actor_ref = slot.lock().unwrap().clone();
+            if let Some(actor_ref) = actor_ref {
+                if actor_ref.wait_started().await.is_ok() {
+                    break actor_ref;
+                }
+            }
+            tokio::task::yield_now().await;
+        };
+        assert_eq!(status.restarts(), 2);
+        assert_eq!(status.current_backoff(), Duration::from_millis(4));
+
+        actor_ref.stop(0).unwrap();
+        assert_eq!(handle.await.unwrap().unwrap().0, 2);
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_start() {
+        let (signal, started) = async_oneshot_channel::oneshot();
+        tokio::spawn(async move { signal.send(()) });
+        let (actor_ref, run) = SlowStartActor(Some(started)).start(None).await.unwrap();
+        assert!(actor_ref.is_started());
+        let handle = tokio::spawn(run);
+        actor_ref.send(()).await.unwrap();
+        actor_ref.stop(()).unwrap();
+        assert!(handle.await.unwrap().is_ok());
+
+        let (actor_ref, run) = FlakyActor(0).into_future(None);
+        assert_eq!(run.run_until_started().await.err(), Some(()));
+        assert!(actor_ref.is_finished());
+    }
+
+    /// Panics on `0`, and restarts after each panic.
+    #[derive(Default)]
+    struct PoisonActor {
+        handled: Vec<usize>,
+        starts: usize,
+        poisoned: usize,
+    }
+
+    impl Actor for PoisonActor {
+        type Error = ();
+        type Message = usize;
+
+        async fn on_start(&mut self, _: &WeakActorRef<Self>) -> Result<(), Self::Error> {
+            self.starts += 1;
+            Ok(())
+        }
+
+        async fn on_msg(&mut self, _: &WeakActorRef<Self>, msg: usize) -> Result<(), Self::Error> {
+            assert_ne!(msg, 0, "poison message");
+            self.handled.push(msg);
+            Ok(())
+        }
+
+        fn on_panic(&mut self, _: &(dyn std::any::Any + Send)) -> Supervision {
+            Supervision::Restart
+        }
+
+        async fn on_poison(&mut self, msg: Option<usize>) -> Result<(), Self::Error> {
+            assert!(msg.is_none());
+            self.poisoned += 1;
+            Ok(())
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_restart_on_panic() {
+        let (actor_ref, fut) = PoisonActor::default().into_future(None);
+        actor_ref.send(1).await.unwrap();
+        actor_ref.send(0).await.unwrap();
+        actor_ref.send(2).await.unwrap();
+        let handle = tokio::spawn(fut);
+
+        assert_eq!(actor_ref.send_ack(0).await, Err(Unacked));
+        assert_eq!(actor_ref.send_ack(3).await, Ok(()));
+
+        actor_ref.stop(4).unwrap();
+        let res = handle.await.unwrap().unwrap();
+        assert_eq!(res.handled, vec![1, 2, 3]);
+        assert_eq!(res.starts, 3);
+        assert_eq!(res.poisoned, 2);
+    }
+
+    struct RecordActor(Vec<usize>);
+
+    impl Actor for RecordActor {
+        type Error = ();
+        type Message = usize;
+
+        async fn on_msg(
+            &mut self,
+            _: &WeakActorRef<Self>,
+            msg: Self::Message,
+        ) -> Result<(), Self::Error> {
+            self.0.push(msg);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lifo() {
+        let actor = RecordActor(Vec::new());
+        let (actor_ref, fut) = ActorRun::with_mailbox(actor, Mailbox::lifo(Some(3)));
+
+        for i in 1..=3 {
+            actor_ref.send(i).await.unwrap();
+        }
+        assert_eq!(actor_ref.len(), 3);
+        assert_eq!(actor_ref.capacity(), Some(3));
+
+        let handle = tokio::spawn(fut);
+        actor_ref.send_ack(4).await.unwrap();
+        actor_ref.stop(0).unwrap();
+
+        let res = handle.await.unwrap().unwrap();
+        assert_eq!(res.0[0], 3);
+        assert_eq!(res.0.len(), 4);
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_lifo_drain() {
+        let actor = RecordActor(Vec::new());
+        let (actor_ref, fut) = ActorRun::with_mailbox(actor, Mailbox::lifo(None));
+
+        for i in 1..=3 {
+            actor_ref.send(i).await.unwrap();
+        }
+        actor_ref.stop(0).unwrap();
+
+        let res = tokio::spawn(fut).await.unwrap().unwrap();
+        assert_eq!(res.0, vec![3, 2, 1]);
+        assert!(actor_ref.send(4).await.is_err());
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_weighted() {
+        let actor = RecordActor(Vec::new());
+        let high = Tier(1);
+        let mailbox = Mailbox::weighted(None, &[(high, 3), (Tier(0), 1)]);
+        let (actor_ref, fut) = ActorRun::with_mailbox(actor, mailbox);
+
+        for i in 0..9 {
+            actor_ref.send_tiered(100 + i, high).await.unwrap();
+        }
+        for i in 0..3 {
+            actor_ref.send(i).await.unwrap();
+        }
+        actor_ref.stop(0).unwrap();
+
+        let res = tokio::spawn(fut).await.unwrap().unwrap();
+        let expected = vec![100, 101, 102, 0, 103, 104, 105, 1, 106, 107, 108, 2];
+        assert_eq!(res.0, expected);
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_priority_fn() {
+        let actor = RecordActor(Vec::new());
+        let mailbox = Mailbox::priority_fn(None, |msg: &usize| (*msg / 10) as u8);
+        let (actor_ref, fut) = ActorRun::with_mailbox(actor, mailbox);
+
+        for msg in [1, 20, 2, 31, 21, 3, 30] {
+            actor_ref.send(msg).await.unwrap();
+        }
+        assert_eq!(
+            actor_ref.snapshot_mailbox().unwrap(),
+            vec![31, 30, 20, 21, 1, 2, 3]
+        );
+        actor_ref.stop(0).unwrap();
+
+        let res = tokio::spawn(fut).await.unwrap().unwrap();
+        assert_eq!(res.0, vec![31, 30, 20, 21, 1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_weighted_flood() {
+        let actor = RecordActor(Vec::new());
+        let high = Tier(1);
+        let mailbox = Mailbox::weighted(Some(8), &[(high, 3), (Tier(0), 1)]);
+        let (actor_ref, fut) = ActorRun::with_mailbox(actor, mailbox);
+        let handle = tokio::spawn(fut);
+
+        let flood_ref = actor_ref.clone();
+        let flood =
+            tokio::spawn(async move { while flood_ref.send_tiered(100, high).await.is_ok() {} });
+        actor_ref.send_ack(1).await.unwrap();
+        actor_ref.send_ack(2).await.unwrap();
+        actor_ref.stop(0).unwrap();
+
+        let res = handle.await.unwrap().unwrap();
+        flood.await.unwrap();
+        assert!(res.0.contains(&1) && res.0.contains(&2));
+    }
+
+    /// Takes every queued message at once, and hands them out largest first.
+    #[derive(Default)]
+    struct LargestFirst(Vec<usize>);
+
+    impl Dispatcher<RecordActor> for LargestFirst {
+        async fn next(&mut self, mailbox: &Mailbox<RecordActor>) -> Option<usize> {
+            if self.0.is_empty() {
+                self.0.push(mailbox.recv_msg().await?);
+                self.0.extend(std::iter::from_fn(|| mailbox.try_recv_msg()));
+                self.0.sort_unstable();
+            }
+            self.0.pop()
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_dispatcher() {
+        let (mut mailbox, actor_ref) = Mailbox::new(None);
+        mailbox.set_dispatcher(LargestFirst::default());
+        let (actor_ref, fut) =
+            ActorRun::with_mailbox(RecordActor(Vec::new()), (mailbox, actor_ref));
+        for i in [2, 5, 1] {
+            actor_ref.send(i).await.unwrap();
+        }
+        let handle = tokio::spawn(fut);
+        actor_ref.flush().await.unwrap();
+        for i in [4, 7] {
+            actor_ref.send(i).await.unwrap();
+        }
+        actor_ref.stop(0).unwrap();
+        assert_eq!(handle.await.unwrap().unwrap().0, vec![5, 2, 1, 7, 4]);
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_idle() {
+        let (actor_ref, fut) = MyActor(0).into_future(None);
+        for _ in 0..3 {
+            actor_ref.send(1).await.unwrap();
+        }
+        let idle = tokio::spawn({
+            let actor_ref = actor_ref.clone();
+            async move { actor_ref.idle().await }
+        });
+        tokio::task::yield_now().await;
+        let handle = tokio::spawn(fut);
+        assert_eq!(idle.await.unwrap(), Ok(()));
+        assert_eq!(actor_ref.wait_until(|actor| actor.0 == 3).await, Ok(()));
+
+        // Staying empty is not another transition, so this only resolves on exit.
+        let idle = tokio::spawn({
+            let actor_ref = actor_ref.clone();
+            async move { actor_ref.idle().await }
+        });
+        tokio::task::yield_now().await;
+        actor_ref.close();
+        assert_eq!(idle.await.unwrap(), Err(Exited));
+        handle.await.unwrap().unwrap();
+    }
+
+    /// Records the context of every message it handles.
+    struct CtxActor(Vec<(usize, u64)>);
+
+    impl Actor for CtxActor {
+        type Error = ();
+        type Message = usize;
+
+        async fn on_msg(&mut self, _: &WeakActorRef<Self>, _: usize) -> Result<(), ()> {
+            unreachable!("on_msg_ctx is overridden")
+        }
+
+        async fn on_msg_ctx(
+            &mut self,
+            _: &WeakActorRef<Self>,
+            _: usize,
+            ctx: HandlerCtx,
+        ) -> Result<Vec<usize>, ()> {
+            self.0.push((ctx.backlog, ctx.seq));
+            Ok(Vec::new())
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_handler_ctx() {
+        let (actor_ref, fut) = CtxActor(Vec::new()).into_future(None);
+        for i in 0..3 {
+            actor_ref.send(i).await.unwrap();
+        }
+        drop(actor_ref);
+        assert_eq!(fut.await.unwrap().0, vec![(2, 0), (1, 1), (0, 2)]);
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_send_batch_ack() {
+        let (actor_ref, fut) = DrainActor(Default::default()).into_future(None);
+        let handle = tokio::spawn(fut);
+        assert_eq!(actor_ref.send_batch_ack(vec![1, 2, 3]).await, Ok(()));
+        assert_eq!(actor_ref.send_batch_ack(Vec::new()).await, Ok(()));
+
+        // Queued behind a stop, the batch is handled by the drain, where 0 fails.
+        actor_ref.stop(5).unwrap();
+        assert_eq!(actor_ref.send_batch_ack(vec![4, 0, 6]).await, Err(2));
+        assert!(handle.await.unwrap().is_err());
+
+        // The actor fails on 0, and the rest of the batch is dropped with its mailbox.
+        let (actor_ref, fut) = DrainActor(Default::default()).into_future(None);
+        let batch = tokio::spawn({
+            let actor_ref = actor_ref.clone();
+            async move { actor_ref.send_batch_ack(vec![0, 1]).await }
+        });
+        tokio::task::yield_now().await;
+        assert!(fut.await.is_err());
+        assert_eq!(batch.await.unwrap(), Err(0));
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_request_stop() {
+        let (actor_ref, fut) = MyActor(0).into_future(None);
+        let weak = actor_ref.downgrade();
+        let stoppers: Vec<_> = (0..8).map(|_| actor_ref.clone()).collect();
+        let results: Vec<_> = stoppers.iter().map(|r| r.request_stop(1)).collect();
+        assert_eq!(results.iter().filter(|res| res.is_ok()).count(), 1);
+        assert!(results[1..]
+            .iter()
+            .all(|res| *res == Err(AlreadyStopping(1))));
+        assert_eq!(weak.stop(2), Err(2));
+        assert_eq!(actor_ref.stop(3), Err(3));
+
+        drop((actor_ref, stoppers));
+        assert_eq!(fut.await.unwrap().0, 0);
+    }
+
+    /// Remembers the stop message it was stopped with.
+    struct StopReasonActor(Option<usize>);
+
+    impl Actor for StopReasonActor {
+        type Error = ();
+        type Message = usize;
+
+        async fn on_msg(&mut self, _: &WeakActorRef<Self>, _: usize) -> Result<(), ()> {
+            Ok(())
+        }
+
+        async fn on_stop(&mut self, stop: Option<usize>) -> Result<(), ()> {
+            self.0 = stop;
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "derive")]
+    /// [`PingActor`], with its dispatch generated by `#[derive(Actor)]`.
+    struct DerivedPingActor(Vec<usize>);
+
+    #[cfg(feature = "derive")]
+    #[derive(Debug, Actor)]
+    #[actor(DerivedPingActor)]
+    enum DerivedPingMessage {
+        #[handler(handle_ping)]
+        Ping(usize),
+        #[handler(handle_pings)]
+        Pings { first: usize, second: usize },
+        #[handler(handle_stop)]
+        Stop,
+    }
+
+    #[cfg(feature = "derive")]
+    impl DerivedPingActor {
+        async fn handle_ping(&mut self, _: &WeakActorRef<Self>, num: usize) -> Result<(), ()> {
+            self.0.push(num);
+            Ok(())
+        }
+
+        async fn handle_pings(
+            &mut self,
+            _: &WeakActorRef<Self>,
+            first: usize,
+            second: usize,
+        ) -> Result<(), ()> {
+            self.0.extend([first, second]);
+            Ok(())
+        }
+
+        async fn handle_stop(&mut self, this: &WeakActorRef<Self>) -> Result<(), ()> {
+            this.stop(DerivedPingMessage::Stop).unwrap();
+            Ok(())
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[cfg(feature = "derive")]
+    #[tokio::test]
+    async fn test_derive_actor() {
+        let (actor_ref, fut) = DerivedPingActor(Vec::new()).into_future(None);
+        actor_ref.send(DerivedPingMessage::Ping(1)).await.unwrap();
+        actor_ref
+            .send(DerivedPingMessage::Pings {
+                first: 2,
+                second: 3,
+            })
+            .await
+            .unwrap();
+        actor_ref.send(DerivedPingMessage::Stop).await.unwrap();
+        assert_eq!(fut.await.unwrap().0, [1, 2, 3]);
+    }
+
+    /// Logs its id when it stops, failing its cleanup if `fail` is set.
+    struct PhaseActor {
+        id: usize,
+        fail: bool,
+        log: std::sync::Arc<std::sync::Mutex<Vec<usize>>>,
+    }
+
+    impl Actor for PhaseActor {
+        type Error = ();
+        type Message = ();
+
+        async fn on_msg(&mut self, _: &WeakActorRef<Self>, _: ()) -> Result<(), ()> {
+            Ok(())
+        }
+
+        async fn on_stop(&mut self, _: Option<()>) -> Result<(), ()> {
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            self.log.lock().unwrap().push(self.id);
+            if self.fail {
+                return Err(());
+            }
+            Ok(())
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_shutdown_group() {
+        for abort_on_error in [false, true] {
+            let log = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+            let mut group = ShutdownGroup::new().abort_on_error(abort_on_error);
+            let mut refs = Vec::new();
+            for (id, phase) in [(0, 2), (1, 1), (2, 0), (3, 1)] {
+                let (actor_ref, fut) = PhaseActor {
+                    id,
+                    fail: id == 1,
+                    log: log.clone(),
+                }
+                .into_future(None);
+                tokio::spawn(fut);
+                group.add(phase, &actor_ref, ());
+                refs.push(actor_ref);
+            }
+            assert_eq!(group.len(), 4);
+
+            let res = group.stop_all().await;
+            assert_eq!(
+                res,
+                Err(ShutdownFailed {
+                    phases: vec![1],
+                    aborted: abort_on_error,
+                })
+            );
+            let log = log.lock().unwrap().clone();
+            assert_eq!(log[0], 2);
+            assert_eq!(log[1..3].iter().sum::<usize>(), 4);
+            if abort_on_error {
+                assert_eq!(log.len(), 3);
+                assert!(!refs[0].is_closed());
+            } else {
+                assert_eq!(log[3], 0);
+            }
+        }
+    }
+
+    /// Sums its messages, and persists the sum.
+    struct SnapshotActor(u64);
+
+    impl Actor for SnapshotActor {
+        type Error = ();
+        type Message = u64;
+
+        async fn on_msg(&mut self, _: &WeakActorRef<Self>, msg: u64) -> Result<(), ()> {
+            self.0 += msg;
+            Ok(())
+        }
+
+        fn persist(&self) -> Option<Vec<u8>> {
+            Some(self.0.to_le_bytes().to_vec())
+        }
+
+        fn restore(snapshot: &[u8]) -> Option<Self> {
+            Some(Self(u64::from_le_bytes(snapshot.try_into().ok()?)))
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_persist() {
+        let snapshots = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = snapshots.clone();
+        let (mut mailbox, actor_ref) = Mailbox::new(None);
+        mailbox.set_snapshot_sink(2, move |s: &[u8]| {
+            sink.lock().unwrap().push(s.to_vec());
+            Ok(())
+        });
+        let (actor_ref, fut) = ActorRun::with_mailbox(SnapshotActor(0), (mailbox, actor_ref));
+        let handle = tokio::spawn(fut);
+        for msg in [1, 2, 3] {
+            actor_ref.send(msg).await.unwrap();
+        }
+        actor_ref.stop(0).unwrap();
+        handle.await.unwrap().unwrap();
+        let sums: Vec<_> = snapshots
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|s| SnapshotActor::restore(s).unwrap().0)
+            .collect();
+        assert_eq!(sums, [3, 6]);
+
+        let path = std::env::temp_dir().join(format!("async-actor-{}.snap", std::process::id()));
+        let file = SnapshotFile::new(&path);
+        assert_eq!(file.load().unwrap(), None);
+        let (mut mailbox, actor_ref) = Mailbox::new(None);
+        mailbox.set_snapshot_sink(1, file.clone());
+        let (actor_ref, fut) = ActorRun::with_mailbox(SnapshotActor(10), (mailbox, actor_ref));
+        actor_ref.send(5).await.unwrap();
+        drop(actor_ref);
+        fut.await.unwrap();
+        let restored = SnapshotActor::restore(&file.load().unwrap().unwrap()).unwrap();
+        assert_eq!(restored.0, 15);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_escalate_stop() {
+        let (actor_ref, fut) = StopReasonActor(None).into_future(None);
+        actor_ref.send(0).await.unwrap();
+        actor_ref.escalate_stop(1).unwrap();
+        actor_ref.escalate_stop(3).unwrap();
+        actor_ref.escalate_stop(2).unwrap();
+        assert_eq!(fut.await.unwrap().0, Some(3));
+
+        let (actor_ref, fut) = StopReasonActor(None).into_future(None);
+        actor_ref.stop(2).unwrap();
+        actor_ref.escalate_stop(1).unwrap();
+        assert_eq!(fut.await.unwrap().0, Some(2));
+        assert_eq!(actor_ref.escalate_stop(5), Err(5));
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_link() {
+        let (a_ref, a_fut) = MyActor(0).into_future(None);
+        let (b_ref, b_fut) = RecordActor(Vec::new()).into_future(None);
+        link(&a_ref, &b_ref);
+        let (a_handle, b_handle) = (tokio::spawn(a_fut), tokio::spawn(b_fut));
+
+        b_ref.send(1).await.unwrap();
+        a_ref.stop(0).unwrap();
+        assert!(a_handle.await.unwrap().is_ok());
+        assert_eq!(b_handle.await.unwrap().unwrap().0, vec![1]);
+        assert!(b_ref.is_closed() && a_ref.is_closed());
+    }
+
+    /// Records handled and expired messages separately.
+    #[derive(Default)]
+    struct TtlActor {
+        handled: Vec<usize>,
+        expired: Vec<usize>,
+    }
+
+    impl Actor for TtlActor {
+        type Error = ();
+        type Message = usize;
+
+        async fn on_msg(&mut self, _: &WeakActorRef<Self>, msg: usize) -> Result<(), ()> {
+            self.handled.push(msg);
+            Ok(())
+        }
+
+        fn on_expired(&mut self, msg: usize) {
+            self.expired.push(msg);
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_send_ttl() {
+        let (actor_ref, fut) = TtlActor::default().into_future(None);
+        let hour = std::time::Duration::from_secs(3600);
+        actor_ref
+            .send_ttl(1, std::time::Duration::ZERO)
+            .await
+            .unwrap();
+        actor_ref.send_ttl(2, hour).await.unwrap();
+        actor_ref.send(3).await.unwrap();
+        let handle = tokio::spawn(fut);
+        actor_ref.flush().await.unwrap();
+
+        drop(actor_ref);
+        let actor = handle.await.unwrap().unwrap();
+        assert_eq!(actor.handled, vec![2, 3]);
+        assert_eq!(actor.expired, vec![1]);
+    }
+
+    #[derive(Default)]
+    struct DeadlineActor {
+        handled: Vec<(usize, Option<Deadline>)>,
+        missed: Vec<usize>,
+    }
+
+    impl Actor for DeadlineActor {
+        type Error = ();
+        type Message = usize;
+
+        async fn on_msg(&mut self, _: &WeakActorRef<Self>, _: usize) -> Result<(), ()> {
+            unreachable!()
+        }
+
+        async fn on_msg_ctx(
+            &mut self,
+            _: &WeakActorRef<Self>,
+            msg: usize,
+            ctx: HandlerCtx,
+        ) -> Result<Vec<usize>, ()> {
+            self.handled.push((msg, ctx.deadline));
+            Ok(Vec::new())
+        }
+
+        fn on_deadline_exceeded(&mut self, msg: usize, deadline: Deadline) {
+            assert!(deadline.is_expired());
+            self.missed.push(msg);
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_send_by() {
+        let hour = std::time::Duration::from_secs(3600);
+        let deadline = Deadline::after(hour);
+        let narrowed = deadline.narrow(std::time::Duration::from_secs(60));
+        assert!(narrowed < deadline);
+        assert!(narrowed.remaining() <= std::time::Duration::from_secs(60));
+        assert_eq!(deadline.narrow(hour * 2), deadline);
+
+        let (actor_ref, fut) = DeadlineActor::default().into_future(None);
+        actor_ref
+            .send_by(1, std::time::Instant::now())
+            .await
+            .unwrap();
+        actor_ref.send_by(2, deadline).await.unwrap();
+        actor_ref.send(3).await.unwrap();
+        let handle = tokio::spawn(fut);
+        actor_ref.flush().await.unwrap();
+
+        drop(actor_ref);
+        let actor = handle.await.unwrap().unwrap();
+        assert_eq!(actor.handled, vec![(2, Some(deadline)), (3, None)]);
+        assert_eq!(actor.missed, vec![1]);
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_multi_actor() {
+        let mut multi = MultiActor::new();
+        let slow_ref = multi.add(SleepyActor::default(), None);
+        let (done, finished) = async_oneshot_channel::oneshot();
+        let (fast_ref, fast_fut) = RecordActor(Vec::new()).into_future(None);
+        multi.add_run(fast_fut, move |res| {
+            let _ = done.send(res.map(|actor| actor.0));
+        });
+        assert_eq!(multi.len(), 2);
+        let handle = tokio::spawn(multi);
+
+        // The sleeping handler doesn't hold up the other actor.
+        slow_ref.send(200).await.unwrap();
+        fast_ref.send_ack(1).await.unwrap();
+        fast_ref.send(2).await.unwrap();
+        drop(fast_ref);
+        assert_eq!(finished.recv().await.unwrap().ok(), Some(vec![1, 2]));
+        assert!(!handle.is_finished());
+
+        drop(slow_ref);
+        handle.await.unwrap();
+    }
+
+    /// Offloads odd messages to detached work, which reports back with the message doubled.
+    struct OffloadOddActor(Vec<usize>);
+
+    impl Actor for OffloadOddActor {
+        type Error = ();
+        type Message = usize;
+
+        async fn on_msg(&mut self, this: &WeakActorRef<Self>, msg: usize) -> Result<(), ()> {
+            if msg.is_multiple_of(2) {
+                self.0.push(msg);
+                return Ok(());
+            }
+            let weak = this.clone();
+            let work = async move {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                let _ = weak.send(msg * 2).await;
+            };
+            this.detach(work).map_err(|_| ())
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_detach() {
+        let (mut mailbox, actor_ref) = Mailbox::new(None);
+        mailbox.set_spawner(|work| drop(tokio::spawn(work)));
+        let (actor_ref, fut) =
+            ActorRun::with_mailbox(OffloadOddActor(Vec::new()), (mailbox, actor_ref));
+        let handle = tokio::spawn(fut);
+
+        let (sent, was_sent) = async_oneshot_channel::oneshot();
+        actor_ref.downgrade().send_then(
+            3,
+            move || {
+                let _ = sent.send(());
+            },
+            |fut| drop(tokio::spawn(fut)),
+        );
+        was_sent.recv().await.unwrap();
+        actor_ref.send(4).await.unwrap();
+        actor_ref
+            .wait_until(|actor| actor.0.len() == 2)
+            .await
+            .unwrap();
+        actor_ref.stop(0).unwrap();
+        assert_eq!(handle.await.unwrap().unwrap().0, vec![4, 6]);
+
+        let (actor_ref, fut) = OffloadOddActor(Vec::new()).into_future(None);
+        actor_ref.send(1).await.unwrap();
+        drop(actor_ref);
+        assert!(fut.await.is_err());
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_bus() {
+        let (bus_ref, bus_fut) = Bus::<usize>::new().into_future(None);
+        let bus_handle = tokio::spawn(bus_fut);
+
+        let (a_ref, a_fut) = RecordActor(Vec::new()).into_future(None);
+        let a_handle = tokio::spawn(a_fut);
+        let (b_ref, b_fut) = RecordActor(Vec::new()).into_future(Some(1));
+        let (c_ref, c_fut) = RecordActor(Vec::new()).into_future(None);
+
+        bus_ref.subscribe(a_ref.downgrade()).await.unwrap();
+        bus_ref.subscribe(b_ref.downgrade()).await.unwrap();
+        let c_id = bus_ref.subscribe(c_ref.downgrade()).await.unwrap();
+
+        // `b` isn't running, so its mailbox fills up after the first message.
+        for i in 0..3 {
+            bus_ref.publish(i).await.unwrap();
+        }
+        bus_ref.unsubscribe(c_id).await.unwrap();
+        bus_ref.unsubscribe(c_id).await.unwrap();
+        bus_ref.send_ack(BusMessage::Publish(3)).await.unwrap();
+
+        // Drop `a`; the bus prunes it on the next publish.
+        a_ref.drain_and_stop().await.unwrap();
+        bus_ref.publish(4).await.unwrap();
+
+        drop(bus_ref);
+        let bus = bus_handle.await.unwrap().unwrap();
+        assert_eq!(bus.subscriber_count(), 1);
+
+        assert_eq!(a_handle.await.unwrap().unwrap().0, vec![0, 1, 2, 3]);
+        drop(b_ref);
+        assert_eq!(b_fut.await.unwrap().0, vec![0]);
+        drop(c_ref);
+        assert_eq!(c_fut.await.unwrap().0, vec![0, 1, 2]);
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_bus_subscription() {
+        let (bus_ref, bus_fut) = Bus::<usize>::new().into_future(None);
+        let bus_handle = tokio::spawn(bus_fut);
+        let (a_ref, a_fut) = RecordActor(Vec::new()).into_future(None);
+        let a_handle = tokio::spawn(a_fut);
+
+        let sub = bus_ref.subscription(a_ref.downgrade()).await.unwrap();
+        bus_ref.send_ack(BusMessage::Publish(1)).await.unwrap();
+        let id = sub.id();
+        drop(sub);
+        bus_ref.send_ack(BusMessage::Publish(2)).await.unwrap();
+        // Unsubscribing an id that the guard already removed is harmless.
+        bus_ref.unsubscribe(id).await.unwrap();
+
+        bus_ref
+            .subscription(a_ref.downgrade())
+            .await
+            .unwrap()
+            .detach();
+        bus_ref.send_ack(BusMessage::Publish(3)).await.unwrap();
+
+        drop(bus_ref);
+        assert_eq!(bus_handle.await.unwrap().unwrap().subscriber_count(), 1);
+        a_ref.drain_and_stop().await.unwrap();
+        assert_eq!(a_handle.await.unwrap().unwrap().0, vec![1, 3]);
+    }
+
+    struct PlusOneActor;
+
+    #[derive(Debug)]
+    enum PlusOneActorMessage {
+        PlusOne(usize, async_oneshot_channel::Sender<usize>),
+        Stop,
+    }
+
+    impl Actor for PlusOneActor {
+        type Message = PlusOneActorMessage;
+        type Error = ();
+
+        async fn on_msg(
+            &mut self,
+            _: &WeakActorRef<Self>,
+            msg: Self::Message,
+        ) -> Result<(), Self::Error> {
+            match msg {
+                PlusOneActorMessage::PlusOne(num, reply) => {
+                    let _ = reply.send(num + 1);
+                    Ok(())
+                }
+                _ => Err(()),
+            }
+        }
+    }
+
+    /// Records whether each request was cancelled by the time it was handled.
+    struct CancelActor(Vec<bool>);
+
+    impl Actor for CancelActor {
+        type Error = ();
+        type Message = Reply<usize>;
+
+        async fn on_msg(&mut self, _: &WeakActorRef<Self>, reply: Reply<usize>) -> Result<(), ()> {
+            self.0.push(reply.is_cancelled());
+            let _ = reply.send(1);
+            Ok(())
+        }
+    }
+
+    /// Reports each step of counting up to the requested number, then replies with the count.
+    struct CountActor;
+
+    impl Actor for CountActor {
+        type Error = ();
+        type Message = (u32, ProgressSender<u32, u32>);
+
+        async fn on_msg(
+            &mut self,
+            _: &WeakActorRef<Self>,
+            (n, reply): Self::Message,
+        ) -> Result<(), ()> {
+            for step in 1..n {
+                // Keep counting even if the caller stopped watching.
+                let _ = reply.progress(step);
+            }
+            if n > 0 {
+                let _ = reply.finish(n);
+            }
+            Ok(())
+        }
+    }
+
+    /// Answers requests from a separate task, keeping their cancellation flags.
+    struct OffloadActor(Vec<Cancellation>);
+
+    impl Actor for OffloadActor {
+        type Error = ();
+        type Message = Reply<usize>;
+
+        async fn on_msg(&mut self, _: &WeakActorRef<Self>, reply: Reply<usize>) -> Result<(), ()> {
+            self.0.push(reply.cancellation());
+            tokio::spawn(async move {
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                let _ = reply.answer(1);
+            });
+            Ok(())
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_cancellation() {
+        let (actor_ref, fut) = OffloadActor(Vec::new()).into_future(None);
+        let handle = tokio::spawn(fut);
+        let timeout = std::time::Duration::from_millis(10);
+        let asked = tokio::time::timeout(timeout, actor_ref.ask_cancellable(|reply| reply)).await;
+        assert!(asked.is_err());
+
+        drop(actor_ref);
+        let actor = handle.await.unwrap().unwrap();
+        assert_eq!(actor.0.len(), 1);
+        assert!(actor.0[0].is_cancelled());
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_ask_cancellable() {
+        let (actor_ref, fut) = CancelActor(Vec::new()).into_future(None);
+        let timeout = std::time::Duration::from_millis(10);
+        let asked = tokio::time::timeout(timeout, actor_ref.ask_cancellable(|reply| reply)).await;
+        assert!(asked.is_err());
 
-pub use actor::*;
-pub use actor_ref::*;
-pub use actor_run::*;
-pub use mailbox::Mailbox;
+        let handle = tokio::spawn(fut);
+        assert_eq!(actor_ref.ask_cancellable(|reply| reply).await, Ok(1));
+        drop(actor_ref);
+        assert_eq!(handle.await.unwrap().unwrap().0, vec![true, false]);
+    }
 
-#[cfg(test)]
-mod test {
-    use super::*;
+    /// Answers after the given delay in milliseconds, keeping the requests' cancellation flags.
+    struct ReplicaActor(u64, Vec<Cancellation>);
 
-    struct MyActor(usize);
+    impl Actor for ReplicaActor {
+        type Error = ();
+        type Message = Reply<u64>;
 
-    impl Actor for MyActor {
+        async fn on_msg(&mut self, _: &WeakActorRef<Self>, reply: Reply<u64>) -> Result<(), ()> {
+            self.1.push(reply.cancellation());
+            let delay = self.0;
+            tokio::spawn(async move {
+                tokio::time::sleep(std::time::Duration::from_millis(delay)).await;
+                let _ = reply.answer(delay);
+            });
+            Ok(())
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_ask_any() {
+        let (refs, runs): (Vec<_>, Vec<_>) = [50, 5, 30]
+            .map(|delay| ReplicaActor(delay, Vec::new()).into_future(None))
+            .into_iter()
+            .unzip();
+        let handles: Vec<_> = runs.into_iter().map(tokio::spawn).collect();
+
+        assert_eq!(ask_any(&refs, |reply| reply).await, Ok((1, 5)));
+        for actor_ref in &refs {
+            actor_ref.close();
+        }
+        let mut cancelled = Vec::new();
+        for handle in handles {
+            cancelled.push(handle.await.unwrap().unwrap().1[0].is_cancelled());
+        }
+        assert_eq!(cancelled, [true, true, true]);
+        assert_eq!(ask_any(&refs, |reply| reply).await, Err(AllFailed));
+    }
+
+    #[tokio::test]
+    async fn test_ask_any_full() {
+        let (stalled, _stalled_run) = ReplicaActor(1, Vec::new()).into_future(Some(1));
+        let (fast, fast_run) = ReplicaActor(5, Vec::new()).into_future(Some(1));
+        // Fills both mailboxes with a request that is given up on right away. The stalled
+        // replica never runs, so its mailbox stays full.
+        for actor_ref in [&stalled, &fast] {
+            let refs = [actor_ref.clone()];
+            let asked = ask_any(&refs, |reply| reply);
+            let given_up = tokio::time::timeout(std::time::Duration::from_millis(1), asked);
+            assert!(given_up.await.is_err());
+        }
+
+        // Every replica is full, so the request waits for the first one with room.
+        let refs = [stalled.clone(), fast.clone()];
+        let asked = tokio::spawn(async move { ask_any(&refs, |reply| reply).await });
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        tokio::spawn(fast_run);
+        let answered = tokio::time::timeout(std::time::Duration::from_secs(1), asked);
+        assert_eq!(answered.await.unwrap().unwrap(), Ok((1, 5)));
+
+        // The full replica is skipped while another one has room.
+        let refs = [stalled, fast];
+        let asked = ask_any(&refs, |reply| reply);
+        let answered = tokio::time::timeout(std::time::Duration::from_secs(1), asked);
+        assert_eq!(answered.await.unwrap(), Ok((1, 5)));
+    }
+
+    /// Answers requests below 10, and falls back to `None` for other even ones.
+    struct AnswerActor;
+
+    impl Actor for AnswerActor {
         type Error = ();
-        type Message = usize;
+        type Message = (usize, Reply<Option<usize>>);
 
         async fn on_msg(
             &mut self,
             _: &WeakActorRef<Self>,
-            msg: Self::Message,
-        ) -> Result<(), Self::Error> {
-            self.0 += msg;
-            println!("Received message: {}. Current state: {}", msg, self.0);
-
+            (n, mut reply): Self::Message,
+        ) -> Result<(), ()> {
+            assert!(reply.is_waiting());
+            if n % 2 == 0 {
+                reply.set_fallback(None);
+            }
+            if n < 10 {
+                let _ = reply.answer(Some(n));
+            }
             Ok(())
         }
     }
 
+    #[cfg(not(feature = "bounded-only"))]
     #[tokio::test]
-    async fn test_actor() {
-        let actor = MyActor(0);
+    async fn test_reply_answer() {
+        let (actor_ref, fut) = AnswerActor.into_future(None);
+        let handle = tokio::spawn(fut);
+
+        assert_eq!(actor_ref.ask_cancellable(|r| (2, r)).await, Ok(Some(2)));
+        assert_eq!(actor_ref.ask_cancellable(|r| (12, r)).await, Ok(None));
+        assert_eq!(
+            actor_ref.ask_cancellable(|r| (13, r)).await,
+            Err(AskError::Dropped)
+        );
+        drop(actor_ref);
+        handle.await.unwrap().unwrap();
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_reply() {
+        let actor = PlusOneActor;
         let (actor_ref, fut) = actor.into_future(None);
         let handle = tokio::spawn(fut);
 
-        actor_ref.send(3).await.unwrap();
-        actor_ref.send(7).await.unwrap();
+        let (reply_sender, reply_receiver) = async_oneshot_channel::oneshot();
+        actor_ref
+            .send(PlusOneActorMessage::PlusOne(3, reply_sender))
+            .await
+            .unwrap();
+        let res = reply_receiver.recv().await;
+        assert_eq!(res, Some(4));
 
-        actor_ref.stop(0).unwrap();
+        let (reply_sender, reply_receiver) = async_oneshot_channel::oneshot();
+        actor_ref
+            .send(PlusOneActorMessage::PlusOne(7, reply_sender))
+            .await
+            .unwrap();
+        let res = reply_receiver.recv().await;
+        assert_eq!(res, Some(8));
+
+        actor_ref.stop(PlusOneActorMessage::Stop).unwrap();
 
         let res = handle.await;
         assert!(res.is_ok());
     }
 
+    #[cfg(not(feature = "bounded-only"))]
     #[tokio::test]
-    async fn test_actor_long() {
-        let actor = MyActor(0);
-        let (actor_ref, fut) = actor.into_future(None);
+    async fn test_pipe() {
+        let (plus_one, fut) = PlusOneActor.into_future(None);
+        tokio::spawn(fut);
+        let (record, fut) = RecordActor(Vec::new()).into_future(None);
+        let record_handle = tokio::spawn(fut);
+
+        let (reply_sender, reply_receiver) = async_oneshot_channel::oneshot();
+        let mut task = None;
+        plus_one.pipe(
+            reply_receiver,
+            record.downgrade(),
+            |n| n * 10,
+            |t| task = Some(tokio::spawn(t)),
+        );
+        plus_one
+            .send(PlusOneActorMessage::PlusOne(3, reply_sender))
+            .await
+            .unwrap();
+        task.unwrap().await.unwrap();
+
+        let (tx, rx) = async_channel::unbounded::<usize>();
+        let mut task = None;
+        plus_one.pipe_stream(
+            rx,
+            record.downgrade(),
+            |n| n + 1,
+            |t| task = Some(tokio::spawn(t)),
+        );
+        tx.send(1).await.unwrap();
+        tx.send(2).await.unwrap();
+        tx.close();
+        task.unwrap().await.unwrap();
+
+        // The stream stays open, but the task ends once the receiving actor exits.
+        let (_tx, rx) = async_channel::unbounded::<usize>();
+        let mut task = None;
+        plus_one.pipe_stream(
+            rx,
+            record.downgrade(),
+            |n| n,
+            |t| task = Some(tokio::spawn(t)),
+        );
+        record.stop(0).unwrap();
+        let res = record_handle.await.unwrap().unwrap();
+        assert_eq!(res.0, vec![40, 2, 3]);
+        task.unwrap().await.unwrap();
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_spawn_and_send() {
+        let (actor_ref, fut) = RecordActor(Vec::new()).into_future(None);
         let handle = tokio::spawn(fut);
 
-        for i in 0..1000 {
-            actor_ref.send(i).await.unwrap();
-        }
+        let mut task = None;
+        actor_ref.as_weak().spawn_and_send(
+            async { 5 },
+            |n| n + 1,
+            |t| task = Some(tokio::spawn(t)),
+        );
+        task.unwrap().await.unwrap();
 
+        let mut task = None;
+        actor_ref.as_weak().spawn_and_send(
+            std::future::pending::<usize>(),
+            |n| n,
+            |t| task = Some(tokio::spawn(t)),
+        );
         actor_ref.stop(0).unwrap();
+        assert_eq!(handle.await.unwrap().unwrap().0, vec![6]);
+        task.unwrap().await.unwrap();
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_send_ack_error() {
+        let actor = PlusOneActor;
+        let (actor_ref, fut) = actor.into_future(None);
+        let handle = tokio::spawn(fut);
+
+        let res = actor_ref.send_ack(PlusOneActorMessage::Stop).await;
+        assert_eq!(res, Err(Unacked));
 
         let res = handle.await;
-        assert!(res.is_ok());
+        assert!(res.unwrap().is_err());
     }
 
+    #[cfg(not(feature = "bounded-only"))]
     #[tokio::test]
-    async fn test_drop() {
-        let actor = MyActor(0);
+    async fn test_ask() {
+        let actor = PlusOneActor;
         let (actor_ref, fut) = actor.into_future(None);
         let handle = tokio::spawn(fut);
 
-        actor_ref.send(3).await.unwrap();
-        actor_ref.send(7).await.unwrap();
+        let res = actor_ref
+            .ask(|reply| PlusOneActorMessage::PlusOne(3, reply))
+            .await;
+        assert_eq!(res, Ok(4));
 
-        drop(actor_ref);
+        actor_ref.stop(PlusOneActorMessage::Stop).unwrap();
+        assert!(handle.await.unwrap().is_ok());
 
-        let res = handle.await;
-        assert!(res.is_ok());
+        let res = actor_ref
+            .ask(|reply| PlusOneActorMessage::PlusOne(3, reply))
+            .await;
+        assert_eq!(res, Err(AskError::Closed));
     }
 
+    #[cfg(not(feature = "bounded-only"))]
     #[tokio::test]
-    async fn test_drop_partial() {
-        let actor = MyActor(0);
-        let (actor_ref, fut) = actor.into_future(None);
+    async fn test_ask_map() {
+        let (actor_ref, fut) = PlusOneActor.into_future(None);
         let handle = tokio::spawn(fut);
 
-        let ActorRef { sender, stop } = actor_ref;
-        sender.send(3).await.unwrap();
-        sender.send(7).await.unwrap();
+        let res = actor_ref
+            .ask_map(
+                |reply| PlusOneActorMessage::PlusOne(3, reply),
+                |n| n.to_string(),
+            )
+            .await;
+        assert_eq!(res.as_deref(), Ok("4"));
 
-        drop(stop);
+        actor_ref.stop(PlusOneActorMessage::Stop).unwrap();
+        assert!(handle.await.unwrap().is_ok());
+        let res = actor_ref
+            .ask_map(
+                |reply| PlusOneActorMessage::PlusOne(3, reply),
+                |_| unreachable!(),
+            )
+            .await;
+        assert_eq!(res, Err::<(), _>(AskError::Closed));
+    }
 
-        let res = handle.await;
-        assert!(res.is_ok());
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_ask_progress() {
+        let (actor_ref, fut) = CountActor.into_future(None);
+        let handle = tokio::spawn(fut);
+
+        let (progress, reply) = actor_ref.ask_progress(|reply| (3, reply)).await;
+        let mut updates = Vec::new();
+        while let Ok(step) = progress.recv().await {
+            updates.push(step);
+        }
+        assert_eq!(updates, [1, 2]);
+        assert_eq!(reply.await, Some(3));
+
+        // Without a final reply, the future resolves to `None`.
+        let (progress, reply) = actor_ref.ask_progress(|reply| (0, reply)).await;
+        assert_eq!(reply.await, None);
+        assert!(progress.recv().await.is_err());
+
+        // The caller may ignore the updates.
+        let (progress, reply) = actor_ref.ask_progress(|reply| (2, reply)).await;
+        drop(progress);
+        assert_eq!(reply.await, Some(2));
+
+        actor_ref.close();
+        assert!(handle.await.unwrap().is_ok());
+        let (progress, reply) = actor_ref.ask_progress(|reply| (2, reply)).await;
+        assert_eq!(reply.await, None);
+        assert!(progress.recv().await.is_err());
     }
 
+    #[cfg(not(feature = "bounded-only"))]
     #[tokio::test]
-    async fn test_drop_partial_sender() {
-        let actor = MyActor(0);
-        let (actor_ref, fut) = actor.into_future(None);
+    async fn test_relocate() {
+        let (actor_ref, run) = PlusOneActor.into_future(None);
+        let (relocatable, handle) = run.into_relocatable();
+        let task = tokio::spawn(relocatable);
+        let res = actor_ref.ask(|r| PlusOneActorMessage::PlusOne(1, r)).await;
+        assert_eq!(res, Ok(2));
+
+        handle.park();
+        let Relocated::Parked(parked) = task.await.unwrap() else {
+            panic!("the actor should have parked");
+        };
+        // Requests sent while the actor is parked are answered once it has moved.
+        let asker = actor_ref.clone();
+        let ask =
+            tokio::spawn(async move { asker.ask(|r| PlusOneActorMessage::PlusOne(2, r)).await });
+        let moved = std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            runtime.block_on(parked.resume(|relocatable| relocatable))
+        });
+        assert_eq!(ask.await.unwrap(), Ok(3));
+
+        actor_ref.stop(PlusOneActorMessage::Stop).unwrap();
+        assert!(matches!(moved.join().unwrap(), Relocated::Finished(Ok(_))));
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_actor_into_parts() {
+        let (actor_ref, mut actor, mailbox) = MyActor(0).into_parts(None);
+        let run = tokio::spawn(async move { actor.run_with(mailbox).await.map(|()| actor.0) });
+        actor_ref.send(3).await.unwrap();
+        actor_ref.send(4).await.unwrap();
+        actor_ref.stop(0).unwrap();
+        assert_eq!(run.await.unwrap(), Ok(7));
+        assert!(actor_ref.is_closed());
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_try_ask_now() {
+        let (actor_ref, fut) = PlusOneActor.into_future(None);
+        let Ok(AskNow::Pending(reply)) = actor_ref
+            .try_ask_now(|reply| PlusOneActorMessage::PlusOne(3, reply))
+            .await
+        else {
+            panic!("answered before the actor ran");
+        };
         let handle = tokio::spawn(fut);
+        assert_eq!(reply.await, Ok(4));
 
-        let ActorRef { sender, .. } = actor_ref;
-        sender.send(3).await.unwrap();
-        sender.send(7).await.unwrap();
+        // Answered before the request is even sent.
+        let res = actor_ref
+            .try_ask_now(|reply| {
+                reply.send(7).unwrap();
+                PlusOneActorMessage::PlusOne(0, reply)
+            })
+            .await;
+        assert!(matches!(res, Ok(AskNow::Ready(7))));
 
-        drop(sender);
+        actor_ref.stop(PlusOneActorMessage::Stop).unwrap();
+        assert!(handle.await.unwrap().is_ok());
+    }
 
-        let res = handle.await;
-        assert!(res.is_ok());
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_weak_ask() {
+        let (actor_ref, fut) = PlusOneActor.into_future(None);
+        let handle = tokio::spawn(fut);
+        let weak = actor_ref.downgrade();
+
+        let res = weak
+            .ask(|reply| PlusOneActorMessage::PlusOne(3, reply))
+            .await;
+        assert_eq!(res, Ok(4));
+
+        drop(actor_ref);
+        assert!(handle.await.unwrap().is_ok());
+        let res = weak
+            .ask(|reply| PlusOneActorMessage::PlusOne(3, reply))
+            .await;
+        assert_eq!(res, Err(AskError::ActorGone));
     }
 
-    struct PlusOneActor;
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_join_replies() {
+        let (actor_ref, fut) = PlusOneActor.into_future(None);
+        tokio::spawn(fut);
 
-    #[derive(Debug)]
-    enum PlusOneActorMessage {
-        PlusOne(usize, async_oneshot_channel::Sender<usize>),
-        Stop,
+        let mut receivers = Vec::new();
+        for num in [1, 2] {
+            let (reply_sender, reply_receiver) = async_oneshot_channel::oneshot();
+            actor_ref
+                .send(PlusOneActorMessage::PlusOne(num, reply_sender))
+                .await
+                .unwrap();
+            receivers.push(reply_receiver);
+        }
+        let (dropped_sender, reply_receiver) = async_oneshot_channel::oneshot::<usize>();
+        receivers.insert(1, reply_receiver);
+        drop(dropped_sender);
+
+        assert_eq!(join_replies(receivers).await, vec![Some(2), None, Some(3)]);
+        assert!(join_replies::<usize>(Vec::new()).await.is_empty());
     }
 
-    impl Actor for PlusOneActor {
-        type Message = PlusOneActorMessage;
+    /// Holds on to every reply sender it receives without ever answering.
+    struct SilentActor(Vec<async_oneshot_channel::Sender<usize>>);
+
+    impl Actor for SilentActor {
         type Error = ();
+        type Message = async_oneshot_channel::Sender<usize>;
 
         async fn on_msg(
             &mut self,
             _: &WeakActorRef<Self>,
             msg: Self::Message,
         ) -> Result<(), Self::Error> {
-            match msg {
-                PlusOneActorMessage::PlusOne(num, reply) => {
-                    let _ = reply.send(num + 1);
-                    Ok(())
-                }
-                _ => Err(()),
-            }
+            self.0.push(msg);
+            Ok(())
         }
     }
 
+    #[cfg(not(feature = "bounded-only"))]
     #[tokio::test]
-    async fn test_reply() {
-        let actor = PlusOneActor;
+    async fn test_ask_timeout() {
+        let actor = SilentActor(Vec::new());
         let (actor_ref, fut) = actor.into_future(None);
         let handle = tokio::spawn(fut);
 
-        let (reply_sender, reply_receiver) = async_oneshot_channel::oneshot();
-        actor_ref
-            .send(PlusOneActorMessage::PlusOne(3, reply_sender))
-            .await
-            .unwrap();
-        let res = reply_receiver.recv().await;
-        assert_eq!(res, Some(4));
+        let res = actor_ref
+            .ask_timeout(
+                |reply| reply,
+                std::time::Duration::from_millis(10),
+                tokio::time::sleep,
+            )
+            .await;
+        assert_eq!(res, Err(AskError::Timeout));
 
-        let (reply_sender, reply_receiver) = async_oneshot_channel::oneshot();
-        actor_ref
-            .send(PlusOneActorMessage::PlusOne(7, reply_sender))
-            .await
-            .unwrap();
-        let res = reply_receiver.recv().await;
-        assert_eq!(res, Some(8));
+        drop(actor_ref);
+        let actor = handle.await.unwrap().unwrap();
+        assert_eq!(actor.0.len(), 1);
+        assert!(actor.0[0].send(1).is_ok());
+    }
 
-        actor_ref.stop(PlusOneActorMessage::Stop).unwrap();
+    #[cfg(feature = "futures")]
+    async fn next<S: futures_core::Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+        std::future::poll_fn(|cx| std::pin::Pin::new(&mut *stream).poll_next(cx)).await
+    }
 
-        let res = handle.await;
-        assert!(res.is_ok());
+    #[cfg(not(feature = "bounded-only"))]
+    #[cfg(feature = "futures")]
+    #[tokio::test]
+    async fn test_mailbox_stream() {
+        let (mailbox, actor_ref) = Mailbox::<MyActor>::new(None);
+        actor_ref.send(1).await.unwrap();
+        actor_ref.send(2).await.unwrap();
+        actor_ref.stop(3).unwrap();
+
+        let mut stream = mailbox.into_stream();
+        assert!(matches!(
+            next(&mut stream).await,
+            Some(MailboxEvent::Stop(Some(3)))
+        ));
+        assert!(actor_ref.send(4).await.is_err());
+        assert!(matches!(
+            next(&mut stream).await,
+            Some(MailboxEvent::Message(Some(1)))
+        ));
+        assert!(matches!(
+            next(&mut stream).await,
+            Some(MailboxEvent::Message(Some(2)))
+        ));
+        assert!(next(&mut stream).await.is_none());
+        assert!(next(&mut stream).await.is_none());
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[cfg(feature = "futures")]
+    #[tokio::test]
+    async fn test_duplex() {
+        let (mut mailbox, actor_ref) = Mailbox::new(None);
+        mailbox.set_outputs::<usize>(None);
+        let (actor_ref, fut) = ActorRun::with_mailbox(DoublerActor, (mailbox, actor_ref));
+        let actor_ref = actor_ref.into_duplex::<u8>().unwrap_err();
+        let Ok(mut duplex) = actor_ref.into_duplex::<usize>() else {
+            panic!("the actor has an output channel of type usize");
+        };
+        let handle = tokio::spawn(fut);
+
+        duplex.send(1).await.unwrap();
+        duplex.send(2).await.unwrap();
+        assert_eq!(next(&mut duplex).await, Some(2));
+        duplex.close();
+        assert_eq!(next(&mut duplex).await, Some(4));
+        assert_eq!(next(&mut duplex).await, None);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[cfg(feature = "metrics")]
+    #[tokio::test]
+    async fn test_handler_latency() {
+        let (actor_ref, fut) = SleepyActor::default().into_future(None);
+        let metrics = actor_ref.metrics();
+        assert_eq!(metrics.handler_latency(), LatencySnapshot::default());
+
+        for ms in [1, 1, 20] {
+            actor_ref.send(ms).await.unwrap();
+        }
+        drop(actor_ref);
+        fut.await.unwrap();
+
+        let latency = metrics.handler_latency();
+        assert_eq!(latency.count, 3);
+        assert!(latency.min >= std::time::Duration::from_millis(1));
+        assert!(latency.max >= std::time::Duration::from_millis(20));
+        assert!(latency.min <= latency.mean && latency.mean <= latency.max);
+        assert!(latency.p99 >= latency.mean && latency.p99 <= latency.max);
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_ask_timeout_tokio() {
+        let actor = PlusOneActor;
+        let (actor_ref, fut) = actor.into_future(None);
+        let handle = tokio::spawn(fut);
+
+        let res = actor_ref
+            .ask_timeout_tokio(
+                |reply| PlusOneActorMessage::PlusOne(1, reply),
+                std::time::Duration::from_secs(1),
+            )
+            .await;
+        assert_eq!(res, Ok(2));
+
+        drop(actor_ref);
+        assert!(handle.await.unwrap().is_ok());
     }
 
     struct PingActor(ActorRef<PongActor>);
@@ -235,6 +3760,39 @@ mod test {
         }
     }
 
+    struct Ping(usize);
+
+    impl From<Ping> for PingActorMessage {
+        fn from(ping: Ping) -> Self {
+            PingActorMessage::Ping(ping.0)
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_send_into() {
+        let pong_actor = PongActor;
+        let (pong_actor_ref, pong_fut) = pong_actor.into_future(None);
+        let pong_handle = tokio::spawn(pong_fut);
+
+        let ping_actor = PingActor(pong_actor_ref);
+        let (ping_actor_ref, ping_fut) = ping_actor.into_future(None);
+        let ping_handle = tokio::spawn(ping_fut);
+
+        ping_actor_ref.send_into(Ping(3)).await.unwrap();
+        ping_actor_ref
+            .downgrade()
+            .send_into(PingActorMessage::Stop)
+            .await
+            .unwrap();
+
+        assert!(ping_handle.await.unwrap().is_ok());
+        assert!(pong_handle.await.unwrap().is_ok());
+
+        let res = ping_actor_ref.send_into(Ping(7)).await;
+        assert!(matches!(res, Err(PingActorMessage::Ping(7))));
+    }
+
     struct PongActor;
 
     #[derive(Debug)]
@@ -266,6 +3824,7 @@ mod test {
         }
     }
 
+    #[cfg(not(feature = "bounded-only"))]
     #[tokio::test]
     async fn test_ping_pong() {
         let pong_actor = PongActor;
@@ -293,4 +3852,96 @@ mod test {
         let res = pong_handle.await;
         assert!(res.is_ok());
     }
+
+    #[derive(Debug)]
+    enum GateMsg {
+        Open,
+        Item(u32),
+    }
+
+    /// Records items, stashing them until it is opened.
+    struct GateActor {
+        open: bool,
+        items: Vec<u32>,
+    }
+
+    impl Actor for GateActor {
+        type Error = ();
+        type Message = GateMsg;
+
+        fn should_stash(&self, msg: &GateMsg) -> bool {
+            !self.open && matches!(msg, GateMsg::Item(_))
+        }
+
+        async fn on_msg(&mut self, this: &WeakActorRef<Self>, msg: GateMsg) -> Result<(), ()> {
+            match msg {
+                GateMsg::Open => {
+                    self.open = true;
+                    this.unstash();
+                }
+                GateMsg::Item(item) => self.items.push(item),
+            }
+            Ok(())
+        }
+    }
+
+    #[cfg(not(feature = "bounded-only"))]
+    #[tokio::test]
+    async fn test_stash() {
+        for (mode, expected) in [
+            (UnstashMode::Priority, [1, 2, 3, 4, 5]),
+            (UnstashMode::Interleaved, [1, 4, 2, 5, 3]),
+        ] {
+            let gate = GateActor {
+                open: false,
+                items: Vec::new(),
+            };
+            let (mut mailbox, actor_ref) = Mailbox::new(None);
+            mailbox.set_unstash_mode(mode);
+            let (actor_ref, fut) = ActorRun::with_mailbox(gate, (mailbox, actor_ref));
+            for item in 1..=3 {
+                actor_ref.send(GateMsg::Item(item)).await.unwrap();
+            }
+            actor_ref.send(GateMsg::Open).await.unwrap();
+            for item in 4..=5 {
+                actor_ref.send(GateMsg::Item(item)).await.unwrap();
+            }
+            drop(actor_ref);
+            assert_eq!(fut.await.unwrap().items, expected);
+        }
+
+        // Stashed messages don't hold back flushes, are acknowledged once handled, and are
+        // dropped if the actor stops before unstashing them.
+        let gate = GateActor {
+            open: false,
+            items: Vec::new(),
+        };
+        let (actor_ref, fut) = gate.into_future(None);
+        let handle = tokio::spawn(fut);
+        let (acked, ()) = tokio::join!(actor_ref.send_ack(GateMsg::Item(1)), async {
+            actor_ref.send(GateMsg::Item(2)).await.unwrap();
+            tokio::time::timeout(std::time::Duration::from_secs(1), actor_ref.flush())
+                .await
+                .unwrap()
+                .unwrap();
+            actor_ref.send(GateMsg::Open).await.unwrap();
+            actor_ref.send(GateMsg::Item(3)).await.unwrap();
+        });
+        assert_eq!(acked, Ok(()));
+        drop(actor_ref);
+        assert_eq!(handle.await.unwrap().unwrap().items, [1, 2, 3]);
+
+        let gate = GateActor {
+            open: false,
+            items: Vec::new(),
+        };
+        let (actor_ref, fut) = gate.into_future(None);
+        let handle = tokio::spawn(fut);
+        let (unacked, ()) = tokio::join!(actor_ref.send_ack(GateMsg::Item(1)), async {
+            actor_ref.flush().await.unwrap();
+            actor_ref.stop(GateMsg::Open).unwrap();
+        });
+        assert_eq!(unacked, Err(Unacked));
+        assert!(handle.await.unwrap().unwrap().items.is_empty());
+    }
 }
@@ -1,87 +1,904 @@
 use std::{
-    future::Future,
-    pin::Pin,
-    task::{Context, Poll},
+    collections::VecDeque,
+    future::{poll_fn, Future},
+    pin::pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::Poll,
+    time::{Duration, Instant},
 };
 
-use async_channel::Receiver as MultiReceiver;
+use async_channel::{Receiver as ControlReceiver, RecvError, TryRecvError};
 use async_oneshot_channel::Receiver as OneshotReceiver;
 use either::Either;
 
-use crate::{Actor, ActorRef, WeakActorRef};
+use crate::{
+    ack::Ack,
+    channel::{self, Receiver as MultiReceiver, Sender as MultiSender},
+    control::{Control, Escalated, MoreSevere, Watcher},
+    deadline::Deadline,
+    detach::{Detached, Spawner},
+    dispatch::{Dispatcher, DynDispatcher},
+    envelope::{HandlerCtx, Meta},
+    layer::{DynLayer, Layer},
+    persist::Persister,
+    queue::{CoalesceKey, Coalesced, Lifo, Prioritized, Tier, Weighted},
+    rate::RateLimit,
+    recorder::{RecordFn, Recorded, Recorder},
+    select::biased,
+    shared::{Outputs, Shared},
+    stash::{Stash, UnstashMode},
+    timeout::HandlerTimeout,
+    trigger::Trigger,
+    Actor, ActorRef, SnapshotSink, WeakActorRef,
+};
+
+#[derive(Debug)]
+/// A message as it travels through the mailbox, along with any delivery tracking attached to it.
+pub(crate) struct Packet<M> {
+    pub(crate) msg: M,
+    pub(crate) ack: Option<Ack>,
+    pub(crate) tier: Tier,
+    /// Boxed, so that messages sent without metadata only pay for a null pointer.
+    pub(crate) meta: Option<Box<Meta>>,
+    /// When the message goes stale, if it was sent with [`ActorRef::send_ttl`].
+    pub(crate) expires_at: Option<Instant>,
+    /// When the message must be handled by, if it was sent with [`ActorRef::send_by`].
+    pub(crate) deadline: Option<Deadline>,
+    /// The key the message was sent with by [`ActorRef::send_coalesced`].
+    pub(crate) coalesce: Option<CoalesceKey>,
+}
+
+impl<M> Packet<M> {
+    pub(crate) fn new(msg: M) -> Self {
+        Self {
+            msg,
+            ack: None,
+            tier: Tier::default(),
+            meta: None,
+            expires_at: None,
+            deadline: None,
+            coalesce: None,
+        }
+    }
+}
+
+impl<M> From<M> for Packet<M> {
+    fn from(msg: M) -> Self {
+        Self::new(msg)
+    }
+}
+
+/// Delivery state of the message most recently handed out by the mailbox.
+#[derive(Default)]
+struct InFlight {
+    /// Delivery tracking, settled by [`Mailbox::complete`].
+    ack: Option<Ack>,
+    /// Metadata, if the message had any.
+    meta: Option<Meta>,
+    /// Set if the message had outlived its TTL or missed its deadline.
+    expired: bool,
+    /// Deadline, if the message had one.
+    deadline: Option<Deadline>,
+    /// Set if the message came from the express lane or the stash rather than the mailbox, so
+    /// that it does not count towards flushes.
+    unqueued: bool,
+}
 
 /// A mailbox for an actor, containing a receiver for messages, a receiver for stop messages,
 /// and a weak reference to the actor.
 ///
 /// Importantly, we do not store a strong [`ActorRef`] in the mailbox, as the actor would otherwise
 /// keep itself alive even if all other references to it were dropped.
+///
+/// The mailbox also carries the options of the default run loop, such as
+/// [`Mailbox::set_dispatcher`] or [`Mailbox::set_rate_limit`]. Set them on a new mailbox, then
+/// run the actor with it using [`ActorRun::with_mailbox`](crate::ActorRun::with_mailbox).
 pub struct Mailbox<A: Actor> {
-    pub receiver: MultiReceiver<A::Message>,
+    /// The message channel, shared with [`Mailbox::receiver`].
+    messages: Arc<MultiReceiver<A::Message>>,
+    /// Direct access to the message channel, kept for run loops written against earlier
+    /// versions, where this was the channel's `async_channel::Receiver`.
+    #[deprecated(
+        note = "use `Mailbox::recv_msg` and `Mailbox::try_recv_msg`, which track follow-ups, \
+                acknowledgements and TTLs"
+    )]
+    pub receiver: MessageReceiver<A::Message>,
     pub stop: OneshotReceiver<A::Message>,
     pub this: WeakActorRef<A>,
+    pub(crate) control: ControlReceiver<Control<A>>,
+    /// The message most recently handed out by the mailbox.
+    in_flight: Mutex<InFlight>,
+    /// Number of messages handed to the actor by the default run loop so far.
+    handled: AtomicU64,
+    /// Follow-up messages returned by [`Actor::on_msg_emit`], received ahead of the channel.
+    followups: Mutex<VecDeque<A::Message>>,
+    /// Messages set aside with [`Mailbox::stash`], and those released by [`Mailbox::unstash`].
+    stash: Mutex<Stash<A::Message>>,
+    /// Messages sent with [`ActorRef::send_priority`], taken from the control channel and not
+    /// handled yet.
+    express: Mutex<VecDeque<A::Message>>,
+    /// Flushes waiting on handled messages, with the number of messages each is still waiting for.
+    barriers: Mutex<Vec<(usize, Ack)>>,
+    /// Predicates waited on with [`ActorRef::wait_until`] that did not hold yet.
+    watchers: Mutex<Vec<(Watcher<A>, Ack)>>,
+    /// The most severe stop message requested with [`ActorRef::escalate_stop`] so far.
+    escalated: Mutex<Option<Escalated<A::Message>>>,
+    /// Log of received messages, set by [`Mailbox::start_recording`].
+    pub(crate) recorder: Option<RecordFn<A::Message>>,
+    /// Periodic snapshots of the actor, set by [`Mailbox::set_snapshot_sink`].
+    pub(crate) persister: Option<Persister>,
+    /// Time limit for each handler call, set by [`Mailbox::set_handler_timeout`].
+    pub(crate) handler_timeout: Option<HandlerTimeout>,
+    /// External event source, set by [`Mailbox::set_trigger`].
+    pub(crate) trigger: Option<Trigger<A>>,
+    /// Message dispatch for the default run loop, set by [`Mailbox::set_dispatcher`], and taken
+    /// out of the mailbox by the run loop when it starts. Locked so that the mailbox stays `Sync`.
+    pub(crate) dispatcher: Mutex<Option<Box<dyn DynDispatcher<A>>>>,
+    /// Middleware around the handler, outermost first, added with [`Mailbox::layer`].
+    pub(crate) layers: Vec<Box<dyn DynLayer<A>>>,
+    /// Clones the actor before each message, set by [`Mailbox::set_transactional`].
+    pub(crate) snapshot: Option<fn(&A) -> A>,
+    /// Formats each message before it is handled, set by [`Mailbox::set_traced`].
+    pub(crate) describe: Option<fn(&A::Message) -> String>,
 }
 
 impl<A: Actor> Mailbox<A> {
+    /// Creates a mailbox that delivers messages in FIFO order, bounded to `size` messages if
+    /// given. An unbounded mailbox never makes senders wait, so a slow actor lets its backlog,
+    /// and the memory it holds, grow without limit.
+    ///
+    /// With the `bounded-only` feature enabled, creating an unbounded mailbox of any kind
+    /// panics, including through `into_future(None)` and the other constructors taking a
+    /// mailbox size, so that every actor applies backpressure. To migrate, pass `Some(size)`
+    /// wherever `None` was passed, sized for the largest backlog the actor should buffer, and
+    /// handle the waiting this introduces in [`ActorRef::send`], or the
+    /// [`TrySendError::Full`](crate::TrySendError::Full) errors of [`ActorRef::try_send`].
+    /// As the feature changes the behavior of every actor in the build, it is meant to be
+    /// enabled by the final application rather than by libraries.
     pub fn new(size: Option<usize>) -> (Self, ActorRef<A>) {
-        let (multi_sender, multi_receiver) = if let Some(size) = size {
-            async_channel::bounded(size)
-        } else {
-            async_channel::unbounded()
-        };
+        let (multi_sender, multi_receiver) = channel::fifo(size);
+        Self::from_channel(multi_sender, multi_receiver)
+    }
+
+    /// Creates a mailbox that delivers messages in last-in-first-out order: each receive returns
+    /// the most recently sent message that is still queued. This ordering also applies while the
+    /// actor drains its mailbox on stop, so the drain processes the remaining messages newest
+    /// first.
+    pub fn lifo(size: Option<usize>) -> (Self, ActorRef<A>) {
+        let (multi_sender, multi_receiver) = channel::ordered(size, Lifo::new());
+        Self::from_channel(multi_sender, multi_receiver)
+    }
+
+    /// Creates a mailbox that schedules messages fairly across weighted tiers. `tiers` lists each
+    /// tier with its weight, from highest to lowest priority: in every round, each tier delivers
+    /// up to `weight` of its queued messages before lower tiers get their turn, so every tier with
+    /// a weight above zero is guaranteed to make progress. Tiers with a weight of zero are only
+    /// served when no other tier has queued messages.
+    ///
+    /// Messages are assigned a tier with [`ActorRef::send_tiered`]; messages sent in any other way
+    /// are in `Tier(0)`. Messages in a tier that is not listed are scheduled with the last tier.
+    /// The same scheduling applies while the actor drains its mailbox on stop.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tiers` is empty.
+    pub fn weighted(size: Option<usize>, tiers: &[(Tier, u32)]) -> (Self, ActorRef<A>) {
+        let (multi_sender, multi_receiver) = channel::ordered(size, Weighted::new(tiers));
+        Self::from_channel(multi_sender, multi_receiver)
+    }
+
+    /// Creates a mailbox that delivers messages by priority, as assigned to each message by
+    /// `priority` when it is sent: each receive returns the queued message with the highest
+    /// priority. Messages with equal priority are received in the order they were sent. The same
+    /// ordering applies while the actor drains its mailbox on stop.
+    pub fn priority_fn(
+        size: Option<usize>,
+        priority: impl Fn(&A::Message) -> u8 + Send + 'static,
+    ) -> (Self, ActorRef<A>) {
+        let (multi_sender, multi_receiver) = channel::ordered(size, Prioritized::new(priority));
+        Self::from_channel(multi_sender, multi_receiver)
+    }
+
+    /// Creates a mailbox for actors that track a value which is overwritten often, such as the
+    /// latest reading of a sensor. A message sent with [`ActorRef::send_coalesced`] replaces the
+    /// queued message with the same key, if there is one, and takes its place in the queue, so
+    /// that the actor only handles the latest update per key and the backlog stays bounded by
+    /// the number of keys. A replacement fits even if the mailbox is full.
+    ///
+    /// Other messages are delivered in FIFO order, as with [`Mailbox::new`]. The message that
+    /// was replaced is dropped: if it was sent with [`ActorRef::send_ack`], its sender gets
+    /// [`Unacked`](crate::Unacked).
+    pub fn coalescing(size: Option<usize>) -> (Self, ActorRef<A>) {
+        let (multi_sender, multi_receiver) = channel::ordered(size, Coalesced::new());
+        Self::from_channel(multi_sender, multi_receiver)
+    }
+
+    fn from_channel(
+        multi_sender: MultiSender<A::Message>,
+        multi_receiver: MultiReceiver<A::Message>,
+    ) -> (Self, ActorRef<A>) {
         let (stop_sender, stop_receiver) = async_oneshot_channel::oneshot();
-        let actor_ref = ActorRef {
-            sender: multi_sender,
-            stop: stop_sender,
-        };
+        let (control_sender, control_receiver) = async_channel::unbounded();
+        let shared = Arc::new(Shared::default());
+        let actor_ref = ActorRef::new(multi_sender, stop_sender, control_sender, shared);
+        let messages = Arc::new(multi_receiver);
+        let this = actor_ref.downgrade();
+        #[cfg(debug_assertions)]
+        let this = this.into_self();
+        #[allow(deprecated)]
         let mailbox = Self {
-            receiver: multi_receiver,
+            receiver: MessageReceiver(messages.clone()),
+            messages,
             stop: stop_receiver,
-            this: actor_ref.downgrade(),
+            this,
+            control: control_receiver,
+            in_flight: Mutex::new(InFlight::default()),
+            handled: AtomicU64::new(0),
+            followups: Mutex::new(VecDeque::new()),
+            stash: Mutex::new(Stash::new()),
+            express: Mutex::new(VecDeque::new()),
+            barriers: Mutex::new(Vec::new()),
+            watchers: Mutex::new(Vec::new()),
+            escalated: Mutex::new(None),
+            recorder: None,
+            persister: None,
+            handler_timeout: None,
+            trigger: None,
+            dispatcher: Mutex::new(None),
+            layers: Vec::new(),
+            snapshot: None,
+            describe: None,
         };
         (mailbox, actor_ref)
     }
 
-    pub fn recv(
+    /// Receives the next message from the message channel, ignoring the stop channel. Returns
+    /// [`None`] once the channel is closed and empty.
+    ///
+    /// Follow-up messages queued with [`Mailbox::push_followups`] are received first, then
+    /// unstashed messages as ordered by the mailbox's [`UnstashMode`].
+    pub async fn recv_msg(&self) -> Option<A::Message> {
+        let followup = self.followups.lock().unwrap().pop_front();
+        if let Some(msg) = followup {
+            return Some(self.deliver_followup(msg));
+        }
+        if let Some(msg) = self.try_recv_unstashed() {
+            return Some(msg);
+        }
+        let packet = self.messages.recv().await.ok()?;
+        Some(self.deliver(packet))
+    }
+
+    /// Like [`Mailbox::recv_msg`], but returns [`None`] right away if no message is queued.
+    pub fn try_recv_msg(&self) -> Option<A::Message> {
+        let followup = self.followups.lock().unwrap().pop_front();
+        if let Some(msg) = followup {
+            return Some(self.deliver_followup(msg));
+        }
+        if let Some(msg) = self.try_recv_unstashed() {
+            return Some(msg);
+        }
+        let packet = self.messages.try_recv().ok()?;
+        Some(self.deliver(packet))
+    }
+
+    /// Sets aside `msg`, which must be the message most recently received from this mailbox,
+    /// along with its acknowledgement and metadata, until [`Mailbox::unstash`] is called. The
+    /// default run loop calls this for the messages that [`Actor::should_stash`] defers.
+    ///
+    /// A stashed message no longer holds back flushes: [`ActorRef::flush`] counts it as done
+    /// once it is stashed, and does not wait for it again when it is unstashed. Its
+    /// acknowledgement fires once it is handled after being unstashed.
+    pub fn stash(&self, msg: A::Message) {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        let packet = Packet {
+            ack: in_flight.ack.take(),
+            meta: in_flight.meta.take().map(Box::new),
+            deadline: in_flight.deadline.take(),
+            ..Packet::new(msg)
+        };
+        drop(in_flight);
+        self.stash.lock().unwrap().stashed.push_back(packet);
+    }
+
+    /// Releases every stashed message to be received again, in the order they were stashed,
+    /// after any released earlier and not received yet. See [`UnstashMode`] for how they are
+    /// ordered against the messages queued in the mailbox. A message whose deadline passed
+    /// while it was stashed is reported as such when it is received again.
+    pub fn unstash(&self) {
+        self.stash.lock().unwrap().unstash();
+    }
+
+    /// Sets how unstashed messages are ordered against the messages queued in the mailbox. The
+    /// default is [`UnstashMode::Priority`].
+    pub fn set_unstash_mode(&mut self, mode: UnstashMode) {
+        self.stash.get_mut().unwrap().mode = mode;
+    }
+
+    /// Sets the [`Dispatcher`] that the default run loop receives messages through, e.g. to
+    /// batch or filter messages on top of the mailbox's own ordering.
+    pub fn set_dispatcher(&mut self, dispatcher: impl Dispatcher<A> + 'static) {
+        *self.dispatcher.get_mut().unwrap() = Some(Box::new(dispatcher));
+    }
+
+    /// Adds a [`Layer`] around the handler of the default run loop. Layers added first are
+    /// outermost, so they see each message first and each result last.
+    pub fn layer(&mut self, layer: impl Layer<A>) {
+        self.layers.push(Box::new(layer));
+    }
+
+    /// Gives the actor an output channel, turning it into a source of `Out` values. Handlers
+    /// emit values with [`WeakActorRef::emit`], and callers receive them from
+    /// [`ActorRef::outputs`], which implements `Stream`. The channel is closed once the actor
+    /// stops, which ends the stream after the remaining values are received.
+    ///
+    /// `output_size` bounds the channel like the mailbox size bounds the mailbox: once it is
+    /// full, `emit` waits until a value is received. If `None`, the channel is unbounded, and
+    /// values nobody receives are kept until the actor is dropped. Only the first call has an
+    /// effect.
+    pub fn set_outputs<Out: Send + 'static>(&mut self, output_size: Option<usize>) {
+        let _ = self
+            .this
+            .shared
+            .outputs
+            .set(Outputs::new::<Out>(output_size));
+    }
+
+    /// Sheds load once the mailbox is backed up: while the mailbox holds `shed_above` or more
+    /// messages, new messages are rejected right away rather than queued, and
+    /// [`Actor::on_shed`] is called for each of them. Sending methods return the message, with
+    /// [`TrySendError::Shed`](crate::TrySendError::Shed) for [`ActorRef::try_send`]. Only the
+    /// first call has an effect.
+    ///
+    /// Unlike a full bounded mailbox, which makes senders wait, this rejects work at a soft
+    /// threshold below the capacity, which keeps the latency of accepted messages bounded.
+    pub fn set_shed_above(&mut self, shed_above: usize) {
+        let _ = self.this.shared.shed_above.set(shed_above);
+    }
+
+    /// Records every message the actor receives, and its stop message, in the returned
+    /// [`Recorder`]. The log can be replayed into a fresh actor with [`replay`](crate::replay)
+    /// to reproduce a bug.
+    pub fn start_recording(&mut self) -> Recorder<A>
+    where
+        A::Message: Clone,
+    {
+        let (recorder, record) = Recorder::new();
+        self.recorder = Some(record);
+        recorder
+    }
+
+    /// Limits how long each call to [`Actor::on_msg`] may take. As the crate is
+    /// runtime-agnostic, `sleep_fn` must return a future that completes after the given
+    /// duration, such as `tokio::time::sleep`.
+    ///
+    /// A handler that exceeds `timeout` is cancelled at its next await point, and
+    /// [`Actor::on_msg_timeout`] is called instead. Cancelling a handler midway can leave the
+    /// actor's state inconsistent, so only use this for actors whose handlers can be safely
+    /// abandoned, or that can recover in `on_msg_timeout`. A handler that never yields cannot
+    /// be cancelled.
+    pub fn set_handler_timeout<S>(
+        &mut self,
+        timeout: Duration,
+        sleep_fn: impl Fn(Duration) -> S + Send + Sync + 'static,
+    ) where
+        S: Future + Send + 'static,
+    {
+        self.handler_timeout = Some(HandlerTimeout::new(timeout, sleep_fn));
+    }
+
+    /// Makes each message all-or-nothing: the run loop clones the actor before every call to
+    /// [`Actor::on_msg`], and if the handler fails and [`Actor::on_error`] returns
+    /// [`ErrorAction::Rollback`](crate::ErrorAction::Rollback), it puts the clone back in place
+    /// of the partially updated actor before receiving the next message. The failed message is
+    /// not acknowledged, and the actor keeps running.
+    ///
+    /// Cloning the actor for every message is expensive for actors with a lot of state. Only
+    /// the actor itself is rolled back: messages it sent or other side effects of the handler
+    /// are not undone.
+    pub fn set_transactional(&mut self)
+    where
+        A: Clone,
+    {
+        self.snapshot = Some(A::clone);
+    }
+
+    /// Keeps track of the message that broke the actor: the run loop formats every message
+    /// with `Debug` before handing it to [`Actor::on_msg`], and if the handler fails the actor,
+    /// keeps the formatted message. Use
+    /// [`ActorRun::with_failed_message`](crate::ActorRun::with_failed_message) to get it along
+    /// with the error.
+    ///
+    /// Formatting every message is expensive, so this is meant for debugging rather than for
+    /// hot paths. Only the first failing message is kept, e.g. when draining continues after an
+    /// error, see [`Actor::on_drain_error`].
+    pub fn set_traced(&mut self)
+    where
+        A::Message: std::fmt::Debug,
+    {
+        self.describe = Some(|msg| format!("{msg:?}"));
+    }
+
+    /// Makes the run loop also wait on an external event source, such as a file watcher or a
+    /// config reload signal, alongside the mailbox. Each time the future returned by
+    /// `trigger_fn` completes, `on_external` is called with the actor and its own reference,
+    /// and a new future is requested from `trigger_fn`; the actor keeps running.
+    ///
+    /// Messages and stop messages that are ready take priority over the trigger. `on_external`
+    /// is synchronous, so longer work should be done by sending the actor a message from it.
+    pub fn set_trigger<T>(
+        &mut self,
+        trigger_fn: impl FnMut() -> T + Send + 'static,
+        on_external: impl FnMut(&mut A, &WeakActorRef<A>) + Send + 'static,
+    ) where
+        T: Future + Send + 'static,
+    {
+        self.trigger = Some(Trigger::new(trigger_fn, on_external));
+    }
+
+    /// Gives the actor a way to spawn background work on the caller's runtime, e.g.
+    /// `|work| { tokio::spawn(work); }`. Handlers start such work with
+    /// [`WeakActorRef::detach`], which lets them return, and the actor move on to its next
+    /// message, while the work is still running. Only the first call has an effect.
+    pub fn set_spawner(&mut self, spawn_fn: impl Fn(Detached) + Send + Sync + 'static) {
+        let _ = self.this.shared.spawner.set(Spawner(Box::new(spawn_fn)));
+    }
+
+    /// Makes the run loop hand at most `rate` messages per second to the actor, sleeping
+    /// between messages with `sleep_fn` once the rate is exceeded. As the crate is
+    /// runtime-agnostic, `sleep_fn` must return a future that completes after the given
+    /// duration, such as `tokio::time::sleep`. Only the first call has an effect.
+    ///
+    /// The limit is a token bucket holding up to `rate` tokens, so bursts of up to `rate`
+    /// messages are handled right away after a quiet period. Only processing is throttled:
+    /// senders queue messages as usual, and the mailbox fills up while the actor waits. The
+    /// number of available tokens is reported by [`ActorRef::rate_limit_tokens`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate` is zero.
+    pub fn set_rate_limit<S>(
+        &mut self,
+        rate: u32,
+        sleep_fn: impl Fn(Duration) -> S + Send + Sync + 'static,
+    ) where
+        S: Future + Send + 'static,
+    {
+        let _ = self
+            .this
+            .shared
+            .rate_limit
+            .set(RateLimit::new(rate, sleep_fn));
+    }
+
+    /// Makes the run loop write a snapshot of the actor, taken with [`Actor::persist`], to
+    /// `sink` after every `every` handled messages, and once more when the actor stops, before
+    /// [`Actor::on_stop`]. Snapshots are written on the actor's task, between messages.
+    ///
+    /// To recover from a crash, create the next run from the last snapshot with
+    /// [`Actor::restore`], e.g. in the factory of a
+    /// [`BackoffSupervisor`](crate::BackoffSupervisor). See [`SnapshotSink`] for writing
+    /// snapshots so that a crash mid-write doesn't corrupt the last one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `every` is zero.
+    pub fn set_snapshot_sink(&mut self, every: usize, sink: impl SnapshotSink) {
+        self.persister = Some(Persister::new(every, sink));
+    }
+
+    /// Returns the number of messages currently queued in the mailbox.
+    pub fn len(&self) -> usize {
+        self.messages.len()
+            + self.followups.lock().unwrap().len()
+            + self.stash.lock().unwrap().unstashed.len()
+    }
+
+    /// Queues `msgs` to be received next, in order, ahead of the messages in the message
+    /// channel. This never waits for space in the mailbox, so it is safe to use from within the
+    /// actor even when its mailbox is bounded and full. The default run loop calls this with the
+    /// messages returned by [`Actor::on_msg_emit`].
+    pub fn push_followups(&self, msgs: impl IntoIterator<Item = A::Message>) {
+        self.followups.lock().unwrap().extend(msgs);
+    }
+
+    /// Returns `true` if there are no messages queued in the mailbox.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Closes the message channel so that no further messages can be sent. Messages already
+    /// in the mailbox can still be received. Returns `true` if this call closed the channel.
+    pub fn close(&self) -> bool {
+        self.messages.close()
+    }
+
+    /// Marks the actor as started, so that [`ActorRef::is_started`] returns `true` and
+    /// [`ActorRef::wait_started`] resolves. The default run loop calls this right after
+    /// [`Actor::on_start`] succeeds; custom run loops should do the same.
+    pub fn set_started(&self) {
+        self.this.shared.set_started();
+    }
+
+    /// Marks the message most recently received from this mailbox as successfully handled.
+    /// This fires the acknowledgement for messages sent with
+    /// [`ActorRef::send_ack`](crate::ActorRef::send_ack).
+    ///
+    /// Custom run loops should call this after each message is handled without error.
+    /// Receiving another message without completing the previous one leaves it unacknowledged.
+    pub fn complete(&self) {
+        if let Some(ack) = self.take_in_flight() {
+            ack.fire();
+        }
+    }
+
+    /// Takes the in-flight message's acknowledgement, for run loops that complete messages out
+    /// of order.
+    pub(crate) fn take_in_flight(&self) -> Option<Ack> {
+        self.in_flight.lock().unwrap().ack.take()
+    }
+
+    /// Drops the in-flight message's acknowledgement without firing it, for a message that
+    /// will not complete.
+    pub(crate) fn abandon(&self) {
+        self.in_flight.lock().unwrap().ack.take();
+    }
+
+    /// Registers a flush, fired once every message queued right now has been handled.
+    pub(crate) fn add_barrier(&self, ack: Ack) {
+        match self.len() {
+            0 => ack.fire(),
+            len => self.barriers.lock().unwrap().push((len, ack)),
+        }
+    }
+
+    /// Returns `true` if the message most recently handed out was queued in the mailbox, and
+    /// so counts towards flushes with [`Mailbox::settle_barriers`]. Messages from the express
+    /// lane, see [`ActorRef::send_priority`], and unstashed messages, which were counted when
+    /// they were stashed, don't.
+    pub(crate) fn is_queued(&self) -> bool {
+        !self.in_flight.lock().unwrap().unqueued
+    }
+
+    /// Counts one more handled message towards the registered flushes, firing those that are
+    /// done.
+    pub(crate) fn settle_barriers(&self) {
+        let mut barriers = self.barriers.lock().unwrap();
+        for (remaining, _) in barriers.iter_mut() {
+            *remaining -= 1;
+        }
+        for (_, ack) in barriers.extract_if(.., |(remaining, _)| *remaining == 0) {
+            ack.fire();
+        }
+    }
+
+    /// Registers a predicate over the actor's state, fired once it holds.
+    pub(crate) fn add_watcher(&self, actor: &A, watcher: Watcher<A>, ack: Ack) {
+        if watcher(actor) {
+            ack.fire();
+        } else {
+            self.watchers.lock().unwrap().push((watcher, ack));
+        }
+    }
+
+    /// Evaluates the registered predicates against the actor's state, firing those that hold.
+    pub(crate) fn check_watchers(&self, actor: &A) {
+        let mut watchers = self.watchers.lock().unwrap();
+        for (_, ack) in watchers.extract_if(.., |(watcher, _)| watcher(actor)) {
+            ack.fire();
+        }
+    }
+
+    /// Records a stop upgrade, keeping it only if it is more severe than earlier ones.
+    pub(crate) fn escalate(&self, stop: A::Message, more_severe: MoreSevere<A::Message>) {
+        let mut escalated = self.escalated.lock().unwrap();
+        if escalated
+            .as_ref()
+            .is_none_or(|(cur, _)| more_severe(&stop, cur))
+        {
+            *escalated = Some((stop, more_severe));
+        }
+    }
+
+    /// Picks the stop message for [`Actor::on_stop`]: the most severe of `stop` and the upgrades
+    /// requested so far. Control operations still pending are settled first, as the run loop
+    /// will not receive them anymore; a pending state replacement is dropped, and express
+    /// messages are set aside to be handled before [`Actor::on_stop`].
+    pub(crate) fn final_stop(&self, actor: &A, stop: Option<A::Message>) -> Option<A::Message> {
+        while let Ok(control) = self.control.try_recv() {
+            match control {
+                Control::Escalate(stop, more_severe) => self.escalate(stop, more_severe),
+                Control::Flush(ack) => self.add_barrier(ack),
+                Control::Watch(watcher, ack) => self.add_watcher(actor, watcher, ack),
+                Control::Express(msg) => self.express.lock().unwrap().push_back(msg),
+                Control::Replace(_) | Control::TakeBacklog(_) => {}
+            }
+        }
+        match (stop, self.escalated.lock().unwrap().take()) {
+            (Some(stop), Some((escalated, more_severe))) if !more_severe(&escalated, &stop) => {
+                Some(stop)
+            }
+            (_, Some((escalated, _))) => Some(escalated),
+            (stop, None) => stop,
+        }
+    }
+
+    /// Stops all children spawned with [`WeakActorRef::spawn_child`] that are still running.
+    /// The default run loop calls this right before [`Actor::on_stop`] when the actor stops;
+    /// custom run loops should do the same. Children are also stopped when the mailbox is
+    /// dropped.
+    pub fn stop_children(&self) {
+        self.this.shared.stop_children();
+    }
+
+    /// Takes the metadata of the message most recently received from this mailbox, if it was
+    /// sent with [`ActorRef::send_enveloped`]. The default run loop passes it to
+    /// [`Actor::on_msg_meta`].
+    pub fn take_meta(&self) -> Option<Meta> {
+        self.in_flight.lock().unwrap().meta.take()
+    }
+
+    /// Returns `true` if the message most recently received from this mailbox was sent with
+    /// [`ActorRef::send_ttl`] and had outlived its TTL when it was received, or was sent with
+    /// [`ActorRef::send_by`] and had missed its deadline. The default run loop passes such
+    /// messages to [`Actor::on_expired`] or [`Actor::on_deadline_exceeded`] instead of the
+    /// handler.
+    pub fn is_expired(&self) -> bool {
+        self.in_flight.lock().unwrap().expired
+    }
+
+    /// Returns the deadline of the message most recently received from this mailbox if it was
+    /// sent with [`ActorRef::send_by`] and its deadline had passed when it was received. The
+    /// default run loop passes such messages to [`Actor::on_deadline_exceeded`] instead of the
+    /// handler.
+    pub fn deadline_exceeded(&self) -> Option<Deadline> {
+        let in_flight = self.in_flight.lock().unwrap();
+        in_flight.deadline.filter(|_| in_flight.expired)
+    }
+
+    /// Builds the [`HandlerCtx`] for the message that was just received, counting it as handled.
+    pub(crate) fn handler_ctx(&self) -> HandlerCtx {
+        let backlog = self.len();
+        let mut in_flight = self.in_flight.lock().unwrap();
+        HandlerCtx {
+            backlog,
+            seq: self.handled.fetch_add(1, Ordering::Relaxed),
+            meta: in_flight.meta.take(),
+            deadline: in_flight.deadline.take(),
+        }
+    }
+
+    /// Takes the next unstashed message if it is due, see [`UnstashMode`]. It was recorded when
+    /// it was first received, and is tracked again with the acknowledgement and metadata it
+    /// was stashed with.
+    fn try_recv_unstashed(&self) -> Option<A::Message> {
+        let packet = self.stash.lock().unwrap().next(self.messages.len())?;
+        let msg = self.track(packet);
+        self.in_flight.lock().unwrap().unqueued = true;
+        Some(msg)
+    }
+
+    /// Closes the mailbox and removes every message still queued, in delivery order, for
+    /// [`ActorRef::take_backlog`]. Acknowledgements of the removed messages are dropped.
+    pub(crate) fn take_backlog(&self) -> Vec<A::Message> {
+        self.this.shared.request_stop();
+        self.close();
+        let mut backlog: Vec<_> = self.express.lock().unwrap().drain(..).collect();
+        backlog.extend(self.followups.lock().unwrap().drain(..));
+        let mut stash = self.stash.lock().unwrap();
+        backlog.extend(stash.unstashed.drain(..).map(|packet| packet.msg));
+        backlog.extend(stash.stashed.drain(..).map(|packet| packet.msg));
+        drop(stash);
+        while let Ok(packet) = self.messages.try_recv() {
+            backlog.push(packet.msg);
+        }
+        backlog
+    }
+
+    /// Takes the next message from the express lane, prepared for the handler like a message
+    /// received from the channel without metadata.
+    pub(crate) fn try_recv_express(&self) -> Option<A::Message> {
+        let msg = self.express.lock().unwrap().pop_front()?;
+        self.record(Recorded::Message(&msg));
+        let msg = self.deliver_followup(msg);
+        self.in_flight.lock().unwrap().unqueued = true;
+        Some(msg)
+    }
+
+    fn deliver_followup(&self, msg: A::Message) -> A::Message {
+        *self.in_flight.lock().unwrap() = InFlight::default();
+        msg
+    }
+
+    fn deliver(&self, packet: Packet<A::Message>) -> A::Message {
+        self.record(Recorded::Message(&packet.msg));
+        self.track(packet)
+    }
+
+    /// Makes `packet` the in-flight message, taking over its delivery tracking.
+    fn track(&self, packet: Packet<A::Message>) -> A::Message {
+        let now = Instant::now();
+        let expired = packet.expires_at.is_some_and(|at| at <= now)
+            || packet
+                .deadline
+                .is_some_and(|deadline| deadline.instant() <= now);
+        *self.in_flight.lock().unwrap() = InFlight {
+            ack: packet.ack,
+            meta: packet.meta.map(|meta| *meta),
+            expired,
+            deadline: packet.deadline,
+            unqueued: false,
+        };
+        packet.msg
+    }
+
+    /// Receives the next stop message or message, whichever is ready first. The stop channel
+    /// takes priority if both are ready.
+    ///
+    /// Once the actor is aborted with its [`AbortHandle`](crate::AbortHandle), this returns
+    /// [`MailboxEvent::Stop`] with [`None`], so that custom run loops stop without draining.
+    pub async fn recv(&self) -> MailboxEvent<A> {
+        let shared = &self.this.shared;
+        match biased(
+            shared.abort.aborted(),
+            biased(self.stop.recv(), self.recv_msg()),
+        )
+        .await
+        {
+            Either::Left(()) => {
+                shared.set_aborted();
+                MailboxEvent::Stop(None)
+            }
+            Either::Right(Either::Left(stop)) => MailboxEvent::Stop(stop),
+            Either::Right(Either::Right(msg)) => MailboxEvent::Message(msg),
+        }
+    }
+
+    /// Receives the next event for the default run loop, taking messages from `msg`. An abort
+    /// takes priority over control operations, which take priority over the stop channel, which
+    /// takes priority over the express lane, then messages, and then the trigger, if there is
+    /// one.
+    ///
+    /// Express messages travel through the control channel, and are set aside as they arrive so
+    /// that a stop sent after them is still received first.
+    pub(crate) async fn next_event(
         &self,
-    ) -> MailboxRecv<
-        impl Future<Output = Option<A::Message>> + '_,
-        impl Future<Output = Option<A::Message>> + '_,
-    > {
-        MailboxRecv {
-            stop: self.stop.recv(),
-            msg: async { self.receiver.recv().await.ok() },
+        msg: impl Future<Output = Option<A::Message>>,
+    ) -> Event<A> {
+        let mut msg = pin!(msg);
+        loop {
+            let control = async {
+                match self.control.recv().await {
+                    Ok(control) => control,
+                    // Every `ActorRef` has been dropped, so the message channel decides when to
+                    // stop.
+                    Err(_) => std::future::pending().await,
+                }
+            };
+            let trigger = async {
+                match &self.trigger {
+                    Some(trigger) => trigger.fired().await,
+                    None => std::future::pending().await,
+                }
+            };
+            // Only filled by this loop, so it does not need to wake the task.
+            let express = poll_fn(|_| match self.try_recv_express() {
+                Some(msg) => Poll::Ready(msg),
+                None => Poll::Pending,
+            });
+            let recv = biased(self.stop.recv(), biased(express, msg.as_mut()));
+            let event = biased(control, biased(recv, trigger));
+            let event = match biased(self.this.shared.abort.aborted(), event).await {
+                Either::Left(()) => return Event::Abort,
+                Either::Right(event) => event,
+            };
+            return match event {
+                Either::Left(Control::Express(msg)) => {
+                    self.express.lock().unwrap().push_back(msg);
+                    continue;
+                }
+                Either::Left(control) => Event::Control(control),
+                Either::Right(Either::Left(Either::Left(stop))) => Event::Stop(stop),
+                Either::Right(Either::Left(Either::Right(Either::Left(msg)))) => {
+                    Event::Express(msg)
+                }
+                Either::Right(Either::Left(Either::Right(Either::Right(msg)))) => {
+                    Event::Message(msg)
+                }
+                Either::Right(Either::Right(())) => Event::External,
+            };
         }
     }
 }
 
-pin_project_lite::pin_project! {
-    #[derive(Debug)]
-    #[must_use = "futures do nothing unless you `.await` or poll them"]
-    /// Convenience future that polls both the stop and message receivers, prioritizing the stop receiver.
-    pub struct MailboxRecv<F1, F2> {
-        #[pin]
-        stop: F1,
-        #[pin]
-        msg: F2,
-    }
+/// What [`Mailbox::recv`] received.
+pub enum MailboxEvent<A: Actor> {
+    /// A stop message, or [`None`] if the stop channel was closed without one, i.e. every
+    /// [`ActorRef`] to the actor has been dropped.
+    Stop(Option<A::Message>),
+    /// A message, or [`None`] if the message channel is closed and empty.
+    Message(Option<A::Message>),
 }
 
-impl<T, U, F1, F2> Future for MailboxRecv<F1, F2>
+impl<A: Actor> std::fmt::Debug for MailboxEvent<A>
 where
-    F1: Future<Output = T>,
-    F2: Future<Output = U>,
+    A::Message: std::fmt::Debug,
 {
-    type Output = Either<T, U>;
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Stop(stop) => f.debug_tuple("Stop").field(stop).finish(),
+            Self::Message(msg) => f.debug_tuple("Message").field(msg).finish(),
+        }
+    }
+}
 
-    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let this = self.project();
+/// An event received by the default run loop, see [`Mailbox::next_event`].
+pub(crate) enum Event<A: Actor> {
+    /// A control operation other than [`Control::Express`], which arrives as [`Event::Express`].
+    Control(Control<A>),
+    Stop(Option<A::Message>),
+    /// A message from the express lane, ready for the handler.
+    Express(A::Message),
+    Message(Option<A::Message>),
+    External,
+    /// The actor was aborted with its [`AbortHandle`](crate::AbortHandle).
+    Abort,
+}
 
-        if let Poll::Ready(t) = this.stop.poll(cx) {
-            return Poll::Ready(Either::Left(t));
-        }
-        if let Poll::Ready(u) = this.msg.poll(cx) {
-            return Poll::Ready(Either::Right(u));
-        }
-        Poll::Pending
+/// Receives messages straight from a mailbox's message channel, see [`Mailbox::receiver`].
+///
+/// Messages received this way bypass the mailbox's tracking: follow-ups queued with
+/// [`Mailbox::push_followups`] are not received, messages sent with
+/// [`ActorRef::send_ack`](crate::ActorRef::send_ack) are never acknowledged, and TTLs and
+/// deadlines are not checked. Prefer [`Mailbox::recv_msg`] and [`Mailbox::try_recv_msg`].
+pub struct MessageReceiver<M>(Arc<MultiReceiver<M>>);
+
+impl<M> MessageReceiver<M> {
+    /// Receives the next message, or fails once the channel is closed and empty.
+    pub async fn recv(&self) -> Result<M, RecvError> {
+        self.0.recv().await.map(|packet| packet.msg)
+    }
+
+    /// Receives the next message if one is queued, without waiting.
+    pub fn try_recv(&self) -> Result<M, TryRecvError> {
+        self.0.try_recv().map(|packet| packet.msg)
+    }
+
+    /// Closes the channel, see [`Mailbox::close`].
+    pub fn close(&self) -> bool {
+        self.0.close()
+    }
+
+    /// Returns the number of messages queued in the channel.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if no messages are queued in the channel.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<M> std::fmt::Debug for MessageReceiver<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MessageReceiver")
+            .field("len", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<A: Actor> Drop for Mailbox<A> {
+    fn drop(&mut self) {
+        // Release any messages left behind, so that senders waiting on an acknowledgement are
+        // notified instead of waiting for the last `ActorRef` to be dropped.
+        self.messages.close();
+        while self.messages.try_recv().is_ok() {}
+        // The run loop normally stops children before `on_stop`, but not if it exited early.
+        self.this.shared.stop_children();
+        self.this.shared.set_exited();
     }
 }
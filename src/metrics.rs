@@ -0,0 +1,120 @@
+//! Runtime statistics about an actor, collected by the default run loop when the `metrics`
+//! feature is enabled.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::{shared::Shared, Actor, ActorRef};
+
+/// Number of histogram buckets. Bucket `i` counts durations of less than `2^i` nanoseconds
+/// that did not fit in bucket `i - 1`, so the last bucket covers everything from about 2.3
+/// minutes up.
+const BUCKETS: usize = 39;
+
+/// A lock-free histogram of handler durations with power-of-two buckets, which keeps recording
+/// down to a handful of atomic additions per message at the cost of percentiles only being
+/// accurate to within a factor of two.
+#[derive(Debug)]
+pub(crate) struct Histogram {
+    buckets: [AtomicU64; BUCKETS],
+    count: AtomicU64,
+    sum_nanos: AtomicU64,
+    min_nanos: AtomicU64,
+    max_nanos: AtomicU64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            count: AtomicU64::new(0),
+            sum_nanos: AtomicU64::new(0),
+            min_nanos: AtomicU64::new(u64::MAX),
+            max_nanos: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Histogram {
+    pub(crate) fn record(&self, duration: Duration) {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        let bucket = (u64::BITS - nanos.leading_zeros()) as usize;
+        self.buckets[bucket.min(BUCKETS - 1)].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.min_nanos.fetch_min(nanos, Ordering::Relaxed);
+        self.max_nanos.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> LatencySnapshot {
+        let count = self.count.load(Ordering::Relaxed);
+        if count == 0 {
+            return LatencySnapshot::default();
+        }
+        let max = self.max_nanos.load(Ordering::Relaxed);
+        // The smallest bucket bound below which at least 99% of the durations fall.
+        let target = count - count / 100;
+        let mut seen = 0;
+        let mut p99 = max;
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            seen += bucket.load(Ordering::Relaxed);
+            if seen >= target {
+                p99 = 1u64.checked_shl(i as u32).unwrap_or(u64::MAX).min(max);
+                break;
+            }
+        }
+        LatencySnapshot {
+            count,
+            min: Duration::from_nanos(self.min_nanos.load(Ordering::Relaxed)),
+            max: Duration::from_nanos(max),
+            mean: Duration::from_nanos(self.sum_nanos.load(Ordering::Relaxed) / count),
+            p99: Duration::from_nanos(p99),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// Statistics about how long an actor's handler calls took, see
+/// [`ActorMetrics::handler_latency`]. All durations are zero if no message has been handled.
+pub struct LatencySnapshot {
+    /// The number of handler calls measured.
+    pub count: u64,
+    /// The shortest handler call.
+    pub min: Duration,
+    /// The longest handler call.
+    pub max: Duration,
+    /// The average duration of a handler call.
+    pub mean: Duration,
+    /// The duration that 99% of handler calls did not exceed. This is an upper bound, accurate
+    /// to within a factor of two, and never more than [`LatencySnapshot::max`].
+    pub p99: Duration,
+}
+
+#[derive(Debug, Clone)]
+/// A handle to an actor's runtime statistics, returned by [`ActorRef::metrics`]. It doesn't
+/// keep the actor alive, and keeps reporting the final statistics once the actor has stopped.
+pub struct ActorMetrics {
+    shared: Arc<Shared>,
+}
+
+impl ActorMetrics {
+    /// Returns statistics about the duration of every call to the actor's message handler so
+    /// far, as measured by the default run loop with [`std::time::Instant`].
+    pub fn handler_latency(&self) -> LatencySnapshot {
+        self.shared.handler_latency.snapshot()
+    }
+}
+
+impl<A: Actor> ActorRef<A> {
+    /// Returns a handle to the actor's runtime statistics.
+    pub fn metrics(&self) -> ActorMetrics {
+        ActorMetrics {
+            shared: self.weak.shared.clone(),
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Wake, Waker},
+};
+
+use crate::{Actor, ActorRef, ActorRun, RunError};
+
+type Run = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+#[derive(Default)]
+/// Actors that are ready to be polled, shared with the wakers handed to them.
+struct ReadyQueue {
+    ready: Mutex<Vec<usize>>,
+    /// The waker of the task running the [`MultiActor`].
+    waker: Mutex<Option<Waker>>,
+}
+
+/// Wakes one actor of a [`MultiActor`], by queueing it and waking the shared task.
+struct ActorWaker {
+    index: usize,
+    queue: Arc<ReadyQueue>,
+}
+
+impl Wake for ActorWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.queue.ready.lock().unwrap().push(self.index);
+        if let Some(waker) = &*self.queue.waker.lock().unwrap() {
+            waker.wake_by_ref();
+        }
+    }
+}
+
+/// Runs several actors on a single future, so that many small actors can share one task
+/// instead of being spawned on a task each. Every actor still has its own mailbox and
+/// [`ActorRef`]. The future resolves once all of the actors have stopped.
+///
+/// Scheduling is cooperative: the actors are only polled when they are woken, and each woken
+/// actor is polled once before the future yields back to the runtime, so an actor that is
+/// waiting on I/O or its mailbox doesn't hold up the others. A handler that runs for a long
+/// time without awaiting does block every actor on the same future, though, just as it would
+/// block the rest of a task; such actors should get a task of their own.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct MultiActor {
+    runs: Vec<Option<Run>>,
+    wakers: Vec<Waker>,
+    queue: Arc<ReadyQueue>,
+    running: usize,
+}
+
+impl MultiActor {
+    /// Creates a runner without any actors.
+    pub fn new() -> Self {
+        Self {
+            runs: Vec::new(),
+            wakers: Vec::new(),
+            queue: Arc::default(),
+            running: 0,
+        }
+    }
+
+    /// Adds `actor` with the given mailbox size, see [`Actor::into_future`], and returns a
+    /// reference to it. The actor's result is discarded once it stops.
+    pub fn add<A: Actor + Send + 'static>(
+        &mut self,
+        actor: A,
+        mailbox_size: Option<usize>,
+    ) -> ActorRef<A> {
+        let (actor_ref, run) = actor.into_future(mailbox_size);
+        self.add_run(run, drop);
+        actor_ref
+    }
+
+    /// Adds an already created [`ActorRun`], e.g. one with a custom mailbox, and calls `on_exit`
+    /// with its result once the actor stops.
+    pub fn add_run<A: Actor + Send + 'static>(
+        &mut self,
+        run: ActorRun<A>,
+        on_exit: impl FnOnce(Result<A, RunError<A>>) + Send + 'static,
+    ) {
+        let index = self.runs.len();
+        self.runs
+            .push(Some(Box::pin(async move { on_exit(run.await) })));
+        self.wakers.push(Waker::from(Arc::new(ActorWaker {
+            index,
+            queue: self.queue.clone(),
+        })));
+        // Every actor is polled once to get it started.
+        self.queue.ready.lock().unwrap().push(index);
+        self.running += 1;
+    }
+
+    /// Returns the number of actors that are still running.
+    pub fn len(&self) -> usize {
+        self.running
+    }
+
+    /// Returns `true` if every actor has stopped, or none were added.
+    pub fn is_empty(&self) -> bool {
+        self.running == 0
+    }
+}
+
+impl Default for MultiActor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Future for MultiActor {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        *this.queue.waker.lock().unwrap() = Some(cx.waker().clone());
+        let ready = std::mem::take(&mut *this.queue.ready.lock().unwrap());
+        for index in ready {
+            let Some(run) = &mut this.runs[index] else {
+                continue;
+            };
+            let mut cx = Context::from_waker(&this.wakers[index]);
+            if run.as_mut().poll(&mut cx).is_ready() {
+                this.runs[index] = None;
+                this.running -= 1;
+            }
+        }
+        if this.running == 0 {
+            return Poll::Ready(());
+        }
+        // Actors woken while the others were polled are left for the next poll, after yielding
+        // to the runtime, so that a busy actor cannot keep the future from returning.
+        Poll::Pending
+    }
+}
+
+impl fmt::Debug for MultiActor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MultiActor")
+            .field("running", &self.running)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,32 @@
+use crate::{Actor, ActorRef, WeakActorRef};
+
+/// An actor that broadcasts events to subscribers, see [`ActorRef::subscribe_events`].
+///
+/// The event type is part of the actor, so subscribers and the actor's handlers always agree
+/// on it. Unlike [`WeakActorRef::emit`], which hands each value to a single receiver, every
+/// subscriber gets every event.
+pub trait Observable: Actor {
+    type Event: Clone + Send + 'static;
+}
+
+impl<A: Observable> ActorRef<A> {
+    /// Subscribes to the events that the actor broadcasts with [`WeakActorRef::broadcast`].
+    /// Every subscriber receives a clone of each event broadcast after it subscribed, from the
+    /// returned receiver, which implements `Stream`. The receiver ends once the actor has stopped
+    /// and the remaining events have been received.
+    ///
+    /// Dropping the receiver unsubscribes. Events are buffered without bound for subscribers
+    /// that don't keep up, so a subscriber should keep receiving for as long as it is alive.
+    pub fn subscribe_events(&self) -> async_channel::Receiver<A::Event> {
+        self.weak.shared.subscribe()
+    }
+}
+
+impl<A: Observable> WeakActorRef<A> {
+    /// Sends a clone of `event` to every subscriber, see [`ActorRef::subscribe_events`], and
+    /// returns the number of subscribers it reached. Never waits, and subscribers that were
+    /// dropped are pruned along the way. Events nobody subscribed to are discarded.
+    pub fn broadcast(&self, event: A::Event) -> usize {
+        self.shared.broadcast(event)
+    }
+}
@@ -0,0 +1,118 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::Actor;
+
+/// Where [`Mailbox::set_snapshot_sink`](crate::Mailbox::set_snapshot_sink) writes the actor's
+/// snapshots.
+///
+/// Each call is handed a complete snapshot that replaces the previous one. To survive a crash
+/// in the middle of a write, a sink should never overwrite the last good snapshot in place:
+/// write the new one to a temporary file, sync it, and rename it over the old one, which is
+/// atomic on the same filesystem. [`SnapshotFile`] does exactly that.
+///
+/// Closures taking the snapshot bytes are sinks too, e.g. to write to an `impl Write` or a
+/// database.
+pub trait SnapshotSink: Send + 'static {
+    /// Writes `snapshot`, replacing the previous snapshot.
+    fn write_snapshot(&mut self, snapshot: &[u8]) -> io::Result<()>;
+}
+
+impl<F: FnMut(&[u8]) -> io::Result<()> + Send + 'static> SnapshotSink for F {
+    fn write_snapshot(&mut self, snapshot: &[u8]) -> io::Result<()> {
+        self(snapshot)
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A [`SnapshotSink`] that keeps the latest snapshot in a file, replacing it atomically.
+///
+/// Snapshots are written to the path with a `.tmp` extension appended, synced to disk, then
+/// renamed over the file, so that the file always holds a complete snapshot, even if the
+/// process crashes while writing. A supervisor restarting the actor reads it back with
+/// [`SnapshotFile::load`] and passes it to [`Actor::restore`].
+pub struct SnapshotFile {
+    path: PathBuf,
+}
+
+impl SnapshotFile {
+    /// Creates a sink that writes snapshots to `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Returns the path of the snapshot file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the latest snapshot, or returns [`None`] if none was written yet.
+    pub fn load(&self) -> io::Result<Option<Vec<u8>>> {
+        match fs::read(&self.path) {
+            Ok(snapshot) => Ok(Some(snapshot)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl SnapshotSink for SnapshotFile {
+    fn write_snapshot(&mut self, snapshot: &[u8]) -> io::Result<()> {
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let file = fs::File::create(&tmp)?;
+        io::Write::write_all(&mut &file, snapshot)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+/// Periodic snapshots of the actor's state, set by
+/// [`Mailbox::set_snapshot_sink`](crate::Mailbox::set_snapshot_sink).
+pub(crate) struct Persister {
+    every: u64,
+    /// The sink, and the number of messages handled since the last snapshot. Locked so that
+    /// the mailbox stays `Sync`.
+    state: Mutex<(Box<dyn SnapshotSink>, u64)>,
+}
+
+impl Persister {
+    pub(crate) fn new(every: usize, sink: impl SnapshotSink) -> Self {
+        assert!(every > 0, "`every` must be at least 1");
+        Self {
+            every: every as u64,
+            state: Mutex::new((Box::new(sink), 0)),
+        }
+    }
+
+    /// Counts a handled message, and writes a snapshot of `actor` every `every` messages, or
+    /// right away if `force` is set.
+    pub(crate) fn handled<A: Actor>(&self, actor: &mut A, force: bool) {
+        let mut state = self.state.lock().unwrap();
+        let (sink, count) = &mut *state;
+        if !force {
+            *count += 1;
+            if *count < self.every {
+                return;
+            }
+        }
+        *count = 0;
+        if let Some(snapshot) = actor.persist() {
+            if let Err(err) = sink.write_snapshot(&snapshot) {
+                drop(state);
+                actor.on_snapshot_error(err);
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for Persister {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Persister")
+            .field("every", &self.every)
+            .finish_non_exhaustive()
+    }
+}
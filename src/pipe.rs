@@ -0,0 +1,93 @@
+use std::{future::Future, pin::Pin, sync::Arc};
+
+use async_oneshot_channel::Receiver as OneshotReceiver;
+use either::Either;
+
+use crate::{select::biased, shared::Shared, Actor, ActorRef, WeakActorRef};
+
+/// A forwarding task, as handed to the `spawn_fn` of [`ActorRef::pipe`],
+/// [`ActorRef::pipe_stream`] and [`WeakActorRef::spawn_and_send`].
+type PipeTask = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Resolves once either actor has exited.
+async fn either_exited(from: Arc<Shared>, to: Arc<Shared>) {
+    biased(from.wait_exited(), to.wait_exited()).await;
+}
+
+impl<A: Actor> ActorRef<A> {
+    /// Forwards the reply to a request made to this actor into the mailbox of actor `to`,
+    /// converted with `map`. `from_reply` is the receiving half of the oneshot channel whose
+    /// sender was handed to this actor, as with [`ActorRef::ask`].
+    ///
+    /// As the crate is runtime-agnostic, the forwarding task is handed to `spawn_fn`, which
+    /// should spawn it, e.g. `|task| { tokio::spawn(task); }`. The task holds no strong
+    /// reference to either actor, and ends without forwarding anything if the reply sender is
+    /// dropped, or if either actor exits before the reply arrives.
+    pub fn pipe<R, B>(
+        &self,
+        from_reply: OneshotReceiver<R>,
+        to: WeakActorRef<B>,
+        map: impl FnOnce(R) -> B::Message + Send + 'static,
+        spawn_fn: impl FnOnce(PipeTask),
+    ) where
+        R: Send + 'static,
+        B: Actor,
+    {
+        let exited = either_exited(self.weak.shared.clone(), to.shared.clone());
+        spawn_fn(Box::pin(async move {
+            if let Either::Left(Some(reply)) = biased(from_reply.recv(), exited).await {
+                let _ = to.send(map(reply)).await;
+            }
+        }));
+    }
+
+    /// Like [`ActorRef::pipe`], but forwards every value received from `from` until it is
+    /// closed, in order. The forwarding task also ends as soon as either actor exits, or a
+    /// value can no longer be delivered to `to`; values still buffered in `from` are then left
+    /// there.
+    pub fn pipe_stream<R, B>(
+        &self,
+        from: async_channel::Receiver<R>,
+        to: WeakActorRef<B>,
+        mut map: impl FnMut(R) -> B::Message + Send + 'static,
+        spawn_fn: impl FnOnce(PipeTask),
+    ) where
+        R: Send + 'static,
+        B: Actor,
+    {
+        let exited = either_exited(self.weak.shared.clone(), to.shared.clone());
+        spawn_fn(Box::pin(async move {
+            let forward = async move {
+                while let Ok(value) = from.recv().await {
+                    if to.send(map(value)).await.is_err() {
+                        break;
+                    }
+                }
+            };
+            biased(forward, exited).await;
+        }));
+    }
+}
+
+impl<A: Actor> WeakActorRef<A> {
+    /// Runs `fut` without blocking the actor, and sends its output back to the actor as a
+    /// message, converted with `map`. This is how a handler starts async work whose result
+    /// should be handled like any other message.
+    ///
+    /// As with [`ActorRef::pipe`], the task is handed to `spawn_fn` to be spawned on the
+    /// caller's runtime. If the actor exits before `fut` completes, `fut` is dropped and
+    /// nothing is sent.
+    pub fn spawn_and_send<T: Send + 'static>(
+        &self,
+        fut: impl Future<Output = T> + Send + 'static,
+        map: impl FnOnce(T) -> A::Message + Send + 'static,
+        spawn_fn: impl FnOnce(PipeTask),
+    ) {
+        let this = self.clone();
+        spawn_fn(Box::pin(async move {
+            if let Either::Left(out) = biased(fut, this.shared.wait_exited()).await {
+                let _ = this.send(map(out)).await;
+            }
+        }));
+    }
+}
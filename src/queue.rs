@@ -0,0 +1,642 @@
+//! A mailbox backend built on a mutex-protected queue, used when messages need to be delivered
+//! in an order other than the FIFO order provided by `async_channel`.
+
+use std::{
+    any::Any,
+    cmp::{Ordering as CmpOrdering, Reverse},
+    collections::{hash_map::Entry, BinaryHeap, HashMap, VecDeque},
+    hash::Hash,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard, Weak,
+    },
+};
+
+use async_channel::{RecvError, SendError, TryRecvError, TrySendError};
+use event_listener::Event;
+
+use crate::mailbox::Packet;
+
+/// The order in which a [`Queue`] hands out the messages it holds.
+pub(crate) trait Order<M>: Send {
+    fn push(&mut self, packet: Packet<M>);
+    fn pop(&mut self) -> Option<Packet<M>>;
+    fn len(&self) -> usize;
+    /// Visits every queued packet without removing it, in delivery order where the order does
+    /// not depend on future pops.
+    fn visit(&self, f: &mut dyn FnMut(&Packet<M>));
+    /// Returns `true` if pushing `packet` replaces a queued packet rather than adding one, so
+    /// that it fits even if the queue is full.
+    fn replaces(&self, packet: &Packet<M>) -> bool {
+        let _ = packet;
+        false
+    }
+}
+
+/// Last-in-first-out ordering: the most recently sent message is received first.
+pub(crate) struct Lifo<M>(VecDeque<Packet<M>>);
+
+impl<M> Lifo<M> {
+    pub(crate) fn new() -> Self {
+        Self(VecDeque::new())
+    }
+}
+
+impl<M: Send> Order<M> for Lifo<M> {
+    fn push(&mut self, packet: Packet<M>) {
+        self.0.push_back(packet);
+    }
+
+    fn pop(&mut self) -> Option<Packet<M>> {
+        self.0.pop_back()
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn visit(&self, f: &mut dyn FnMut(&Packet<M>)) {
+        self.0.iter().rev().for_each(f);
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// A scheduling tier for messages sent to an actor with a weighted mailbox, see
+/// [`Mailbox::weighted`](crate::Mailbox::weighted). Messages sent without an explicit tier are in
+/// `Tier(0)`.
+pub struct Tier(pub u8);
+
+struct Lane<M> {
+    tier: Tier,
+    weight: u32,
+    credit: u32,
+    queue: VecDeque<Packet<M>>,
+}
+
+/// Weighted fair ordering across tiers: each tier may deliver up to `weight` messages per round,
+/// in the order the tiers were configured, and a new round starts once every tier with queued
+/// messages has used up its credit. Messages within a tier are delivered in FIFO order.
+pub(crate) struct Weighted<M> {
+    lanes: Vec<Lane<M>>,
+    len: usize,
+}
+
+impl<M> Weighted<M> {
+    pub(crate) fn new(tiers: &[(Tier, u32)]) -> Self {
+        assert!(
+            !tiers.is_empty(),
+            "a weighted mailbox needs at least one tier"
+        );
+        let lanes = tiers
+            .iter()
+            .map(|&(tier, weight)| Lane {
+                tier,
+                weight,
+                credit: weight,
+                queue: VecDeque::new(),
+            })
+            .collect();
+        Self { lanes, len: 0 }
+    }
+
+    fn take(&mut self, idx: usize) -> Option<Packet<M>> {
+        let lane = &mut self.lanes[idx];
+        lane.credit = lane.credit.saturating_sub(1);
+        self.len -= 1;
+        lane.queue.pop_front()
+    }
+}
+
+impl<M: Send> Order<M> for Weighted<M> {
+    fn push(&mut self, packet: Packet<M>) {
+        let idx = self
+            .lanes
+            .iter()
+            .position(|lane| lane.tier == packet.tier)
+            .unwrap_or(self.lanes.len() - 1);
+        self.lanes[idx].queue.push_back(packet);
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<Packet<M>> {
+        if self.len == 0 {
+            return None;
+        }
+        let ready = |lane: &Lane<M>| lane.credit > 0 && !lane.queue.is_empty();
+        if let Some(idx) = self.lanes.iter().position(ready) {
+            return self.take(idx);
+        }
+        // Every tier with queued messages is out of credit, so start a new round.
+        for lane in &mut self.lanes {
+            lane.credit = lane.weight;
+        }
+        if let Some(idx) = self.lanes.iter().position(ready) {
+            return self.take(idx);
+        }
+        // Only tiers with a weight of zero have messages, which are served when nothing else is.
+        let idx = self.lanes.iter().position(|lane| !lane.queue.is_empty())?;
+        self.take(idx)
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn visit(&self, f: &mut dyn FnMut(&Packet<M>)) {
+        // Tier by tier, as the interleaving across tiers depends on the remaining credit.
+        self.lanes.iter().flat_map(|lane| &lane.queue).for_each(f);
+    }
+}
+
+/// A queued packet along with its priority, ordered so that the heap pops the highest priority
+/// first, and the earliest sent packet among those with equal priority.
+struct Ranked<M> {
+    priority: u8,
+    seq: u64,
+    packet: Packet<M>,
+}
+
+impl<M> Ranked<M> {
+    fn key(&self) -> (u8, Reverse<u64>) {
+        (self.priority, Reverse(self.seq))
+    }
+}
+
+impl<M> PartialEq for Ranked<M> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl<M> Eq for Ranked<M> {}
+
+impl<M> PartialOrd for Ranked<M> {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<M> Ord for Ranked<M> {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        self.key().cmp(&other.key())
+    }
+}
+
+/// Priority ordering: the message with the highest priority, as assigned by a user-provided
+/// function, is received first. Messages with equal priority are received in FIFO order.
+pub(crate) struct Prioritized<M> {
+    heap: BinaryHeap<Ranked<M>>,
+    /// Sequence number of the next pushed packet, which keeps equal priorities in FIFO order.
+    seq: u64,
+    priority: Box<dyn Fn(&M) -> u8 + Send>,
+}
+
+impl<M> Prioritized<M> {
+    pub(crate) fn new(priority: impl Fn(&M) -> u8 + Send + 'static) -> Self {
+        Self {
+            heap: BinaryHeap::new(),
+            seq: 0,
+            priority: Box::new(priority),
+        }
+    }
+}
+
+impl<M: Send> Order<M> for Prioritized<M> {
+    fn push(&mut self, packet: Packet<M>) {
+        let priority = (self.priority)(&packet.msg);
+        self.heap.push(Ranked {
+            priority,
+            seq: self.seq,
+            packet,
+        });
+        self.seq += 1;
+    }
+
+    fn pop(&mut self) -> Option<Packet<M>> {
+        self.heap.pop().map(|ranked| ranked.packet)
+    }
+
+    fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    fn visit(&self, f: &mut dyn FnMut(&Packet<M>)) {
+        let mut ranked: Vec<_> = self.heap.iter().collect();
+        ranked.sort_by(|a, b| b.cmp(a));
+        ranked.into_iter().for_each(|ranked| f(&ranked.packet));
+    }
+}
+
+/// The key a message was sent with by [`ActorRef::send_coalesced`](crate::ActorRef::send_coalesced),
+/// type-erased so that keys of any type can share a mailbox. Keys are looked up by their hash,
+/// but only match if they are of the same type and equal.
+pub(crate) struct CoalesceKey {
+    hash: u64,
+    key: Box<dyn Any + Send>,
+    eq: fn(&dyn Any, &dyn Any) -> bool,
+}
+
+impl CoalesceKey {
+    pub(crate) fn new<K: Hash + Eq + Send + 'static>(key: K) -> Self {
+        Self {
+            hash: crate::router::hash(&key),
+            key: Box::new(key),
+            eq: |a, b| {
+                a.downcast_ref::<K>()
+                    .is_some_and(|a| b.downcast_ref() == Some(a))
+            },
+        }
+    }
+
+    fn matches(&self, other: &Self) -> bool {
+        self.hash == other.hash && (self.eq)(&*self.key, &*other.key)
+    }
+}
+
+impl std::fmt::Debug for CoalesceKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CoalesceKey")
+            .field("hash", &self.hash)
+            .finish_non_exhaustive()
+    }
+}
+
+/// FIFO ordering where a message sent with a coalescing key replaces the queued message with the
+/// same key, if there is one, keeping its place in the queue.
+pub(crate) struct Coalesced<M> {
+    queue: VecDeque<Packet<M>>,
+    /// Positions of the queued packets with a key, counted from the first packet ever pushed, by
+    /// the hash of their key. Keys whose hashes collide share an entry.
+    keyed: HashMap<u64, Vec<u64>>,
+    /// Number of packets popped so far, i.e. the position of the front of the queue.
+    popped: u64,
+}
+
+impl<M> Coalesced<M> {
+    pub(crate) fn new() -> Self {
+        Self {
+            queue: VecDeque::new(),
+            keyed: HashMap::new(),
+            popped: 0,
+        }
+    }
+
+    /// Returns the position of the queued packet with the same key as `packet`, if any.
+    fn position(&self, packet: &Packet<M>) -> Option<u64> {
+        let key = packet.coalesce.as_ref()?;
+        self.keyed.get(&key.hash)?.iter().copied().find(|&pos| {
+            self.queue[(pos - self.popped) as usize]
+                .coalesce
+                .as_ref()
+                .is_some_and(|queued| queued.matches(key))
+        })
+    }
+}
+
+impl<M: Send> Order<M> for Coalesced<M> {
+    fn push(&mut self, packet: Packet<M>) {
+        match self.position(&packet) {
+            // The packet replaced is dropped, so its acknowledgement fails.
+            Some(pos) => self.queue[(pos - self.popped) as usize] = packet,
+            None => {
+                if let Some(key) = &packet.coalesce {
+                    let pos = self.popped + self.queue.len() as u64;
+                    self.keyed.entry(key.hash).or_default().push(pos);
+                }
+                self.queue.push_back(packet);
+            }
+        }
+    }
+
+    fn pop(&mut self) -> Option<Packet<M>> {
+        let packet = self.queue.pop_front()?;
+        if let Some(key) = &packet.coalesce {
+            if let Entry::Occupied(mut entry) = self.keyed.entry(key.hash) {
+                let popped = self.popped;
+                entry.get_mut().retain(|&pos| pos != popped);
+                if entry.get().is_empty() {
+                    entry.remove();
+                }
+            }
+        }
+        self.popped += 1;
+        Some(packet)
+    }
+
+    fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    fn visit(&self, f: &mut dyn FnMut(&Packet<M>)) {
+        self.queue.iter().for_each(f);
+    }
+
+    fn replaces(&self, packet: &Packet<M>) -> bool {
+        self.position(packet).is_some()
+    }
+}
+
+struct State<M> {
+    order: Box<dyn Order<M>>,
+    closed: bool,
+    /// Slots held for messages to be sent with `Sender::send_reserved`.
+    reserved: usize,
+}
+
+struct Queue<M> {
+    state: Mutex<State<M>>,
+    capacity: Option<usize>,
+    sender_count: AtomicUsize,
+    /// Number of queued packets, kept in step with the state so that it can be read without
+    /// taking the lock.
+    len: AtomicUsize,
+    /// Set along with `State::closed`, so that it can be read without taking the lock.
+    closed: AtomicBool,
+    /// Notified when a message is pushed, or the queue is closed.
+    recv_ops: Event,
+    /// Notified when a message is popped, or the queue is closed.
+    send_ops: Event,
+}
+
+impl<M> Queue<M> {
+    fn lock(&self) -> MutexGuard<'_, State<M>> {
+        self.state.lock().unwrap()
+    }
+
+    fn close(&self) -> bool {
+        let mut state = self.lock();
+        if state.closed {
+            return false;
+        }
+        state.closed = true;
+        self.closed.store(true, Ordering::Release);
+        drop(state);
+        self.recv_ops.notify(usize::MAX);
+        self.send_ops.notify(usize::MAX);
+        true
+    }
+
+    fn try_send(&self, packet: Packet<M>) -> Result<(), TrySendError<Packet<M>>> {
+        let mut state = self.lock();
+        if state.closed {
+            return Err(TrySendError::Closed(packet));
+        }
+        if self
+            .capacity
+            .is_some_and(|cap| state.order.len() + state.reserved >= cap)
+            && !state.order.replaces(&packet)
+        {
+            return Err(TrySendError::Full(packet));
+        }
+        state.order.push(packet);
+        self.len.store(state.order.len(), Ordering::Release);
+        drop(state);
+        self.recv_ops.notify(1);
+        Ok(())
+    }
+
+    fn try_recv(&self) -> Result<Packet<M>, TryRecvError> {
+        let mut state = self.lock();
+        match state.order.pop() {
+            Some(packet) => {
+                self.len.store(state.order.len(), Ordering::Release);
+                drop(state);
+                self.send_ops.notify(1);
+                Ok(packet)
+            }
+            None if state.closed || self.sender_count.load(Ordering::Acquire) == 0 => {
+                Err(TryRecvError::Closed)
+            }
+            None => Err(TryRecvError::Empty),
+        }
+    }
+}
+
+/// Creates a queue-backed channel with the given capacity and ordering.
+pub(crate) fn queue<M>(
+    capacity: Option<usize>,
+    order: impl Order<M> + 'static,
+) -> (Sender<M>, Receiver<M>) {
+    let queue = Arc::new(Queue {
+        state: Mutex::new(State {
+            order: Box::new(order),
+            closed: false,
+            reserved: 0,
+        }),
+        capacity,
+        sender_count: AtomicUsize::new(1),
+        len: AtomicUsize::new(0),
+        closed: AtomicBool::new(false),
+        recv_ops: Event::new(),
+        send_ops: Event::new(),
+    });
+    (
+        Sender {
+            queue: queue.clone(),
+        },
+        Receiver { queue },
+    )
+}
+
+pub(crate) struct Sender<M> {
+    queue: Arc<Queue<M>>,
+}
+
+impl<M> Sender<M> {
+    pub(crate) fn try_send(&self, packet: Packet<M>) -> Result<(), TrySendError<Packet<M>>> {
+        self.queue.try_send(packet)
+    }
+
+    pub(crate) async fn send(&self, mut packet: Packet<M>) -> Result<(), SendError<Packet<M>>> {
+        loop {
+            match self.queue.try_send(packet) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Closed(p)) => return Err(SendError(p)),
+                Err(TrySendError::Full(p)) => packet = p,
+            }
+            let listener = self.queue.send_ops.listen();
+            match self.queue.try_send(packet) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Closed(p)) => return Err(SendError(p)),
+                Err(TrySendError::Full(p)) => packet = p,
+            }
+            listener.await;
+        }
+    }
+
+    pub(crate) fn try_reserve(&self) -> Result<(), TrySendError<()>> {
+        let mut state = self.queue.lock();
+        if state.closed {
+            return Err(TrySendError::Closed(()));
+        }
+        if let Some(cap) = self.queue.capacity {
+            if state.order.len() + state.reserved >= cap {
+                return Err(TrySendError::Full(()));
+            }
+            state.reserved += 1;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn has_room(&self) -> Result<bool, ()> {
+        let state = self.queue.lock();
+        if state.closed {
+            return Err(());
+        }
+        Ok(self
+            .queue
+            .capacity
+            .is_none_or(|cap| state.order.len() + state.reserved < cap))
+    }
+
+    pub(crate) fn send_reserved(&self, packet: Packet<M>) -> Result<(), Packet<M>> {
+        let mut state = self.queue.lock();
+        if self.queue.capacity.is_some() {
+            state.reserved -= 1;
+        }
+        if state.closed {
+            return Err(packet);
+        }
+        state.order.push(packet);
+        self.queue.len.store(state.order.len(), Ordering::Release);
+        drop(state);
+        self.queue.recv_ops.notify(1);
+        Ok(())
+    }
+
+    pub(crate) fn release(&self) {
+        if self.queue.capacity.is_some() {
+            self.queue.lock().reserved -= 1;
+            self.queue.send_ops.notify(1);
+        }
+    }
+
+    pub(crate) fn send_ops(&self) -> &Event {
+        &self.queue.send_ops
+    }
+
+    pub(crate) fn close(&self) -> bool {
+        self.queue.close()
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.queue.closed.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.queue.len.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn capacity(&self) -> Option<usize> {
+        self.queue.capacity
+    }
+
+    pub(crate) fn snapshot(&self) -> Vec<M>
+    where
+        M: Clone,
+    {
+        let state = self.queue.lock();
+        let mut msgs = Vec::with_capacity(state.order.len());
+        state
+            .order
+            .visit(&mut |packet| msgs.push(packet.msg.clone()));
+        msgs
+    }
+
+    pub(crate) fn downgrade(&self) -> WeakSender<M> {
+        WeakSender {
+            queue: Arc::downgrade(&self.queue),
+        }
+    }
+}
+
+impl<M> Clone for Sender<M> {
+    fn clone(&self) -> Self {
+        self.queue.sender_count.fetch_add(1, Ordering::Relaxed);
+        Self {
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+impl<M> Drop for Sender<M> {
+    fn drop(&mut self) {
+        if self.queue.sender_count.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.queue.recv_ops.notify(usize::MAX);
+        }
+    }
+}
+
+pub(crate) struct WeakSender<M> {
+    queue: Weak<Queue<M>>,
+}
+
+impl<M> WeakSender<M> {
+    pub(crate) fn upgrade(&self) -> Option<Sender<M>> {
+        let queue = self.queue.upgrade()?;
+        let mut count = queue.sender_count.load(Ordering::Relaxed);
+        loop {
+            if count == 0 {
+                return None;
+            }
+            match queue.sender_count.compare_exchange_weak(
+                count,
+                count + 1,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Some(Sender { queue }),
+                Err(current) => count = current,
+            }
+        }
+    }
+}
+
+impl<M> Clone for WeakSender<M> {
+    fn clone(&self) -> Self {
+        Self {
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+pub(crate) struct Receiver<M> {
+    queue: Arc<Queue<M>>,
+}
+
+impl<M> Receiver<M> {
+    pub(crate) fn try_recv(&self) -> Result<Packet<M>, TryRecvError> {
+        self.queue.try_recv()
+    }
+
+    pub(crate) async fn recv(&self) -> Result<Packet<M>, RecvError> {
+        loop {
+            match self.queue.try_recv() {
+                Ok(packet) => return Ok(packet),
+                Err(TryRecvError::Closed) => return Err(RecvError),
+                Err(TryRecvError::Empty) => {}
+            }
+            let listener = self.queue.recv_ops.listen();
+            match self.queue.try_recv() {
+                Ok(packet) => return Ok(packet),
+                Err(TryRecvError::Closed) => return Err(RecvError),
+                Err(TryRecvError::Empty) => {}
+            }
+            listener.await;
+        }
+    }
+
+    pub(crate) fn close(&self) -> bool {
+        self.queue.close()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.queue.len.load(Ordering::Acquire)
+    }
+}
+
+impl<M> Drop for Receiver<M> {
+    fn drop(&mut self) {
+        self.queue.close();
+    }
+}
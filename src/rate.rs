@@ -0,0 +1,84 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+type SleepFn = Box<dyn Fn(Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync>;
+
+/// A token bucket limiting how many messages per second the run loop hands to the actor, set by
+/// [`Mailbox::set_rate_limit`](crate::Mailbox::set_rate_limit).
+///
+/// The bucket holds up to `rate` tokens and refills continuously. It is tracked as the time at
+/// which it will be full again, rather than a token count updated by a timer, so that rounding
+/// never accumulates and the limiter doesn't drift under sustained load.
+pub(crate) struct RateLimit {
+    rate: u32,
+    /// The time it takes to refill one token.
+    interval: Duration,
+    /// The time at which the bucket is full again. In the past if it is full.
+    full_at: Mutex<Instant>,
+    sleep_fn: SleepFn,
+}
+
+impl RateLimit {
+    pub(crate) fn new<S>(
+        rate: u32,
+        sleep_fn: impl Fn(Duration) -> S + Send + Sync + 'static,
+    ) -> Self
+    where
+        S: Future + Send + 'static,
+    {
+        assert!(rate > 0, "`rate` must be at least 1");
+        Self {
+            rate,
+            interval: Duration::from_secs(1) / rate,
+            full_at: Mutex::new(Instant::now()),
+            sleep_fn: Box::new(move |delay| {
+                let sleep = sleep_fn(delay);
+                Box::pin(async move {
+                    sleep.await;
+                })
+            }),
+        }
+    }
+
+    /// Returns the number of whole tokens in the bucket, i.e. how many messages can be handled
+    /// right away.
+    pub(crate) fn tokens(&self) -> u32 {
+        let missing = self
+            .full_at
+            .lock()
+            .unwrap()
+            .saturating_duration_since(Instant::now());
+        let missing = missing.as_nanos().div_ceil(self.interval.as_nanos().max(1));
+        self.rate.saturating_sub(missing as u32)
+    }
+
+    /// Takes a token, sleeping until one is available.
+    pub(crate) async fn acquire(&self) {
+        let wait = {
+            let mut full_at = self.full_at.lock().unwrap();
+            let now = Instant::now();
+            // Taking a token pushes the time at which the bucket is full back by one interval.
+            let next = (*full_at).max(now) + self.interval;
+            *full_at = next;
+            // The bucket only holds `rate` tokens, so a token is available as long as it is not
+            // more than `rate` intervals away from being full.
+            next.saturating_duration_since(now + self.interval * self.rate)
+        };
+        if !wait.is_zero() {
+            (self.sleep_fn)(wait).await;
+        }
+    }
+}
+
+impl std::fmt::Debug for RateLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RateLimit")
+            .field("rate", &self.rate)
+            .field("tokens", &self.tokens())
+            .finish_non_exhaustive()
+    }
+}
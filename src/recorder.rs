@@ -0,0 +1,92 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{Actor, Mailbox, RunError};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// An entry in the log of a [`Recorder`], in the order the actor dispatched it.
+pub enum Recorded<M> {
+    /// A message received from the actor's mailbox, including messages handled while draining
+    /// the mailbox on stop.
+    Message(M),
+    /// The actor stopped, with the stop message passed to [`Actor::on_stop`].
+    Stop(Option<M>),
+}
+
+/// Records the received messages in the mailbox's log, see [`Mailbox::record`].
+pub(crate) type RecordFn<M> = Box<dyn Fn(Recorded<&M>) + Send + Sync>;
+
+/// A log of every message an actor received, created with [`Mailbox::start_recording`].
+/// Pass the log to [`replay`] to drive the same messages through a fresh actor.
+pub struct Recorder<A: Actor> {
+    log: Arc<Mutex<Vec<Recorded<A::Message>>>>,
+}
+
+impl<A: Actor> Recorder<A>
+where
+    A::Message: Clone,
+{
+    pub(crate) fn new() -> (Self, RecordFn<A::Message>) {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let writer = log.clone();
+        let record = Box::new(move |entry: Recorded<&A::Message>| {
+            let entry = match entry {
+                Recorded::Message(msg) => Recorded::Message(msg.clone()),
+                Recorded::Stop(stop) => Recorded::Stop(stop.cloned()),
+            };
+            writer.lock().unwrap().push(entry);
+        });
+        (Self { log }, record)
+    }
+
+    /// Returns a copy of the log recorded so far.
+    pub fn entries(&self) -> Vec<Recorded<A::Message>> {
+        self.log.lock().unwrap().clone()
+    }
+}
+
+impl<A: Actor> Clone for Recorder<A> {
+    fn clone(&self) -> Self {
+        Self {
+            log: self.log.clone(),
+        }
+    }
+}
+
+/// Drives the messages of a log recorded with a [`Recorder`] through `actor`, such as a fresh
+/// instance of the recorded actor, to reproduce its behavior. Resolves with the actor once it
+/// has stopped, or with the error it failed with, like its [`ActorRun`](crate::ActorRun).
+///
+/// Every message is queued before the actor starts, and the actor is then stopped with the
+/// recorded stop message, so the messages are handled in the recorded order and the same stop
+/// message reaches [`Actor::on_stop`]. A log without a stop entry is replayed as if every
+/// reference to the actor was dropped. Follow-ups from [`Actor::on_msg_emit`] are not
+/// recorded, as replaying the messages produces them again.
+pub async fn replay<A: Actor>(
+    actor: A,
+    log: impl IntoIterator<Item = Recorded<A::Message>>,
+) -> Result<A, RunError<A>> {
+    let (actor_ref, run) = actor.into_future(None);
+    for entry in log {
+        match entry {
+            Recorded::Message(msg) => {
+                // The mailbox is unbounded, so this only fails once a stop entry was sent.
+                let _ = actor_ref.try_send(msg);
+            }
+            Recorded::Stop(Some(stop)) => {
+                let _ = actor_ref.stop(stop);
+            }
+            Recorded::Stop(None) => break,
+        }
+    }
+    drop(actor_ref);
+    run.await
+}
+
+impl<A: Actor> Mailbox<A> {
+    /// Records `entry` if recording was started with [`Mailbox::start_recording`].
+    pub(crate) fn record(&self, entry: Recorded<&A::Message>) {
+        if let Some(record) = &self.recorder {
+            record(entry);
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Waker},
+};
+
+use crate::{Actor, ActorRun, RunError};
+
+#[derive(Debug, Default)]
+struct ParkState {
+    requested: AtomicBool,
+    /// Waker of the task running the [`Relocatable`], woken when parking is requested.
+    waker: Mutex<Option<Waker>>,
+}
+
+impl<A: Actor + Send + 'static> ActorRun<A> {
+    /// Makes the actor movable to another executor or runtime while it runs. The returned
+    /// [`Relocatable`] is spawned in place of the [`ActorRun`], and the [`RelocateHandle`]
+    /// asks it to park, see [`RelocateHandle::park`].
+    pub fn into_relocatable(self) -> (Relocatable<A>, RelocateHandle) {
+        let park = Arc::new(ParkState::default());
+        let relocatable = Relocatable {
+            run: Some(self),
+            park: park.clone(),
+        };
+        (relocatable, RelocateHandle(park))
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Asks a [`Relocatable`] actor to park, see [`ActorRun::into_relocatable`].
+pub struct RelocateHandle(Arc<ParkState>);
+
+impl RelocateHandle {
+    /// Asks the actor to park at the next message boundary: once no handler is running, the
+    /// [`Relocatable`] resolves to [`Relocated::Parked`] instead of polling the actor further.
+    /// An actor that finishes first resolves to [`Relocated::Finished`] as usual.
+    ///
+    /// The handle stays usable after the actor is resumed, to move it again.
+    pub fn park(&self) {
+        self.0.requested.store(true, Ordering::Release);
+        if let Some(waker) = self.0.waker.lock().unwrap().take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Runs an actor like [`ActorRun`], but can be parked to move the actor elsewhere, see
+/// [`ActorRun::into_relocatable`].
+///
+/// Parking suspends the actor's run loop where it waits between messages, and hands it over as
+/// a [`Parked`] value. Nothing is torn down: the mailbox, with the messages still queued and
+/// the ones sent while parked, stays attached to the actor, so every [`ActorRef`] stays valid,
+/// and a request sent with [`ActorRef::ask`] is still answered once the actor resumes, even if
+/// it was sent before the move. As no handler is running when the actor parks, timers that it
+/// started, such as a handler timeout, don't tie it to the original runtime.
+///
+/// Parking relies on the run loop telling when a handler is running, which the default
+/// [`Actor::run_with`] does. Custom run loops may be parked at any point where they wait.
+///
+/// [`ActorRef`]: crate::ActorRef
+/// [`ActorRef::ask`]: crate::ActorRef::ask
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Relocatable<A: Actor> {
+    /// `None` once the actor finished or was parked.
+    run: Option<ActorRun<A>>,
+    park: Arc<ParkState>,
+}
+
+/// Returns `true` if parking was requested and no handler is running, and clears the request
+/// if so.
+fn should_park<A: Actor>(park: &ParkState, run: &ActorRun<A>) -> bool {
+    !run.shared.is_in_handler()
+        && park
+            .requested
+            .compare_exchange(true, false, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+}
+
+// `ActorRun` is boxed, and nothing else is polled in place.
+impl<A: Actor> Unpin for Relocatable<A> {}
+
+impl<A: Actor + Send + 'static> Future for Relocatable<A> {
+    type Output = Relocated<A>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        // Registered before checking for a request, so that a later request wakes the task.
+        *this.park.waker.lock().unwrap() = Some(cx.waker().clone());
+        let run = this
+            .run
+            .as_mut()
+            .expect("`Relocatable` polled after completion");
+        if !should_park(&this.park, run) {
+            match Pin::new(&mut *run).poll(cx) {
+                Poll::Ready(res) => {
+                    this.run = None;
+                    return Poll::Ready(Relocated::Finished(res));
+                }
+                // A request made while a handler was running is handled once the run loop
+                // waits between messages again.
+                Poll::Pending if !should_park(&this.park, run) => return Poll::Pending,
+                Poll::Pending => {}
+            }
+        }
+        let run = this.run.take().unwrap();
+        Poll::Ready(Relocated::Parked(Parked {
+            run,
+            park: this.park.clone(),
+        }))
+    }
+}
+
+impl<A: Actor> std::fmt::Debug for Relocatable<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Relocatable")
+            .field("finished", &self.run.is_none())
+            .finish_non_exhaustive()
+    }
+}
+
+/// The outcome of a [`Relocatable`].
+pub enum Relocated<A: Actor> {
+    /// The actor finished, with the same result as its [`ActorRun`].
+    Finished(Result<A, RunError<A>>),
+    /// The actor parked as requested with [`RelocateHandle::park`].
+    Parked(Parked<A>),
+}
+
+impl<A: Actor> std::fmt::Debug for Relocated<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Finished(res) => f
+                .debug_tuple("Finished")
+                .field(&res.as_ref().map(|_| ()).map_err(|_| ()))
+                .finish(),
+            Self::Parked(parked) => f.debug_tuple("Parked").field(parked).finish(),
+        }
+    }
+}
+
+/// A suspended actor, handed over by a [`Relocatable`] that was parked. Messages can still be
+/// sent to it, and are handled once it is resumed.
+pub struct Parked<A: Actor> {
+    run: ActorRun<A>,
+    park: Arc<ParkState>,
+}
+
+impl<A: Actor + Send + 'static> Parked<A> {
+    /// Resumes the actor with `spawn`, which is given a [`Relocatable`] to run on the executor
+    /// the actor moves to, e.g. `tokio::spawn` on another runtime. The [`RelocateHandle`] of
+    /// the actor can park it again.
+    pub fn resume<T>(self, spawn: impl FnOnce(Relocatable<A>) -> T) -> T {
+        spawn(Relocatable {
+            run: Some(self.run),
+            park: self.park,
+        })
+    }
+
+    /// Returns the [`ActorRun`], to keep running the actor without being able to park it.
+    pub fn into_run(self) -> ActorRun<A> {
+        self.run
+    }
+}
+
+impl<A: Actor> std::fmt::Debug for Parked<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Parked").finish_non_exhaustive()
+    }
+}
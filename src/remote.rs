@@ -0,0 +1,380 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    io,
+    marker::PhantomData,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::Poll,
+};
+
+use async_oneshot_channel::Sender as OneshotSender;
+use either::Either;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{select::biased, Actor, ActorRef, AskError};
+
+/// Frames larger than this are rejected as corrupt rather than allocated.
+const MAX_FRAME: usize = 16 << 20;
+
+/// A message sent without expecting a reply.
+const TELL: u8 = 0;
+/// A request, answered with a `REPLY` or `DROPPED` frame with the same correlation id.
+const ASK: u8 = 1;
+const REPLY: u8 = 2;
+/// The actor dropped the reply sender of a request without answering it.
+const DROPPED: u8 = 3;
+
+/// A message that can be sent to an actor in another process, see [`RemoteActorRef`].
+///
+/// The crate doesn't pick a serialization format: implement this with whichever one the two
+/// sides agree on, e.g. by calling into `serde` with `bincode` or `postcard`. Each message is
+/// sent in its own length-prefixed frame, so `decode` is always given exactly the bytes that
+/// `encode` wrote.
+pub trait RemoteMessage: Sized + Send + 'static {
+    /// Appends the encoded message to `buf`.
+    fn encode(&self, buf: &mut Vec<u8>);
+
+    /// Decodes a message encoded with [`RemoteMessage::encode`].
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Returned by [`RemoteMessage::decode`] for bytes that are not a valid message. The connection
+/// fails with [`io::ErrorKind::InvalidData`] when it receives such a message.
+pub struct DecodeError;
+
+/// Writes a frame: its length, its kind, its correlation id, and the encoded message.
+fn frame(kind: u8, id: u64, msg: impl FnOnce(&mut Vec<u8>)) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(64);
+    buf.extend_from_slice(&[0; 4]);
+    buf.push(kind);
+    buf.extend_from_slice(&id.to_be_bytes());
+    msg(&mut buf);
+    let len = (buf.len() - 4) as u32;
+    buf[..4].copy_from_slice(&len.to_be_bytes());
+    buf
+}
+
+/// Reads the next frame, or returns [`None`] if the stream ended between frames.
+async fn read_frame(
+    reader: &mut (impl AsyncRead + Unpin),
+) -> io::Result<Option<(u8, u64, Vec<u8>)>> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len).await {
+        Ok(_) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if !(9..=MAX_FRAME).contains(&len) {
+        return Err(invalid_data("invalid frame length"));
+    }
+    let mut buf = vec![0; len];
+    reader.read_exact(&mut buf).await?;
+    let id = u64::from_be_bytes(buf[1..9].try_into().unwrap());
+    Ok(Some((buf[0], id, buf.split_off(9))))
+}
+
+fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn decode<M: RemoteMessage>(bytes: &[u8]) -> io::Result<M> {
+    M::decode(bytes).map_err(|DecodeError| invalid_data("message failed to decode"))
+}
+
+/// Requests waiting for their reply, by correlation id.
+type Pending = Arc<Mutex<HashMap<u64, OneshotSender<Vec<u8>>>>>;
+
+/// Removes a request from the table if the caller stops waiting for its reply.
+struct PendingGuard<'a> {
+    pending: &'a Pending,
+    id: u64,
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        self.pending.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// A reference to an actor in another process, served there by a [`RemoteServer`].
+///
+/// Messages of type `M` are encoded into length-prefixed frames and written to a transport
+/// provided by the caller, e.g. a TCP or Unix socket. The reference only mimics
+/// [`ActorRef::send`] and [`ActorRef::ask`]: stopping the actor and the other operations of a
+/// local reference are left to the remote side.
+///
+/// The frames are written and replies read by the future returned along with the reference
+/// from [`RemoteActorRef::connect`], which must be spawned or polled for messages to go out.
+pub struct RemoteActorRef<M> {
+    frames: async_channel::Sender<Vec<u8>>,
+    pending: Pending,
+    next_id: Arc<AtomicU64>,
+    _msg: PhantomData<fn(M)>,
+}
+
+impl<M: RemoteMessage> RemoteActorRef<M> {
+    /// Creates a reference that sends over the connection made of `reader` and `writer`,
+    /// along with the future that drives the connection.
+    ///
+    /// The future resolves once every clone of the reference has been dropped, or with an
+    /// error if the transport fails or the other side sends something that is not a frame.
+    /// Requests still waiting for a reply then fail with [`AskError::Dropped`].
+    pub fn connect(
+        reader: impl AsyncRead + Unpin + Send + 'static,
+        writer: impl AsyncWrite + Unpin + Send + 'static,
+    ) -> (Self, impl Future<Output = io::Result<()>> + Send + 'static) {
+        let (frames, outgoing) = async_channel::unbounded();
+        let pending = Pending::default();
+        let remote = Self {
+            frames,
+            pending: pending.clone(),
+            next_id: Arc::new(AtomicU64::new(0)),
+            _msg: PhantomData,
+        };
+        let connection = async move {
+            let res = client(reader, writer, &outgoing, &pending).await;
+            outgoing.close();
+            pending.lock().unwrap().clear();
+            res
+        };
+        (remote, connection)
+    }
+
+    /// Sends a message to the remote actor. Fails with the message in [`Err`] if the
+    /// connection has ended. Resolving only means that the message was queued for the
+    /// connection, not that the remote actor received it.
+    pub async fn send(&self, msg: M) -> Result<(), M> {
+        let frame = frame(TELL, 0, |buf| msg.encode(buf));
+        self.frames.send(frame).await.map_err(|_| msg)
+    }
+
+    /// Sends a request to the remote actor and waits for its reply, see
+    /// [`RemoteServer::on_ask`]. Fails with [`AskError::Closed`] if the connection has
+    /// ended, or with [`AskError::Dropped`] if the remote actor dropped the request without
+    /// answering, or the connection ended before the reply arrived.
+    pub async fn ask<Q: RemoteMessage, R: RemoteMessage>(&self, request: Q) -> Result<R, AskError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (reply_sender, reply_receiver) = async_oneshot_channel::oneshot();
+        self.pending.lock().unwrap().insert(id, reply_sender);
+        let _guard = PendingGuard {
+            pending: &self.pending,
+            id,
+        };
+        let frame = frame(ASK, id, |buf| request.encode(buf));
+        self.frames
+            .send(frame)
+            .await
+            .map_err(|_| AskError::Closed)?;
+        let reply = reply_receiver.recv().await.ok_or(AskError::Dropped)?;
+        R::decode(&reply).map_err(|DecodeError| AskError::Dropped)
+    }
+
+    /// Returns `true` once the connection has ended, so that messages can no longer be sent.
+    pub fn is_closed(&self) -> bool {
+        self.frames.is_closed()
+    }
+}
+
+impl<M> Clone for RemoteActorRef<M> {
+    fn clone(&self) -> Self {
+        Self {
+            frames: self.frames.clone(),
+            pending: self.pending.clone(),
+            next_id: self.next_id.clone(),
+            _msg: PhantomData,
+        }
+    }
+}
+
+impl<M> std::fmt::Debug for RemoteActorRef<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RemoteActorRef")
+            .field("closed", &self.frames.is_closed())
+            .field("pending", &self.pending.lock().unwrap().len())
+            .finish()
+    }
+}
+
+/// Writes the frames queued by the references, and hands replies to the requests waiting for
+/// them, until the references are gone.
+async fn client(
+    mut reader: impl AsyncRead + Unpin,
+    mut writer: impl AsyncWrite + Unpin,
+    outgoing: &async_channel::Receiver<Vec<u8>>,
+    pending: &Pending,
+) -> io::Result<()> {
+    let write = async {
+        while let Ok(frame) = outgoing.recv().await {
+            writer.write_all(&frame).await?;
+            writer.flush().await?;
+        }
+        writer.shutdown().await
+    };
+    let read = async {
+        while let Some((kind, id, reply)) = read_frame(&mut reader).await? {
+            let sender = pending.lock().unwrap().remove(&id);
+            match (kind, sender) {
+                (REPLY, Some(sender)) => {
+                    let _ = sender.send(reply);
+                }
+                // The caller stopped waiting, or the actor dropped the request.
+                (REPLY | DROPPED, _) => {}
+                _ => return Err(invalid_data("unexpected frame from server")),
+            }
+        }
+        // The server hung up, so no more replies are coming.
+        Err(io::ErrorKind::UnexpectedEof.into())
+    };
+    match biased(write, read).await {
+        Either::Left(res) => res,
+        Either::Right(res) => res,
+    }
+}
+
+type ReplyFuture = Pin<Box<dyn Future<Output = Option<Vec<u8>>> + Send>>;
+type AskFn<M> = Box<dyn Fn(&[u8]) -> io::Result<(M, ReplyFuture)> + Send + Sync>;
+
+/// Serves a local actor to [`RemoteActorRef<M>`]s in other processes, decoding the messages
+/// they send and converting them into the actor's messages.
+///
+/// `M` is the type of the messages sent over the wire, which is often a subset of the actor's
+/// messages, as messages carrying reply senders cannot be encoded.
+pub struct RemoteServer<A: Actor, M> {
+    actor_ref: ActorRef<A>,
+    ask: Option<AskFn<A::Message>>,
+    _msg: PhantomData<fn(M)>,
+}
+
+impl<A: Actor, M: RemoteMessage + Into<A::Message>> RemoteServer<A, M> {
+    /// Creates a server for the actor, which receives the messages sent with
+    /// [`RemoteActorRef::send`]. Requests are rejected until [`RemoteServer::on_ask`] is set.
+    pub fn new(actor_ref: ActorRef<A>) -> Self {
+        Self {
+            actor_ref,
+            ask: None,
+            _msg: PhantomData,
+        }
+    }
+
+    /// Accepts requests made with [`RemoteActorRef::ask`]: `make_msg` is given each decoded
+    /// request and the sending half of a oneshot channel, as with [`ActorRef::ask`], and should
+    /// wrap them into a message that the actor answers by sending on the channel. The answer
+    /// is encoded and routed back to the caller by correlation id.
+    pub fn on_ask<Q: RemoteMessage, R: RemoteMessage>(
+        mut self,
+        make_msg: impl Fn(Q, OneshotSender<R>) -> A::Message + Send + Sync + 'static,
+    ) -> Self {
+        self.ask = Some(Box::new(move |bytes| {
+            let (reply_sender, reply_receiver) = async_oneshot_channel::oneshot();
+            let msg = make_msg(decode(bytes)?, reply_sender);
+            let reply: ReplyFuture = Box::pin(async move {
+                let reply = reply_receiver.recv().await?;
+                let mut buf = Vec::new();
+                reply.encode(&mut buf);
+                Some(buf)
+            });
+            Ok((msg, reply))
+        }));
+        self
+    }
+
+    /// Serves one connection, made of `reader` and `writer`, until the other side hangs up and
+    /// every request received so far has been answered. Also resolves once the actor's mailbox
+    /// is closed, and fails if the transport fails or a message does not decode.
+    pub async fn serve(
+        &self,
+        mut reader: impl AsyncRead + Unpin,
+        mut writer: impl AsyncWrite + Unpin,
+    ) -> io::Result<()> {
+        let (answers, answered) = async_channel::unbounded::<(u64, ReplyFuture)>();
+        let read = async move {
+            while let Some((kind, id, bytes)) = read_frame(&mut reader).await? {
+                let msg = match (kind, &self.ask) {
+                    (TELL, _) => decode::<M>(&bytes)?.into(),
+                    (ASK, Some(ask)) => {
+                        let (msg, reply) = ask(&bytes)?;
+                        let _ = answers.send((id, reply)).await;
+                        msg
+                    }
+                    (ASK, None) => return Err(invalid_data("requests are not accepted")),
+                    _ => return Err(invalid_data("unexpected frame from client")),
+                };
+                if self.actor_ref.send(msg).await.is_err() {
+                    break;
+                }
+            }
+            Ok(())
+        };
+        let write = async {
+            let mut running = Vec::new();
+            let mut closed = false;
+            loop {
+                let (id, reply) = if closed {
+                    if running.is_empty() {
+                        break;
+                    }
+                    next_answer(&mut running).await
+                } else {
+                    let event = biased(next_answer(&mut running), answered.recv()).await;
+                    match event {
+                        Either::Left(done) => done,
+                        Either::Right(Ok(ask)) => {
+                            running.push(ask);
+                            continue;
+                        }
+                        // No more requests, but some may still be waiting for their answer.
+                        Either::Right(Err(_)) => {
+                            closed = true;
+                            continue;
+                        }
+                    }
+                };
+                let frame = match reply {
+                    Some(reply) => frame(REPLY, id, |buf| buf.extend_from_slice(&reply)),
+                    None => frame(DROPPED, id, |_| {}),
+                };
+                writer.write_all(&frame).await?;
+                writer.flush().await?;
+            }
+            writer.shutdown().await
+        };
+
+        let mut read = std::pin::pin!(read);
+        let mut write = std::pin::pin!(write);
+        match biased(read.as_mut(), write.as_mut()).await {
+            // Dropping the requests sender lets the writer finish once all answers are out.
+            Either::Left(res) => {
+                res?;
+                write.await
+            }
+            Either::Right(res) => res,
+        }
+    }
+}
+
+/// Waits for the next request to be answered. Never completes if none is waiting.
+async fn next_answer(running: &mut Vec<(u64, ReplyFuture)>) -> (u64, Option<Vec<u8>>) {
+    std::future::poll_fn(|cx| {
+        for i in 0..running.len() {
+            if let Poll::Ready(reply) = running[i].1.as_mut().poll(cx) {
+                return Poll::Ready((running.swap_remove(i).0, reply));
+            }
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+impl<A: Actor, M> std::fmt::Debug for RemoteServer<A, M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RemoteServer")
+            .field("asks", &self.ask.is_some())
+            .finish_non_exhaustive()
+    }
+}
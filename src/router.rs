@@ -0,0 +1,217 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        RwLock,
+    },
+};
+
+use crate::{Actor, ActorRef};
+
+/// Number of points each worker gets on the hash ring of a [`Strategy::ConsistentHash`] router.
+/// More points spread keys more evenly across workers.
+const VIRTUAL_NODES: u64 = 64;
+
+type KeyFn<M> = Box<dyn Fn(&M) -> u64 + Send + Sync>;
+
+/// How a [`Router`] picks the worker for each message.
+pub enum Strategy<M> {
+    /// Send to each worker in turn.
+    RoundRobin,
+    /// Send to each worker in turn, as many messages in a row as its weight, for workers with
+    /// different capacities. The weights are given in the same order as the workers; workers
+    /// without a weight, including those added later, have a weight of 1, and workers with a
+    /// weight of 0 receive nothing.
+    WeightedRoundRobin(Vec<u32>),
+    /// Send every message with the same key to the same worker, for actors that keep state per
+    /// entity, such as per user. The function returns the hash of the message's key; see
+    /// [`Strategy::consistent_hash`] to hash any key type. Workers are placed on a hash ring, so
+    /// adding or removing a worker only moves the keys of the ring segments it takes or gives up.
+    ConsistentHash(KeyFn<M>),
+}
+
+impl<M> Strategy<M> {
+    /// Creates a [`Strategy::ConsistentHash`] that routes by the key `key_fn` returns for each
+    /// message.
+    pub fn consistent_hash<K: Hash>(key_fn: impl Fn(&M) -> K + Send + Sync + 'static) -> Self {
+        Self::ConsistentHash(Box::new(move |msg| hash(&key_fn(msg))))
+    }
+}
+
+impl<M> std::fmt::Debug for Strategy<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RoundRobin => f.write_str("RoundRobin"),
+            Self::WeightedRoundRobin(weights) => {
+                f.debug_tuple("WeightedRoundRobin").field(weights).finish()
+            }
+            Self::ConsistentHash(_) => f.debug_tuple("ConsistentHash").finish_non_exhaustive(),
+        }
+    }
+}
+
+pub(crate) fn hash(value: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+struct Worker<A: Actor> {
+    actor_ref: ActorRef<A>,
+    weight: u32,
+    /// Stable across additions and removals of other workers, as it places the worker on the
+    /// hash ring.
+    id: u64,
+}
+
+/// The workers of a [`Router`], replaced as a whole under its lock.
+struct Pool<A: Actor> {
+    workers: Vec<Worker<A>>,
+    next_id: u64,
+    /// Points on the hash ring, sorted, along with the id of the worker owning each point.
+    ring: Vec<(u64, u64)>,
+}
+
+/// Distributes messages across a pool of worker actors, according to a [`Strategy`].
+///
+/// The router is not an actor itself: it holds strong references to its workers, and sends to
+/// them directly from the caller's task. Workers can be added and removed while the router is
+/// in use, e.g. by an autoscaler, from any task that holds a reference to it.
+pub struct Router<A: Actor> {
+    pool: RwLock<Pool<A>>,
+    strategy: Strategy<A::Message>,
+    /// Count of messages routed so far, which drives the round-robin strategies.
+    next: AtomicUsize,
+}
+
+impl<A: Actor> Router<A> {
+    /// Creates a router that sends to `workers` with [`Strategy::RoundRobin`].
+    pub fn new(workers: Vec<ActorRef<A>>) -> Self {
+        Self::with_strategy(workers, Strategy::RoundRobin)
+    }
+
+    /// Creates a router that sends to `workers` with the given `strategy`.
+    pub fn with_strategy(workers: Vec<ActorRef<A>>, strategy: Strategy<A::Message>) -> Self {
+        let weights = match &strategy {
+            Strategy::WeightedRoundRobin(weights) => weights.clone(),
+            _ => Vec::new(),
+        };
+        let router = Self {
+            pool: RwLock::new(Pool {
+                workers: Vec::new(),
+                next_id: 0,
+                ring: Vec::new(),
+            }),
+            strategy,
+            next: AtomicUsize::new(0),
+        };
+        for (idx, actor_ref) in workers.into_iter().enumerate() {
+            router.push(actor_ref, weights.get(idx).copied().unwrap_or(1));
+        }
+        router
+    }
+
+    fn push(&self, actor_ref: ActorRef<A>, weight: u32) {
+        let mut pool = self.pool.write().unwrap();
+        let id = pool.next_id;
+        pool.next_id += 1;
+        if let Strategy::ConsistentHash(_) = self.strategy {
+            for node in 0..VIRTUAL_NODES {
+                let point = hash(&(id, node));
+                let at = pool.ring.partition_point(|&(p, _)| p < point);
+                pool.ring.insert(at, (point, id));
+            }
+        }
+        pool.workers.push(Worker {
+            actor_ref,
+            weight,
+            id,
+        });
+    }
+
+    /// Adds a worker to the pool, with a weight of 1 for [`Strategy::WeightedRoundRobin`].
+    pub fn add_worker(&self, actor_ref: ActorRef<A>) {
+        self.push(actor_ref, 1);
+    }
+
+    /// Removes every worker that refers to the same actor as `actor_ref`, and returns whether
+    /// any was removed. Messages routed after this returns never go to the removed worker, but
+    /// a concurrent [`Router::send`] that picked it before may still deliver to it.
+    ///
+    /// With [`Strategy::ConsistentHash`], only the keys of the removed worker move to other
+    /// workers.
+    pub fn remove_worker(&self, actor_ref: &ActorRef<A>) -> bool {
+        let mut pool = self.pool.write().unwrap();
+        let len = pool.workers.len();
+        let mut removed = Vec::new();
+        pool.workers.retain(|worker| {
+            let keep = !worker.actor_ref.same_actor(actor_ref);
+            if !keep {
+                removed.push(worker.id);
+            }
+            keep
+        });
+        pool.ring.retain(|(_, id)| !removed.contains(id));
+        pool.workers.len() != len
+    }
+
+    /// Returns the number of workers in the pool.
+    pub fn len(&self) -> usize {
+        self.pool.read().unwrap().workers.len()
+    }
+
+    /// Returns `true` if the pool has no workers.
+    pub fn is_empty(&self) -> bool {
+        self.pool.read().unwrap().workers.is_empty()
+    }
+
+    /// Picks the worker for `msg`, or returns [`None`] if there is no worker to send it to.
+    /// For the round-robin strategies, this counts as routing a message.
+    pub fn route(&self, msg: &A::Message) -> Option<ActorRef<A>> {
+        let pool = self.pool.read().unwrap();
+        if pool.workers.is_empty() {
+            return None;
+        }
+        let idx = match &self.strategy {
+            Strategy::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed) % pool.workers.len(),
+            Strategy::WeightedRoundRobin(_) => {
+                let total: usize = pool.workers.iter().map(|w| w.weight as usize).sum();
+                if total == 0 {
+                    return None;
+                }
+                let mut slot = self.next.fetch_add(1, Ordering::Relaxed) % total;
+                pool.workers.iter().position(|worker| {
+                    let hit = slot < worker.weight as usize;
+                    slot = slot.saturating_sub(worker.weight as usize);
+                    hit
+                })?
+            }
+            Strategy::ConsistentHash(key_fn) => {
+                let key = key_fn(msg);
+                let at = pool.ring.partition_point(|&(p, _)| p < key);
+                let (_, id) = pool.ring.get(at).or(pool.ring.first())?;
+                pool.workers.iter().position(|worker| worker.id == *id)?
+            }
+        };
+        Some(pool.workers[idx].actor_ref.clone())
+    }
+
+    /// Sends `msg` to the worker picked by [`Router::route`]. If there is no worker, or the
+    /// worker's mailbox is closed, the message is returned in [`Err`].
+    pub async fn send(&self, msg: A::Message) -> Result<(), A::Message> {
+        match self.route(&msg) {
+            Some(actor_ref) => actor_ref.send(msg).await,
+            None => Err(msg),
+        }
+    }
+}
+
+impl<A: Actor> std::fmt::Debug for Router<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Router")
+            .field("workers", &self.len())
+            .field("strategy", &self.strategy)
+            .finish()
+    }
+}
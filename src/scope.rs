@@ -0,0 +1,176 @@
+use std::{future::Future, pin::Pin, task::Poll};
+
+use either::Either;
+
+use crate::{select::biased, TrySendError};
+
+/// An actor that may borrow data from its surroundings, run by a [`Scope`].
+///
+/// Unlike [`Actor`](crate::Actor), a scoped actor doesn't need to be `'static`, so it can hold
+/// e.g. a `&mut` to a buffer owned by the caller. In exchange, it only gets a plain message
+/// loop: there is no [`WeakActorRef`](crate::WeakActorRef) to itself, no stop message, and none
+/// of the run loop's other features.
+pub trait ScopedActor: Send {
+    type Error: Send;
+    type Message: Send;
+
+    /// Called with each message received by the actor, one at a time.
+    fn on_msg(
+        &mut self,
+        msg: Self::Message,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+
+    /// Called once the scope has closed the actor's mailbox, after the messages still queued
+    /// have been handled.
+    fn on_stop(&mut self) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        async { Ok(()) }
+    }
+}
+
+/// A reference to an actor running in a [`Scope`], see [`Scope::spawn`].
+pub struct ScopedRef<M> {
+    sender: async_channel::Sender<M>,
+}
+
+impl<M> ScopedRef<M> {
+    /// Sends a message to the actor, waiting for room in its mailbox. Fails with the message in
+    /// [`Err`] once the scope has closed the actor's mailbox, or if the actor failed.
+    pub async fn send(&self, msg: M) -> Result<(), M> {
+        self.sender.send(msg).await.map_err(|err| err.0)
+    }
+
+    /// Sends a message to the actor if its mailbox has room right away.
+    pub fn try_send(&self, msg: M) -> Result<(), TrySendError<M>> {
+        self.sender.try_send(msg).map_err(|err| match err {
+            async_channel::TrySendError::Full(msg) => TrySendError::Full(msg),
+            async_channel::TrySendError::Closed(msg) => TrySendError::Closed(msg),
+        })
+    }
+}
+
+impl<M> Clone for ScopedRef<M> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+impl<M> std::fmt::Debug for ScopedRef<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScopedRef")
+            .field("len", &self.sender.len())
+            .finish_non_exhaustive()
+    }
+}
+
+type ScopedRun<'a, E> = Pin<Box<dyn Future<Output = Result<(), E>> + Send + 'a>>;
+
+/// Runs actors that borrow data for the lifetime `'a`, in the manner of `std::thread::scope`.
+///
+/// Actors are added with [`Scope::spawn`], then [`Scope::run`] drives them together with a body
+/// that talks to them, all on the caller's task. Once the body completes, the scope closes the
+/// actors' mailboxes, and `run` only resolves once every actor has handled its queued messages
+/// and stopped, so no actor outlives the data it borrows. Dropping the future returned by `run`
+/// drops the actors along with it.
+///
+/// Mailboxes are closed one at a time, in reverse order of spawning, and each actor is waited
+/// for before the next mailbox is closed. Actors spawned first can therefore be handed to the
+/// ones spawned later, which keep sending to them while they stop, as in a pipeline.
+///
+/// All actors of a scope share the error type `E`, and the first error fails the whole scope.
+pub struct Scope<'a, E> {
+    /// The actors' message loops, in order of spawning. `None` once an actor has stopped.
+    runs: Vec<Option<ScopedRun<'a, E>>>,
+    /// Close the actors' mailboxes once the body is done, in the same order as `runs`.
+    closers: Vec<Box<dyn FnOnce() + Send + 'a>>,
+}
+
+impl<'a, E: Send + 'a> Scope<'a, E> {
+    /// Creates a scope without any actors.
+    pub fn new() -> Self {
+        Self {
+            runs: Vec::new(),
+            closers: Vec::new(),
+        }
+    }
+
+    /// Adds `actor` to the scope, with the given mailbox size, and returns a reference to it.
+    /// The actor starts handling messages once the scope runs.
+    pub fn spawn<A: ScopedActor<Error = E> + 'a>(
+        &mut self,
+        mut actor: A,
+        mailbox_size: Option<usize>,
+    ) -> ScopedRef<A::Message> {
+        crate::channel::require_bounded(mailbox_size);
+        let (sender, receiver) = match mailbox_size {
+            Some(size) => async_channel::bounded(size),
+            None => async_channel::unbounded(),
+        };
+        let closer = receiver.clone();
+        self.closers.push(Box::new(move || {
+            closer.close();
+        }));
+        self.runs.push(Some(Box::pin(async move {
+            while let Ok(msg) = receiver.recv().await {
+                actor.on_msg(msg).await?;
+            }
+            actor.on_stop().await
+        })));
+        ScopedRef { sender }
+    }
+
+    /// Runs the actors alongside `body`, and resolves to the body's output once the body has
+    /// completed and every actor has stopped. Fails with the first error of an actor, dropping
+    /// the body and the other actors.
+    pub async fn run<T>(self, body: impl Future<Output = T> + Send + 'a) -> Result<T, E> {
+        let Self { mut runs, closers } = self;
+        let output = match biased(drive_until(&mut runs, |_| false), body).await {
+            Either::Left(Err(err)) => return Err(err),
+            Either::Left(Ok(())) => unreachable!("`drive_until` never done"),
+            Either::Right(output) => output,
+        };
+        for (idx, close) in closers.into_iter().enumerate().rev() {
+            close();
+            drive_until(&mut runs, |runs| runs[idx].is_none()).await?;
+        }
+        Ok(output)
+    }
+}
+
+impl<'a, E: Send + 'a> Default for Scope<'a, E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E> std::fmt::Debug for Scope<'_, E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Scope")
+            .field("actors", &self.runs.len())
+            .finish()
+    }
+}
+
+/// Polls every actor that is still running until `done` returns `true`, or an actor fails.
+async fn drive_until<E>(
+    runs: &mut [Option<ScopedRun<'_, E>>],
+    done: impl Fn(&[Option<ScopedRun<'_, E>>]) -> bool,
+) -> Result<(), E> {
+    std::future::poll_fn(|cx| {
+        for slot in runs.iter_mut() {
+            if let Some(Poll::Ready(res)) = slot.as_mut().map(|run| run.as_mut().poll(cx)) {
+                *slot = None;
+                if let Err(err) = res {
+                    return Poll::Ready(Err(err));
+                }
+            }
+        }
+        if done(runs) {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
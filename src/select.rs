@@ -0,0 +1,39 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use either::Either;
+
+/// Waits for either of two futures, polling `first` before `second` on every wake-up so that
+/// `first` wins if both are ready.
+pub(crate) fn biased<F1: Future, F2: Future>(first: F1, second: F2) -> Biased<F1, F2> {
+    Biased { first, second }
+}
+
+pin_project_lite::pin_project! {
+    #[must_use = "futures do nothing unless you `.await` or poll them"]
+    pub(crate) struct Biased<F1, F2> {
+        #[pin]
+        first: F1,
+        #[pin]
+        second: F2,
+    }
+}
+
+impl<F1: Future, F2: Future> Future for Biased<F1, F2> {
+    type Output = Either<F1::Output, F2::Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+
+        if let Poll::Ready(t) = this.first.poll(cx) {
+            return Poll::Ready(Either::Left(t));
+        }
+        if let Poll::Ready(u) = this.second.poll(cx) {
+            return Poll::Ready(Either::Right(u));
+        }
+        Poll::Pending
+    }
+}
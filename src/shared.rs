@@ -0,0 +1,325 @@
+use std::{
+    any::Any,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex, OnceLock,
+    },
+};
+
+use event_listener::Event;
+
+use crate::child::Child;
+
+/// State shared between an actor's mailbox and every reference to the actor.
+#[derive(Debug, Default)]
+pub(crate) struct Shared {
+    /// Set once `on_start` has completed successfully.
+    started: AtomicBool,
+    /// Set once the mailbox has been dropped, i.e. the actor is no longer running.
+    exited: AtomicBool,
+    /// Set when the actor is driven by an `ActorRun`, which reports how it finished.
+    run_tracked: AtomicBool,
+    /// Set by the `ActorRun` once it has resolved or was dropped, after `exited`.
+    finished: AtomicBool,
+    /// Set with `finished` if the `ActorRun` resolved to an error, or was aborted.
+    failed: AtomicBool,
+    /// Set when the `ActorRun` was aborted with its `AbortHandle`, or dropped before it finished.
+    aborted: AtomicBool,
+    /// Set while the default run loop is running a message handler.
+    in_handler: AtomicBool,
+    /// Set by `ActorRef::skip_drain`, so that the stop drain ends at the next message boundary.
+    skip_drain: AtomicBool,
+    /// Set by `WeakActorRef::unstash`, and cleared by the run loop once it has released the stash.
+    unstash_requested: AtomicBool,
+    /// Set by the first stop request, so that only one stop message is ever sent.
+    stop_requested: AtomicBool,
+    /// Set when `Actor::on_stop` fails in the default run loop, after the actor has stopped.
+    stop_failed: AtomicBool,
+    /// Number of times the actor went idle, i.e. handled a message and found its mailbox empty.
+    idle_count: AtomicU64,
+    /// Notified whenever `started`, `exited` or `finished` is set, or `idle_count` changes.
+    lifecycle: Event,
+    /// Load shedding threshold set with `Mailbox::set_shed_above`.
+    pub(crate) shed_above: OnceLock<usize>,
+    /// Handler concurrency set with `ConcurrentActor::into_future_concurrent`.
+    pub(crate) concurrency: OnceLock<usize>,
+    /// Aborts the actor's run loop, handed out by `ActorRun::abort_handle`.
+    pub(crate) abort: crate::AbortHandle,
+    /// Message rate limit set with `Mailbox::set_rate_limit`.
+    pub(crate) rate_limit: OnceLock<crate::rate::RateLimit>,
+    /// Spawner for detached work, set with `Mailbox::set_spawner`.
+    pub(crate) spawner: OnceLock<crate::detach::Spawner>,
+    /// Output channel attached with `Mailbox::set_outputs`.
+    pub(crate) outputs: OnceLock<Outputs>,
+    /// Event channels registered with `ActorRef::subscribe_events`, each a type-erased
+    /// `Sender<A::Event>` of the actor's `Observable::Event` type.
+    /// Cleared on exit, which ends the subscribers' streams.
+    subscribers: Mutex<Vec<Box<dyn Any + Send>>>,
+    /// The message whose handler failed first, formatted, see `Mailbox::set_traced`.
+    failed_message: OnceLock<String>,
+    /// Durations of the actor's handler calls, recorded by the default run loop.
+    #[cfg(feature = "metrics")]
+    pub(crate) handler_latency: crate::metrics::Histogram,
+    /// Children registered with `WeakActorRef::spawn_child`, stopped when the actor stops.
+    children: Mutex<Vec<Child>>,
+}
+
+/// A type-erased output channel of an actor, see `Mailbox::set_outputs`.
+pub(crate) struct Outputs {
+    /// The `(Sender<Out>, Receiver<Out>)` pair of the channel.
+    channel: Box<dyn Any + Send + Sync>,
+    close: Box<dyn Fn() + Send + Sync>,
+}
+
+impl Outputs {
+    pub(crate) fn new<Out: Send + 'static>(size: Option<usize>) -> Self {
+        let (sender, receiver) = match size {
+            Some(size) => async_channel::bounded::<Out>(size),
+            None => async_channel::unbounded(),
+        };
+        let closer = sender.clone();
+        Self {
+            channel: Box::new((sender, receiver)),
+            close: Box::new(move || {
+                closer.close();
+            }),
+        }
+    }
+
+    pub(crate) fn channel<Out: Send + 'static>(
+        &self,
+    ) -> Option<&(async_channel::Sender<Out>, async_channel::Receiver<Out>)> {
+        self.channel.downcast_ref()
+    }
+}
+
+impl std::fmt::Debug for Outputs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Outputs").finish_non_exhaustive()
+    }
+}
+
+impl Shared {
+    pub(crate) fn is_started(&self) -> bool {
+        self.started.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn set_started(&self) {
+        self.started.store(true, Ordering::Release);
+        self.lifecycle.notify(usize::MAX);
+    }
+
+    pub(crate) fn is_in_handler(&self) -> bool {
+        self.in_handler.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn set_in_handler(&self, in_handler: bool) {
+        self.in_handler.store(in_handler, Ordering::Release);
+    }
+
+    pub(crate) fn is_drain_skipped(&self) -> bool {
+        self.skip_drain.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn skip_drain(&self) {
+        self.skip_drain.store(true, Ordering::Release);
+    }
+
+    pub(crate) fn request_unstash(&self) {
+        self.unstash_requested.store(true, Ordering::Release);
+    }
+
+    /// Clears a pending unstash request, returning `true` if there was one.
+    pub(crate) fn take_unstash_request(&self) -> bool {
+        self.unstash_requested.swap(false, Ordering::AcqRel)
+    }
+
+    /// Marks the actor as stopping, returning `false` if it already was.
+    pub(crate) fn request_stop(&self) -> bool {
+        !self.stop_requested.swap(true, Ordering::AcqRel)
+    }
+
+    pub(crate) fn is_stop_requested(&self) -> bool {
+        self.stop_requested.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn is_stop_failed(&self) -> bool {
+        self.stop_failed.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn set_stop_failed(&self) {
+        self.stop_failed.store(true, Ordering::Release);
+    }
+
+    pub(crate) fn set_idle(&self) {
+        self.idle_count.fetch_add(1, Ordering::AcqRel);
+        self.lifecycle.notify(usize::MAX);
+    }
+
+    /// Waits until the actor next goes idle, returning `false` if it exited first.
+    pub(crate) async fn wait_idle(&self) -> bool {
+        let seen = self.idle_count.load(Ordering::Acquire);
+        self.wait_lifecycle(|shared| {
+            if shared.idle_count.load(Ordering::Acquire) != seen {
+                Some(true)
+            } else if shared.is_exited() {
+                Some(false)
+            } else {
+                None
+            }
+        })
+        .await
+    }
+
+    pub(crate) fn set_exited(&self) {
+        if let Some(outputs) = self.outputs.get() {
+            (outputs.close)();
+        }
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.clear();
+        self.exited.store(true, Ordering::Release);
+        drop(subscribers);
+        self.lifecycle.notify(usize::MAX);
+    }
+
+    pub(crate) fn is_exited(&self) -> bool {
+        self.exited.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn set_aborted(&self) {
+        self.aborted.store(true, Ordering::Release);
+    }
+
+    pub(crate) fn is_aborted(&self) -> bool {
+        self.aborted.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn track_run(&self) {
+        self.run_tracked.store(true, Ordering::Release);
+    }
+
+    /// Records how the `ActorRun` finished. Only the first call has an effect.
+    pub(crate) fn set_finished(&self, failed: bool) {
+        if self.finished.load(Ordering::Acquire) {
+            return;
+        }
+        self.failed.store(failed, Ordering::Release);
+        self.finished.store(true, Ordering::Release);
+        self.lifecycle.notify(usize::MAX);
+    }
+
+    /// Registers a subscriber for events of type `Ev`, the actor's `Observable::Event`. The receiver is closed right away if the
+    /// actor has already exited.
+    pub(crate) fn subscribe<Ev: Send + 'static>(&self) -> async_channel::Receiver<Ev> {
+        let (sender, receiver) = async_channel::unbounded::<Ev>();
+        let mut subscribers = self.subscribers.lock().unwrap();
+        // Checked under the lock, which `set_exited` holds while it clears the subscribers.
+        if !self.is_exited() {
+            subscribers.retain(|sender| !is_closed::<Ev>(sender));
+            subscribers.push(Box::new(sender));
+        }
+        receiver
+    }
+
+    /// Sends a clone of `event` to every subscriber for events of type `Ev`, pruning the ones
+    /// that were dropped, and returns the number of subscribers it was sent to.
+    pub(crate) fn broadcast<Ev: Clone + Send + 'static>(&self, event: Ev) -> usize {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|sender| !is_closed::<Ev>(sender));
+        subscribers
+            .iter()
+            .filter_map(|sender| sender.downcast_ref::<async_channel::Sender<Ev>>())
+            .filter(|sender| sender.try_send(event.clone()).is_ok())
+            .count()
+    }
+
+    /// Records the message whose handler failed. Only the first call has an effect, so that the
+    /// message behind the error the actor fails with is kept when draining continues.
+    pub(crate) fn set_failed_message(&self, described: String) {
+        let _ = self.failed_message.set(described);
+    }
+
+    pub(crate) fn failed_message(&self) -> Option<String> {
+        self.failed_message.get().cloned()
+    }
+
+    pub(crate) fn add_child(&self, child: Child) {
+        let mut children = self.children.lock().unwrap();
+        children.retain(|child| !child.is_exited());
+        children.push(child);
+    }
+
+    /// Stops every registered child that is still running, and clears the registry.
+    pub(crate) fn stop_children(&self) {
+        let children = std::mem::take(&mut *self.children.lock().unwrap());
+        for child in children.iter().filter(|child| !child.is_exited()) {
+            child.stop();
+        }
+    }
+
+    /// Waits until the actor has started, returning `false` if it exited without starting.
+    pub(crate) async fn wait_started(&self) -> bool {
+        self.wait_lifecycle(|shared| {
+            if shared.is_started() {
+                Some(true)
+            } else if shared.is_exited() {
+                Some(false)
+            } else {
+                None
+            }
+        })
+        .await
+    }
+
+    /// Returns `true` once the actor has exited and its result is known, see `wait_finished`.
+    pub(crate) fn is_finished(&self) -> bool {
+        if self.run_tracked.load(Ordering::Acquire) {
+            self.finished.load(Ordering::Acquire)
+        } else {
+            self.is_exited()
+        }
+    }
+
+    /// Waits until the actor has exited.
+    pub(crate) async fn wait_exited(&self) {
+        self.wait_lifecycle(|shared| shared.is_exited().then_some(()))
+            .await
+    }
+
+    /// Waits until the actor has exited and its result is known, returning `true` if it failed.
+    /// Actors that are not driven by an `ActorRun` are considered to have succeeded on exit.
+    pub(crate) async fn wait_finished(&self) -> bool {
+        self.wait_lifecycle(|shared| {
+            if shared.run_tracked.load(Ordering::Acquire) {
+                shared
+                    .finished
+                    .load(Ordering::Acquire)
+                    .then(|| shared.failed.load(Ordering::Acquire))
+            } else {
+                shared.is_exited().then_some(false)
+            }
+        })
+        .await
+    }
+
+    /// Waits until `check` returns [`Some`], re-evaluating it on every lifecycle change.
+    async fn wait_lifecycle<T>(&self, check: impl Fn(&Self) -> Option<T>) -> T {
+        loop {
+            if let Some(res) = check(self) {
+                return res;
+            }
+            let listener = self.lifecycle.listen();
+            if let Some(res) = check(self) {
+                return res;
+            }
+            listener.await;
+        }
+    }
+}
+
+/// Returns `true` if `sender` is a `Sender<Ev>` whose receiver was dropped.
+fn is_closed<Ev: Send + 'static>(sender: &(dyn Any + Send)) -> bool {
+    sender
+        .downcast_ref::<async_channel::Sender<Ev>>()
+        .is_some_and(|sender| sender.is_closed())
+}
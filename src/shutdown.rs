@@ -0,0 +1,121 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use crate::{shared::Shared, Actor, ActorRef};
+
+/// An actor registered with a [`ShutdownGroup`], type-erased so that a group can hold actors of
+/// any type.
+struct Member {
+    shared: Arc<Shared>,
+    /// Sends the actor its stop message.
+    stop: Box<dyn FnOnce() + Send>,
+}
+
+#[derive(Default)]
+/// Stops a group of actors in a defined order, e.g. frontends before the backends they call.
+///
+/// Every actor is added with a phase. [`ShutdownGroup::stop_all`] stops the actors of the lowest
+/// phase and waits until all of them have exited, then moves on to the next phase. Actors in the
+/// same phase stop concurrently.
+///
+/// The group only holds weak references, so it does not keep its actors alive, and actors that
+/// already stopped are skipped.
+pub struct ShutdownGroup {
+    phases: BTreeMap<usize, Vec<Member>>,
+    abort_on_error: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Returned by [`ShutdownGroup::stop_all`] if an actor failed, i.e. its [`ActorRun`](crate::ActorRun)
+/// resolved to an error.
+pub struct ShutdownFailed {
+    /// The phases in which at least one actor failed, in ascending order.
+    pub phases: Vec<usize>,
+    /// Whether the shutdown stopped after a failing phase, see
+    /// [`ShutdownGroup::abort_on_error`]. The actors of later phases were not stopped.
+    pub aborted: bool,
+}
+
+impl ShutdownGroup {
+    /// Creates an empty group.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes [`ShutdownGroup::stop_all`] stop after a phase in which an actor failed, leaving
+    /// the actors of later phases running. By default, a failure doesn't prevent later phases.
+    pub fn abort_on_error(mut self, abort_on_error: bool) -> Self {
+        self.abort_on_error = abort_on_error;
+        self
+    }
+
+    /// Adds an actor to be stopped with `stop` in `phase`. Lower phases are stopped first.
+    pub fn add<A: Actor>(&mut self, phase: usize, actor_ref: &ActorRef<A>, stop: A::Message) {
+        let weak = actor_ref.downgrade();
+        self.phases.entry(phase).or_default().push(Member {
+            shared: weak.shared.clone(),
+            stop: Box::new(move || {
+                // Fails if the actor already stopped or is stopping, which is fine.
+                let _ = weak.stop(stop);
+            }),
+        });
+    }
+
+    /// Returns the number of actors in the group.
+    pub fn len(&self) -> usize {
+        self.phases.values().map(Vec::len).sum()
+    }
+
+    /// Returns `true` if no actors were added.
+    pub fn is_empty(&self) -> bool {
+        self.phases.is_empty()
+    }
+
+    /// Stops the group phase by phase, see [`ShutdownGroup`], and resolves once every phase has
+    /// exited.
+    ///
+    /// Actors that are not driven by an [`ActorRun`](crate::ActorRun), e.g. because they run
+    /// their mailbox directly, count as succeeded once they exit. Actors that were aborted, see
+    /// [`ActorRef::is_aborted`](crate::ActorRef::is_aborted), count as failed.
+    pub async fn stop_all(self) -> Result<(), ShutdownFailed> {
+        let mut failed = Vec::new();
+        for (phase, members) in self.phases {
+            let shared: Vec<_> = members
+                .into_iter()
+                .map(|member| {
+                    (member.stop)();
+                    member.shared
+                })
+                .collect();
+            let mut phase_failed = false;
+            for shared in shared {
+                phase_failed |= shared.wait_finished().await;
+            }
+            if phase_failed {
+                failed.push(phase);
+                if self.abort_on_error {
+                    return Err(ShutdownFailed {
+                        phases: failed,
+                        aborted: true,
+                    });
+                }
+            }
+        }
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(ShutdownFailed {
+                phases: failed,
+                aborted: false,
+            })
+        }
+    }
+}
+
+impl std::fmt::Debug for ShutdownGroup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShutdownGroup")
+            .field("len", &self.len())
+            .field("abort_on_error", &self.abort_on_error)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::VecDeque;
+
+use crate::mailbox::Packet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// How the mailbox orders unstashed messages against the messages still queued in it, see
+/// [`Mailbox::set_unstash_mode`](crate::Mailbox::set_unstash_mode).
+///
+/// Both modes guarantee that:
+///
+/// - unstashed messages are received in the order they were stashed, and messages released by
+///   an unstash come after those released earlier and not received yet;
+/// - queued messages keep the order the mailbox gives them, e.g. LIFO or by priority, so a
+///   "queued message" below is whichever message the mailbox would deliver next;
+/// - follow-ups returned by [`Actor::on_msg_emit`](crate::Actor::on_msg_emit) are received
+///   before both, and express messages sent with
+///   [`ActorRef::send_priority`](crate::ActorRef::send_priority) are handled ahead of
+///   everything else;
+/// - a message that is stashed again while the stash is being released waits for the next
+///   unstash, behind the messages already released.
+pub enum UnstashMode {
+    /// Every unstashed message is received before any message queued in the mailbox, including
+    /// messages sent after the unstash. This keeps the order in which the messages arrived,
+    /// which suits state machines that expect stashed messages to be handled first.
+    #[default]
+    Priority,
+    /// Unstashed messages alternate one for one with messages queued in the mailbox, starting
+    /// with an unstashed message after each unstash, so that a large stash does not hold back
+    /// new messages. Whichever side runs out first, the other is received back to back: with
+    /// no message queued, unstashed messages are not held back, and once the stash is empty,
+    /// queued messages are received as usual.
+    Interleaved,
+}
+
+/// Messages set aside with [`Mailbox::stash`](crate::Mailbox::stash), with the delivery
+/// tracking they were received with.
+pub(crate) struct Stash<M> {
+    /// Messages stashed since the last unstash, in the order they were stashed.
+    pub(crate) stashed: VecDeque<Packet<M>>,
+    /// Messages released by an unstash and not received yet.
+    pub(crate) unstashed: VecDeque<Packet<M>>,
+    pub(crate) mode: UnstashMode,
+    /// Whether the next message comes from `unstashed`, for [`UnstashMode::Interleaved`].
+    unstashed_turn: bool,
+}
+
+impl<M> Stash<M> {
+    pub(crate) fn new() -> Self {
+        Self {
+            stashed: VecDeque::new(),
+            unstashed: VecDeque::new(),
+            mode: UnstashMode::default(),
+            unstashed_turn: false,
+        }
+    }
+
+    /// Releases the stashed messages, after the ones released earlier.
+    pub(crate) fn unstash(&mut self) {
+        self.unstashed.append(&mut self.stashed);
+        self.unstashed_turn = true;
+    }
+
+    /// Takes the next unstashed message if it is due before the messages queued in the mailbox,
+    /// of which there are `queued`.
+    pub(crate) fn next(&mut self, queued: usize) -> Option<Packet<M>> {
+        if self.unstashed.is_empty() {
+            return None;
+        }
+        match self.mode {
+            UnstashMode::Priority => self.unstashed.pop_front(),
+            UnstashMode::Interleaved if self.unstashed_turn || queued == 0 => {
+                self.unstashed_turn = false;
+                self.unstashed.pop_front()
+            }
+            UnstashMode::Interleaved => {
+                self.unstashed_turn = true;
+                None
+            }
+        }
+    }
+}
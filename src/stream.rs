@@ -0,0 +1,152 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+
+use crate::{Actor, ActorRef, Mailbox, MailboxEvent};
+
+type Pending<A> = Pin<Box<dyn Future<Output = MailboxEvent<A>> + Send>>;
+
+impl<A: Actor> Mailbox<A> {
+    /// Turns the mailbox into a [`Stream`] of the events [`Mailbox::recv`] receives, for custom
+    /// run loops that want to use stream combinators instead of calling `recv` in a loop.
+    ///
+    /// The stream follows the default run loop: once it yields a stop event, the mailbox is
+    /// closed, and the stream yields the messages that were still queued before it ends. It also
+    /// ends once the message channel is closed and empty, so it never yields
+    /// [`MailboxEvent::Message`] with [`None`].
+    pub fn into_stream(self) -> MailboxStream<A> {
+        MailboxStream {
+            mailbox: Arc::new(self),
+            pending: None,
+            draining: false,
+            done: false,
+        }
+    }
+}
+
+/// A [`Stream`] of the events received by a mailbox, created with [`Mailbox::into_stream`].
+pub struct MailboxStream<A: Actor> {
+    mailbox: Arc<Mailbox<A>>,
+    pending: Option<Pending<A>>,
+    /// Set once a stop event has been yielded, after which only queued messages are received.
+    draining: bool,
+    done: bool,
+}
+
+impl<A: Actor> MailboxStream<A> {
+    /// Returns the mailbox, e.g. to call [`Mailbox::complete`] after handling a message, or to
+    /// reach the actor's own reference through `this`.
+    pub fn mailbox(&self) -> &Mailbox<A> {
+        &self.mailbox
+    }
+}
+
+impl<A: Actor> Stream for MailboxStream<A> {
+    type Item = MailboxEvent<A>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.done {
+            return Poll::Ready(None);
+        }
+        let pending = this.pending.get_or_insert_with(|| {
+            let mailbox = this.mailbox.clone();
+            if this.draining {
+                Box::pin(async move { MailboxEvent::Message(mailbox.recv_msg().await) })
+            } else {
+                Box::pin(async move { mailbox.recv().await })
+            }
+        });
+        let event = match pending.as_mut().poll(cx) {
+            Poll::Ready(event) => event,
+            Poll::Pending => return Poll::Pending,
+        };
+        this.pending = None;
+        match event {
+            MailboxEvent::Stop(stop) => {
+                this.mailbox.close();
+                this.draining = true;
+                Poll::Ready(Some(MailboxEvent::Stop(stop)))
+            }
+            MailboxEvent::Message(Some(msg)) => Poll::Ready(Some(MailboxEvent::Message(Some(msg)))),
+            MailboxEvent::Message(None) => {
+                this.done = true;
+                Poll::Ready(None)
+            }
+        }
+    }
+}
+
+impl<A: Actor> ActorRef<A> {
+    /// Combines the reference with the actor's output channel, see
+    /// [`Mailbox::set_outputs`], into a [`Duplex`] that sends messages in and streams
+    /// outputs of type `Out` back. If the actor has no output channel of type `Out`, the
+    /// reference is returned in [`Err`].
+    pub fn into_duplex<Out: Send + 'static>(self) -> Result<Duplex<A, Out>, Self> {
+        match self.outputs::<Out>() {
+            Some(outputs) => Ok(Duplex {
+                actor_ref: self,
+                outputs: Box::pin(outputs),
+            }),
+            None => Err(self),
+        }
+    }
+}
+
+/// Both ends of an actor with an output channel, created with [`ActorRef::into_duplex`]: messages
+/// go in through [`Duplex::send`], and the values the actor emits come out as a [`Stream`].
+///
+/// This makes an actor fit a framed transport, where incoming frames become messages and
+/// outputs become outgoing frames. There is no `Sink` implementation, as the crate doesn't
+/// depend on `futures-sink`; wrap [`Duplex::send`] in a sink adapter instead. The stream ends
+/// once the actor has stopped and all of its outputs have been received, so
+/// [`Duplex::close`] followed by reading the stream to the end flushes the actor.
+pub struct Duplex<A: Actor, Out> {
+    actor_ref: ActorRef<A>,
+    outputs: Pin<Box<async_channel::Receiver<Out>>>,
+}
+
+impl<A: Actor, Out> Duplex<A, Out> {
+    /// Sends a message to the actor, see [`ActorRef::send`].
+    pub async fn send(&self, msg: A::Message) -> Result<(), A::Message> {
+        self.actor_ref.send(msg).await
+    }
+
+    /// Closes the actor's mailbox, see [`ActorRef::close`]. The actor handles the messages
+    /// already queued and stops, which then ends the stream.
+    pub fn close(&self) -> bool {
+        self.actor_ref.close()
+    }
+
+    /// Returns the reference to the actor.
+    pub fn actor_ref(&self) -> &ActorRef<A> {
+        &self.actor_ref
+    }
+
+    /// Splits the duplex back into the reference and the receiver of the output channel.
+    pub fn into_parts(self) -> (ActorRef<A>, async_channel::Receiver<Out>) {
+        // A polled receiver cannot be moved out of its pin, but a clone receives from the same
+        // channel.
+        let outputs = self.outputs.as_ref().get_ref().clone();
+        (self.actor_ref, outputs)
+    }
+}
+
+impl<A: Actor, Out> Stream for Duplex<A, Out> {
+    type Item = Out;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Out>> {
+        self.get_mut().outputs.as_mut().poll_next(cx)
+    }
+}
+
+impl<A: Actor, Out> std::fmt::Debug for Duplex<A, Out> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Duplex").finish_non_exhaustive()
+    }
+}
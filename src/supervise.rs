@@ -0,0 +1,272 @@
+use std::{
+    any::Any,
+    future::Future,
+    panic::{catch_unwind, resume_unwind, AssertUnwindSafe},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use either::Either;
+
+use crate::{layer::Next, recorder::Recorded, select::biased, Actor, Mailbox};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// What the run loop does when [`Actor::on_msg`] panics, as decided by [`Actor::on_panic`].
+pub enum Supervision {
+    /// Let the panic continue unwinding, taking down the actor's task.
+    #[default]
+    Stop,
+    /// Hand the message to [`Actor::on_poison`], run [`Actor::on_start`] again, and continue
+    /// with the messages still queued in the mailbox.
+    Restart,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// What the run loop does when [`Actor::on_msg`] returns an error, as decided by
+/// [`Actor::on_error`].
+pub enum ErrorAction {
+    /// Return the error, which fails the actor, or while draining is handled according to
+    /// [`Actor::on_drain_error`].
+    #[default]
+    Fail,
+    /// Restore the actor to the state it was in before the message, and continue with the next
+    /// message as if the failed one had never been received. Only actors whose mailbox was set up
+    /// with [`Mailbox::set_transactional`] keep that state around; for other actors this is the
+    /// same as [`ErrorAction::Fail`].
+    Rollback,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// What the run loop does when a handler returns an error while the actor drains its mailbox
+/// on stop, as decided by [`Actor::on_drain_error`].
+pub enum DrainErrorPolicy {
+    /// Stop draining, and return the error without calling [`Actor::on_stop`].
+    Abort,
+    /// Ignore the error, and continue draining.
+    Continue,
+    /// Continue draining and call [`Actor::on_stop`], then return the first error.
+    #[default]
+    ContinueThenFail,
+}
+
+pin_project_lite::pin_project! {
+    /// Resolves to the output of the inner future, or to the panic payload if polling it panics.
+    #[must_use = "futures do nothing unless you `.await` or poll them"]
+    struct CatchUnwind<F> {
+        #[pin]
+        future: F,
+    }
+}
+
+impl<F: Future> Future for CatchUnwind<F> {
+    type Output = Result<F::Output, Box<dyn Any + Send>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let future = self.project().future;
+        match catch_unwind(AssertUnwindSafe(|| future.poll(cx))) {
+            Ok(Poll::Ready(out)) => Poll::Ready(Ok(out)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(payload) => Poll::Ready(Err(payload)),
+        }
+    }
+}
+
+/// Runs [`Actor::on_msg_ctx`] for `msg` and completes it in the mailbox, applying the actor's
+/// [`Supervision`] if the handler panics, and the mailbox's handler timeout if it has one.
+/// Waits for the actor's rate limit, if it has one. Messages that outlived their TTL go to
+/// [`Actor::on_expired`] instead, and those that missed their deadline to
+/// [`Actor::on_deadline_exceeded`], without waiting. Messages that [`Actor::should_stash`]
+/// defers are stashed, and the stash is released if the handler asked for it. Counts the
+/// message for the actor's snapshots, and marks the actor idle if the mailbox is empty
+/// afterwards.
+pub(crate) async fn handle<A: Actor>(
+    actor: &mut A,
+    mailbox: &Mailbox<A>,
+    msg: A::Message,
+) -> Result<(), A::Error> {
+    if mailbox.is_expired() {
+        mailbox.abandon();
+        mailbox.take_meta();
+        match mailbox.deadline_exceeded() {
+            Some(deadline) => actor.on_deadline_exceeded(msg, deadline),
+            None => actor.on_expired(msg),
+        }
+        if mailbox.is_queued() {
+            mailbox.settle_barriers();
+        }
+    } else if actor.should_stash(&msg) {
+        if mailbox.is_queued() {
+            mailbox.settle_barriers();
+        }
+        mailbox.stash(msg);
+    } else {
+        if let Some(limit) = mailbox.this.shared.rate_limit.get() {
+            limit.acquire().await;
+        }
+        run_handler(actor, mailbox, msg).await?;
+        if mailbox.this.shared.abort.is_aborted() {
+            return Ok(());
+        }
+        if mailbox.this.shared.take_unstash_request() {
+            mailbox.unstash();
+        }
+    }
+    if let Some(persister) = &mailbox.persister {
+        persister.handled(actor, false);
+    }
+    if mailbox.is_empty() {
+        mailbox.this.shared.set_idle();
+    }
+    Ok(())
+}
+
+async fn run_handler<A: Actor>(
+    actor: &mut A,
+    mailbox: &Mailbox<A>,
+    msg: A::Message,
+) -> Result<(), A::Error> {
+    let ctx = mailbox.handler_ctx();
+    let queued = mailbox.is_queued();
+    let snapshot = mailbox.snapshot.map(|snapshot| snapshot(actor));
+    let described = mailbox.describe.map(|describe| describe(&msg));
+    let handled = CatchUnwind {
+        future: Next::new(&mailbox.layers, ctx).run(actor, &mailbox.this, msg),
+    };
+    let shared = &mailbox.this.shared;
+    debug_assert!(
+        !shared.is_in_handler(),
+        "a handler of `{}` was started while another one was running",
+        std::any::type_name::<A>()
+    );
+    shared.set_in_handler(true);
+    #[cfg(feature = "metrics")]
+    let started = std::time::Instant::now();
+    let timed = async {
+        match &mailbox.handler_timeout {
+            Some(limit) => biased(handled, limit.sleep())
+                .await
+                .map_right(|()| limit.timeout),
+            None => Either::Left(handled.await),
+        }
+    };
+    // An abort cancels the handler, and the run loop receives it next.
+    let res = biased(shared.abort.aborted(), timed).await;
+    #[cfg(feature = "metrics")]
+    shared.handler_latency.record(started.elapsed());
+    shared.set_in_handler(false);
+    let Either::Right(res) = res else {
+        mailbox.abandon();
+        return Ok(());
+    };
+    if queued {
+        mailbox.settle_barriers();
+    }
+    mailbox.check_watchers(actor);
+
+    let res = match res {
+        Either::Left(res) => res,
+        Either::Right(timeout) => {
+            mailbox.abandon();
+            return actor.on_msg_timeout(timeout).await;
+        }
+    };
+
+    let payload = match res {
+        Ok(Ok(followups)) => {
+            mailbox.push_followups(followups);
+            mailbox.complete();
+            return Ok(());
+        }
+        Ok(Err(err)) => {
+            return match (actor.on_error(&err), snapshot) {
+                (ErrorAction::Rollback, Some(snapshot)) => {
+                    // Restored before the next message is received, so nothing observes the
+                    // partial update. The message had no effect, so it is not acknowledged.
+                    *actor = snapshot;
+                    mailbox.abandon();
+                    Ok(())
+                }
+                _ => {
+                    if let Some(described) = described {
+                        shared.set_failed_message(described);
+                    }
+                    Err(err)
+                }
+            };
+        }
+        Err(payload) => payload,
+    };
+
+    match actor.on_panic(&*payload) {
+        Supervision::Stop => resume_unwind(payload),
+        Supervision::Restart => {
+            drop(payload);
+            mailbox.abandon();
+            // The message was moved into the panicked handler, so there is nothing to recover.
+            actor.on_poison(None).await?;
+            actor.on_start(&mailbox.this).await
+        }
+    }
+}
+
+/// Why a run loop stopped, see [`finish`].
+pub(crate) enum StopReason<M> {
+    /// The actor was asked to stop, with the stop message that was sent, if any.
+    Stop(Option<M>),
+    /// The actor was aborted with its [`AbortHandle`](crate::AbortHandle).
+    Aborted,
+}
+
+/// Handles the express messages the run loop did not receive anymore, takes the final snapshot,
+/// stops the actor's children, records the stop, and runs [`Actor::on_stop`], marking the actor
+/// as stopped with a failed cleanup if it returns an error, see [`RunError::Stop`](crate::RunError::Stop).
+///
+/// If the actor was aborted, possibly while it drained its mailbox, the remaining messages are
+/// dropped instead, and `on_stop` receives [`None`]. Either way, `on_stop` runs exactly once,
+/// after the run loop has stopped receiving from the mailbox.
+pub(crate) async fn finish<A: Actor>(
+    actor: &mut A,
+    mailbox: &Mailbox<A>,
+    reason: StopReason<A::Message>,
+) -> Result<(), A::Error> {
+    let shared = &mailbox.this.shared;
+    let stop = match reason {
+        StopReason::Stop(stop) => stop,
+        StopReason::Aborted => None,
+    };
+    let mut stop = mailbox.final_stop(actor, stop);
+    // Express messages that were accepted before the stop are drained like queued messages.
+    let mut failed = None;
+    while !shared.is_drain_skipped() && !shared.abort.is_aborted() {
+        let Some(msg) = mailbox.try_recv_express() else {
+            break;
+        };
+        let Err(err) = handle(actor, mailbox, msg).await else {
+            continue;
+        };
+        match actor.on_drain_error(&err) {
+            DrainErrorPolicy::Abort => return Err(err),
+            DrainErrorPolicy::Continue => {}
+            DrainErrorPolicy::ContinueThenFail => {
+                failed.get_or_insert(err);
+            }
+        }
+    }
+    if shared.abort.is_aborted() {
+        // Handlers cancelled by the abort did not get to clear this.
+        shared.set_in_handler(false);
+        shared.set_aborted();
+        mailbox.close();
+        stop = None;
+    } else if let Some(persister) = &mailbox.persister {
+        persister.handled(actor, true);
+    }
+    mailbox.stop_children();
+    mailbox.record(Recorded::Stop(stop.as_ref()));
+    let res = actor.on_stop(stop).await;
+    if res.is_err() {
+        shared.set_stop_failed();
+    }
+    res?;
+    failed.map_or(Ok(()), Err)
+}
@@ -0,0 +1,35 @@
+use std::{future::Future, pin::Pin, time::Duration};
+
+type SleepFn = Box<dyn Fn(Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync>;
+
+/// The time limit for a single [`Actor::on_msg`](crate::Actor::on_msg) call, along with the
+/// caller-supplied timer used to enforce it.
+pub(crate) struct HandlerTimeout {
+    pub(crate) timeout: Duration,
+    sleep_fn: SleepFn,
+}
+
+impl HandlerTimeout {
+    pub(crate) fn new<S>(
+        timeout: Duration,
+        sleep_fn: impl Fn(Duration) -> S + Send + Sync + 'static,
+    ) -> Self
+    where
+        S: Future + Send + 'static,
+    {
+        Self {
+            timeout,
+            sleep_fn: Box::new(move |timeout| {
+                let sleep = sleep_fn(timeout);
+                Box::pin(async move {
+                    sleep.await;
+                })
+            }),
+        }
+    }
+
+    /// Returns a future that completes once the time limit has elapsed.
+    pub(crate) fn sleep(&self) -> impl Future<Output = ()> + Send {
+        (self.sleep_fn)(self.timeout)
+    }
+}
@@ -0,0 +1,71 @@
+use std::{
+    future::{poll_fn, Future},
+    pin::Pin,
+    sync::Mutex,
+    task::Poll,
+};
+
+use crate::{Actor, WeakActorRef};
+
+type ArmFn = Box<dyn FnMut() -> Pin<Box<dyn Future<Output = ()> + Send>> + Send>;
+type OnExternal<A> = Box<dyn FnMut(&mut A, &WeakActorRef<A>) + Send>;
+
+/// An external event source for the default run loop, set by
+/// [`Mailbox::set_trigger`](crate::Mailbox::set_trigger).
+pub(crate) struct Trigger<A: Actor> {
+    state: Mutex<TriggerState<A>>,
+}
+
+struct TriggerState<A: Actor> {
+    arm: ArmFn,
+    /// The trigger future currently being waited on, created by `arm` when it is first polled.
+    pending: Option<Pin<Box<dyn Future<Output = ()> + Send>>>,
+    on_external: OnExternal<A>,
+}
+
+impl<A: Actor> Trigger<A> {
+    pub(crate) fn new<T>(
+        mut trigger_fn: impl FnMut() -> T + Send + 'static,
+        on_external: impl FnMut(&mut A, &WeakActorRef<A>) + Send + 'static,
+    ) -> Self
+    where
+        T: Future + Send + 'static,
+    {
+        let arm: ArmFn = Box::new(move || {
+            let trigger = trigger_fn();
+            Box::pin(async move {
+                trigger.await;
+            })
+        });
+        Self {
+            state: Mutex::new(TriggerState {
+                arm,
+                pending: None,
+                on_external: Box::new(on_external),
+            }),
+        }
+    }
+
+    /// Waits until the current trigger future completes. The future is kept across calls, so
+    /// waiting can be interrupted by other events without restarting it.
+    pub(crate) async fn fired(&self) {
+        poll_fn(|cx| {
+            let mut state = self.state.lock().unwrap();
+            let state = &mut *state;
+            let pending = state.pending.get_or_insert_with(&mut state.arm);
+            match pending.as_mut().poll(cx) {
+                Poll::Ready(()) => {
+                    state.pending = None;
+                    Poll::Ready(())
+                }
+                Poll::Pending => Poll::Pending,
+            }
+        })
+        .await
+    }
+
+    /// Runs the callback for a fired trigger.
+    pub(crate) fn handle(&self, actor: &mut A, this: &WeakActorRef<A>) {
+        (self.state.lock().unwrap().on_external)(actor, this);
+    }
+}